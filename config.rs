@@ -16,6 +16,8 @@ pub struct AppConfigInfo {
     pub version: semver::Version,
     pub persistent: bool,
     pub domain: Option<&'static str>,
+    /// how long the previous versions of the rows are kept for the time-travel reads
+    pub history_retention: std::time::Duration,
     pub manifest_dir: &'static str,
     #[cfg(host)]
     pub data_dir: std::path::PathBuf,
@@ -35,6 +37,7 @@ impl AppConfig {
         version: &str,
         persistent: bool,
         domain: Option<&'static str>,
+        history_retention_secs: u64,
     ) {
         let version = version.parse::<semver::Version>().unwrap();
 
@@ -52,6 +55,7 @@ impl AppConfig {
                 version,
                 persistent,
                 domain,
+                history_retention: std::time::Duration::from_secs(history_retention_secs),
                 manifest_dir,
                 #[cfg(host)]
                 data_dir,
@@ -0,0 +1,103 @@
+use crate::*;
+
+/// Rows of the projected aggregates over the `T` rows matching the filter,
+/// prefixed with the values of the column and ordered by them if grouped by one
+#[doc(hidden)]
+pub async fn _aggregate<T: Table>(
+    projection: &str,
+    group_by: Option<&str>,
+    filter: Option<Filter<T>>,
+) -> Result<Vec<Vec<sql::Value>>> {
+    let filter = match filter {
+        Some(filter) => filter.into_expr()?,
+        None => sql::expr("TRUE"),
+    };
+    let select = T::select().filter(filter);
+    match group_by {
+        Some(column) => {
+            let column = format!("\"{column}\"");
+            select
+                .group_by(column.clone())
+                .project(format!("{column}, {projection}").as_str())
+                .order_by(format!("{column} ASC").as_str())
+                .rows()
+                .await
+        }
+        None => select.project(projection).rows().await,
+    }
+}
+
+/// Single aggregated value, `None` if there were no rows or only NULLs
+#[doc(hidden)]
+pub fn _aggregated<V, E>(rows: Vec<Vec<sql::Value>>) -> Result<Option<V>>
+where
+    V: for<'a> TryFrom<&'a sql::Value, Error = E>,
+    E: std::fmt::Debug,
+{
+    match rows.first().and_then(|row| row.first()) {
+        None | Some(sql::Value::Null) => Ok(None),
+        Some(value) => match V::try_from(value) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => Err(e!("unexpected aggregated value {value:?}: {err:?}")),
+        },
+    }
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_aggregated_orders")]
+    struct Order {
+        id: u32,
+        customer: String,
+        amount: u64,
+        discount: Option<f64>,
+        placed: NaiveDate,
+    }
+
+    #[test]
+    fn aggregates_are_computed_by_the_db() {
+        test_db(&[Order::schema()], async {
+            assert_eq!(Order::sum_amount().await?, 0);
+            assert_eq!(Order::avg_amount().await?, None);
+            assert_eq!(Order::max_placed().await?, None);
+
+            let day = |day| NaiveDate::from_ymd_opt(2024, 5, day).unwrap();
+            let orders = [
+                (1, "ann", 10, Some(0.5), day(3)),
+                (2, "bob", 20, None, day(1)),
+                (3, "ann", 40, Some(1.5), day(2)),
+            ];
+            let orders = orders.map(|(id, customer, amount, discount, placed)| Order {
+                id,
+                customer: customer.to_owned(),
+                amount,
+                discount,
+                placed,
+            });
+            Order::insert_many(&orders).await?;
+            let cols = Order::cols;
+
+            assert_eq!(Order::count_where(cols.customer.eq("ann")).await?, 2);
+            assert_eq!(Order::sum_amount().await?, 70);
+            assert_eq!(Order::sum_amount_where(cols.amount.gt(10u64)).await?, 60);
+            assert_eq!(
+                Order::avg_amount_where(cols.customer.eq("ann")).await?,
+                Some(25.0)
+            );
+            // NULLs are skipped
+            assert_eq!(Order::avg_discount().await?, Some(1.0));
+            assert_eq!(Order::min_discount().await?, Some(0.5));
+            assert_eq!(Order::min_placed().await?, Some(day(1)));
+            assert_eq!(
+                Order::max_placed_where(cols.customer.eq("bob")).await?,
+                Some(day(1))
+            );
+            let groups = Order::group_by_customer_count().await?;
+            assert_eq!(groups, vec![("ann".to_owned(), 2), ("bob".to_owned(), 1)]);
+            OK
+        })
+    }
+}
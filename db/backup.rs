@@ -0,0 +1,102 @@
+use crate::*;
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+const BACKUPS_DIRECTORY_NAME: &str = "backups";
+const BACKUP_EXTENSION: &str = "prestdb";
+
+impl Db {
+    /// Writes a consistent copy of the persistent storage into the file, transactions wait until it's written
+    pub async fn backup_to(&self, path: impl AsRef<Path>) -> Result {
+        let DbStorage::Persistent(storage) = self.storage() else {
+            return Err(e!("backups require the persistent storage"));
+        };
+        // written next to the target first so that an interrupted backup doesn't replace the previous one
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        let mut file = BufWriter::new(File::create(&partial)?);
+        storage.backup(&mut file).await?;
+        drop(file);
+        std::fs::rename(partial, path)?;
+        OK
+    }
+
+    /// Replaces all the data of the persistent storage with the backup written by [`Db::backup_to`].
+    /// The current data is backed up into the data directory first
+    pub async fn restore_from(&self, path: impl AsRef<Path>) -> Result {
+        let DbStorage::Persistent(storage) = self.storage() else {
+            return Err(e!("backups require the persistent storage"));
+        };
+        let path = path.as_ref();
+        storage.check_backup(path)?;
+        let previous = self.backup().await?;
+        info!(target: "db", "backed up the DB into {} before restoring", previous.display());
+        storage.restore(path).await?;
+        info!(target: "db", "restored DB from the backup");
+        OK
+    }
+
+    /// Writes a new backup into the data directory and returns its path
+    pub async fn backup(&self) -> Result<PathBuf> {
+        let path = backups_dir()?.join(backup_name());
+        self.backup_to(&path).await?;
+        Ok(path)
+    }
+
+    /// Backs up the DB into the data directory on schedule like `RT.every(1).day().at(3, 0, 0)` keeping the latest `keep` backups
+    pub fn schedule_backups(&self, every: impl RepeatableJob + Send + 'static, keep: usize) {
+        every.schedule("DB backup", move || async move {
+            DB.backup().await?;
+            for outdated in DB.backups()?.into_iter().skip(keep) {
+                std::fs::remove_file(outdated)?;
+            }
+            OK
+        });
+    }
+
+    /// Backups in the data directory from the latest to the oldest
+    pub fn backups(&self) -> Result<Vec<PathBuf>> {
+        let mut backups = vec![];
+        for entry in std::fs::read_dir(backups_dir()?)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == BACKUP_EXTENSION) {
+                backups.push(path);
+            }
+        }
+        // names start with the timestamps
+        backups.sort();
+        backups.reverse();
+        Ok(backups)
+    }
+}
+
+/// Readable time of the backup followed by the time-ordered id which keeps the backups of the same second apart
+fn backup_name() -> String {
+    let time = Utc::now().format("%Y%m%d-%H%M%S");
+    format!("{time}-{}.{BACKUP_EXTENSION}", Uuid::now_v7().simple())
+}
+
+pub(crate) fn backups_dir() -> Result<PathBuf> {
+    let mut path = APP_CONFIG.data_dir.clone();
+    path.push(BACKUPS_DIRECTORY_NAME);
+    std::fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backups_of_the_same_second_are_kept_apart_in_order() {
+        let names: Vec<String> = (0..100).map(|_| backup_name()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(names, sorted);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::*;
+
+use async_broadcast::{broadcast, InactiveReceiver, Receiver, Sender};
+use gluesql::core::{data::Key, store::DataRow};
+use std::{any::Any, collections::HashMap, sync::Mutex, sync::RwLock};
+
+/// Mutation of a [`Table`] row published to the [`Table::subscribe`]rs once it's committed
+#[derive(Debug, Clone)]
+pub enum Change<T> {
+    Inserted(T),
+    Updated(T),
+    Deleted(T),
+}
+
+impl<T> Change<T> {
+    /// Inserted or updated row, or the deleted one
+    pub fn item(&self) -> &T {
+        match self {
+            Change::Inserted(item) | Change::Updated(item) | Change::Deleted(item) => item,
+        }
+    }
+
+    pub fn into_item(self) -> T {
+        match self {
+            Change::Inserted(item) | Change::Updated(item) | Change::Deleted(item) => item,
+        }
+    }
+}
+
+/// Stream of the [`Change`]s returned by [`Table::subscribe`]
+pub type Changes<T> = Receiver<Change<T>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ChangeKind {
+    Inserted,
+    Updated,
+    Deleted,
+}
+
+trait ChangeFeed: Send + Sync {
+    fn has_subscribers(&self) -> bool;
+    fn publish(&self, kind: ChangeKind, row: Vec<sql::Value>);
+    fn as_any(&self) -> &dyn Any;
+}
+
+struct TableFeed<T> {
+    sender: Sender<Change<T>>,
+    // keeps the channel open while there are no subscribers
+    _inactive: InactiveReceiver<Change<T>>,
+}
+
+impl<T: Table + Clone + Sync + 'static> ChangeFeed for TableFeed<T> {
+    fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    fn publish(&self, kind: ChangeKind, row: Vec<sql::Value>) {
+        let item = match T::from_row(row) {
+            Ok(item) => item,
+            Err(e) => {
+                warn!(target: "db", "failed to read changed {} row: {e}", T::TABLE_NAME);
+                return;
+            }
+        };
+        let change = match kind {
+            ChangeKind::Inserted => Change::Inserted(item),
+            ChangeKind::Updated => Change::Updated(item),
+            ChangeKind::Deleted => Change::Deleted(item),
+        };
+        // overflowing channel drops the oldest changes for the lagging subscribers
+        let _ = self.sender.try_broadcast(change);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+struct PendingChange {
+    table: String,
+    /// sortable bytes of the key because scans return them instead of the typed keys
+    key: Option<Vec<u8>>,
+    kind: ChangeKind,
+    row: Vec<sql::Value>,
+}
+
+static FEEDS: Lazy<RwLock<HashMap<&'static str, Arc<dyn ChangeFeed>>>> =
+    Lazy::new(Default::default);
+
+/// Changes captured by the transaction scopes which aren't committed yet
+static PENDING: Lazy<Mutex<HashMap<u64, Vec<PendingChange>>>> = Lazy::new(Default::default);
+
+pub(crate) fn subscribe<T: Table + Clone + Sync + 'static>() -> Changes<T> {
+    let mut feeds = FEEDS.write().expect("change feeds");
+    if let Some(feed) = feeds.get(T::TABLE_NAME) {
+        if let Some(feed) = feed.as_any().downcast_ref::<TableFeed<T>>() {
+            return feed.sender.new_receiver();
+        }
+    }
+    let (mut sender, receiver) = broadcast(1000);
+    sender.set_overflow(true);
+    let feed = TableFeed {
+        sender,
+        _inactive: receiver.clone().deactivate(),
+    };
+    feeds.insert(T::TABLE_NAME, Arc::new(feed));
+    receiver
+}
+
+/// Checks whether changes of the table should be captured
+pub(crate) fn watched(table: &str) -> bool {
+    let feeds = FEEDS.read().expect("change feeds");
+    feeds.get(table).is_some_and(|feed| feed.has_subscribers())
+}
+
+/// Records the change to publish it when the current transaction scope is committed
+pub(crate) fn capture(table: &str, key: Option<&Key>, kind: ChangeKind, row: &DataRow) {
+    let DataRow::Vec(row) = row else {
+        return;
+    };
+    let key = key.and_then(|key| key.to_cmp_be_bytes().ok());
+    let mut pending = PENDING.lock().expect("pending changes");
+    let changes = pending.entry(tx_scope()).or_default();
+
+    // rows replaced with delete + insert are updated
+    let mut kind = kind;
+    if kind == ChangeKind::Inserted && key.is_some() {
+        let deleted = changes.iter().position(|change| {
+            change.kind == ChangeKind::Deleted
+                && change.table == table
+                && change.key == key
+        });
+        if let Some(position) = deleted {
+            changes.remove(position);
+            kind = ChangeKind::Updated;
+        }
+    }
+
+    changes.push(PendingChange {
+        table: table.to_owned(),
+        key,
+        kind,
+        row: row.clone(),
+    });
+}
+
+pub(crate) fn publish_pending(scope: u64) {
+    let Some(changes) = PENDING.lock().expect("pending changes").remove(&scope) else {
+        return;
+    };
+    let feeds = FEEDS.read().expect("change feeds");
+    for PendingChange {
+        table, kind, row, ..
+    } in changes
+    {
+        if let Some(feed) = feeds.get(table.as_str()) {
+            feed.publish(kind, row);
+        }
+    }
+}
+
+pub(crate) fn discard_pending(scope: u64) {
+    PENDING.lock().expect("pending changes").remove(&scope);
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_change_items")]
+    struct ChangeItem {
+        id: u32,
+        name: String,
+    }
+
+    #[test]
+    fn concurrent_writes_publish_their_own_changes() {
+        test_db(&[ChangeItem::schema()], async {
+            let mut changes = ChangeItem::subscribe();
+            let writes = (0..50).map(|id| {
+                tokio::spawn(async move {
+                    let item = ChangeItem {
+                        id,
+                        name: "item".to_owned(),
+                    };
+                    item.insert_self().await?;
+                    // failed duplicates roll back only their own changes
+                    assert!(item.insert_self().await.is_err());
+                    OK
+                })
+            });
+            for write in join_all(writes).await {
+                write.map_err(|e| e!("{e}"))??;
+            }
+
+            let mut inserted = vec![];
+            while let Ok(change) = changes.try_recv() {
+                match change {
+                    Change::Inserted(item) => inserted.push(item.id),
+                    other => return Err(e!("unexpected change: {other:?}")),
+                }
+            }
+            inserted.sort();
+            assert_eq!(inserted, (0..50).collect::<Vec<_>>());
+            OK
+        });
+    }
+}
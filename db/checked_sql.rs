@@ -0,0 +1,228 @@
+use crate::*;
+
+use gluesql::core::{parse_sql::parse, translate::translate};
+
+/// Decodes the values of a [`Table`]-derived column selected by [`sql!`]
+#[doc(hidden)]
+pub struct SqlColumn<V> {
+    decode: fn(sql::Value) -> Result<V>,
+}
+
+impl<V> SqlColumn<V> {
+    pub const fn _new(decode: fn(sql::Value) -> Result<V>) -> Self {
+        Self { decode }
+    }
+
+    pub fn _decode(&self, value: Option<sql::Value>) -> Result<V> {
+        (self.decode)(value.unwrap_or(sql::Value::Null))
+    }
+}
+
+/// Values which can be passed as `$1`, `$2`, ... parameters of [`sql!`] and [`query_as!`],
+/// bound as literal nodes of the parsed statement
+#[doc(hidden)]
+pub trait _SqlParam {
+    fn _sql_param(&self) -> Result<sql::Expr>;
+}
+
+macro_rules! sql_param {
+    ($($ty:ty),* => |$value:ident| $expr:expr) => {
+        $(impl _SqlParam for $ty {
+            fn _sql_param(&self) -> Result<sql::Expr> {
+                let $value = self;
+                Ok(sql::Expr::try_from($expr)?)
+            }
+        })*
+    };
+}
+
+// numbers are cast to the types of the columns they match so that key lookups find the stored values
+sql_param!(i8 => |v| sql::num(*v).cast("INT8"));
+sql_param!(i16 => |v| sql::num(*v).cast("INT16"));
+sql_param!(i32 => |v| sql::num(*v).cast("INT32"));
+sql_param!(i64 => |v| sql::num(*v).cast("INT"));
+sql_param!(i128 => |v| sql::num(v.to_string()).cast("INT128"));
+sql_param!(u8 => |v| sql::num(*v).cast("UINT8"));
+sql_param!(u16 => |v| sql::num(*v).cast("UINT16"));
+sql_param!(u32 => |v| sql::num(*v).cast("UINT32"));
+sql_param!(u64 => |v| sql::num(*v).cast("UINT64"));
+sql_param!(u128 => |v| sql::num(v.to_string()).cast("UINT128"));
+sql_param!(f32 => |v| sql::num(*v).cast("FLOAT32"));
+sql_param!(f64 => |v| sql::num(*v).cast("FLOAT"));
+sql_param!(Decimal => |v| sql::num(v.to_string()).cast("DECIMAL"));
+sql_param!(bool => |v| sql::ExprNode::Expr(std::borrow::Cow::Owned(sql::Expr::Literal(sql::AstLiteral::Boolean(*v)))));
+sql_param!(str, String => |v| sql::text(v.to_string()));
+sql_param!(Uuid => |v| sql::uuid(v.to_string()));
+sql_param!(NaiveDate => |v| sql::date(v.to_string()));
+sql_param!(NaiveTime => |v| sql::time(v.to_string()));
+sql_param!(NaiveDateTime => |v| sql::timestamp(v.to_string()));
+sql_param!(DateTime<Utc> => |v| sql::timestamp(v.naive_utc().to_string()));
+
+impl<T: _SqlParam> _SqlParam for Option<T> {
+    fn _sql_param(&self) -> Result<sql::Expr> {
+        match self {
+            Some(value) => value._sql_param(),
+            None => Ok(sql::Expr::try_from(sql::null())?),
+        }
+    }
+}
+
+impl<T: _SqlParam + ?Sized> _SqlParam for &T {
+    fn _sql_param(&self) -> Result<sql::Expr> {
+        (**self)._sql_param()
+    }
+}
+
+const TABLE_MARKER: &str = "__prest_table_";
+const PARAM_MARKER: &str = "__prest_param_";
+
+/// Parses the checked statement and replaces its `__prest_table_{i}` and `__prest_param_{i}` markers
+/// with the names of the tables and the literals of the parameters
+#[doc(hidden)]
+pub fn _sql_statement(
+    template: &str,
+    tables: &[&str],
+    params: Vec<Result<sql::Expr>>,
+) -> Result<sql::Statement> {
+    let params = params
+        .into_iter()
+        .map(|param| serde_json::to_value(param?).somehow())
+        .collect::<Result<Vec<_>>>()?;
+    let statements = parse(template)?;
+    let [statement] = statements.as_slice() else {
+        return Err(e!("expected a single statement in {template}"));
+    };
+    let statement = serde_json::to_value(translate(statement)?).somehow()?;
+    let statement = bind_markers(statement, tables, &params)?;
+    serde_json::from_value(statement).somehow()
+}
+
+/// Walks the serialized statement to replace the markers
+fn bind_markers(value: JsonValue, tables: &[&str], params: &[JsonValue]) -> Result<JsonValue> {
+    Ok(match value {
+        JsonValue::String(name) => match name.strip_prefix(TABLE_MARKER) {
+            Some(index) => {
+                let table = tables.get(index.parse::<usize>()?);
+                JsonValue::String(
+                    table
+                        .ok_or_else(|| e!("unexpected marker {name}"))?
+                        .to_string(),
+                )
+            }
+            None => JsonValue::String(name),
+        },
+        JsonValue::Object(fields) => {
+            if let Some(JsonValue::String(ident)) = fields.get("Identifier") {
+                if let Some(index) = ident.strip_prefix(PARAM_MARKER) {
+                    let param = params.get(index.parse::<usize>()?);
+                    return param
+                        .cloned()
+                        .ok_or_else(|| e!("unexpected marker {ident}"));
+                }
+            }
+            let fields = fields
+                .into_iter()
+                .map(|(key, value)| Ok((key, bind_markers(value, tables, params)?)))
+                .collect::<Result<_>>()?;
+            JsonValue::Object(fields)
+        }
+        JsonValue::Array(values) => {
+            let values = values
+                .into_iter()
+                .map(|value| bind_markers(value, tables, params))
+                .collect::<Result<_>>()?;
+            JsonValue::Array(values)
+        }
+        other => other,
+    })
+}
+
+/// Executes the statement bound by [`_sql_statement`]
+#[doc(hidden)]
+pub async fn _sql(statement: sql::Statement) -> Result<sql::Payload> {
+    super::execute(statement).await
+}
+
+#[doc(hidden)]
+pub fn _sql_rows(payload: sql::Payload) -> Result<Vec<Vec<sql::Value>>> {
+    match payload {
+        sql::Payload::Select { rows, .. } => Ok(rows),
+        other => Err(e!("expected selected rows, got {other:?}")),
+    }
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_checked_notes")]
+    struct CheckedNote {
+        id: u32,
+        body: String,
+    }
+
+    #[test]
+    fn params_are_bound_as_literals() {
+        test_db(&[CheckedNote::schema()], async {
+            let quoted = "it's'); DELETE FROM test_checked_notes; --";
+            sql!(
+                "INSERT INTO CheckedNote (id, body) VALUES ($1, $2)",
+                1u32,
+                quoted
+            )
+            .await?;
+            sql!("INSERT INTO CheckedNote VALUES ($1, $2)", 2u32, "plain").await?;
+            assert_eq!(CheckedNote::count().await?, 2);
+
+            let bodies: Vec<String> =
+                sql!("SELECT body FROM CheckedNote WHERE id = $1", 1u32).await?;
+            assert_eq!(bodies, vec![quoted]);
+            let ids: Vec<u32> = sql!("SELECT id FROM CheckedNote WHERE body = $1", quoted).await?;
+            assert_eq!(ids, vec![1]);
+
+            let injected = "' OR '1' = '1";
+            let ids: Vec<u32> =
+                sql!("SELECT id FROM CheckedNote WHERE body = $1", injected).await?;
+            assert!(ids.is_empty());
+            let injected = "x'; DELETE FROM test_checked_notes; --";
+            let notes = query_as!(
+                CheckedNote,
+                "SELECT * FROM CheckedNote WHERE body = $1",
+                injected
+            );
+            assert!(notes.await?.is_empty());
+            sql!(
+                "UPDATE CheckedNote SET body = $1 WHERE id = $2",
+                injected,
+                2u32
+            )
+            .await?;
+            let note = CheckedNote::select_by_pkey(2).await?.expect("updated note");
+            assert_eq!(note.body, injected);
+            assert_eq!(CheckedNote::count().await?, 2);
+            OK
+        })
+    }
+
+    #[test]
+    fn only_single_statements_are_run() {
+        let template = r#"SELECT * FROM "__prest_table_0" WHERE body = "__prest_param_0""#;
+        let params = vec!["a'; DELETE FROM notes; --"._sql_param()];
+        let statement = _sql_statement(template, &["notes"], params).unwrap();
+        let sql::Statement::Query(query) = statement else {
+            panic!("expected a query")
+        };
+        let sql::SetExpr::Select(select) = query.body else {
+            panic!("expected a select")
+        };
+        let Some(sql::Expr::BinaryOp { right, .. }) = select.selection else {
+            panic!("expected a comparison")
+        };
+        let literal = sql::AstLiteral::QuotedString("a'; DELETE FROM notes; --".to_owned());
+        assert_eq!(*right, sql::Expr::Literal(literal));
+
+        assert!(_sql_statement("SELECT 1; SELECT 2", &[], vec![]).is_err());
+        assert!(_sql_statement("", &[], vec![]).is_err());
+    }
+}
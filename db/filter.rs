@@ -0,0 +1,327 @@
+use crate::*;
+
+use std::marker::PhantomData;
+
+/// Typed handle of a [`Table`]-derived column which builds [`Filter`]s accepting only values of the field's type
+///
+/// Derived for every column as `Struct::cols.field`, for example `Todo::cols.done.eq(true)`
+pub struct TableColumn<T, V> {
+    name: &'static str,
+    cast: Option<&'static str>,
+    into_expr: fn(&V) -> Result<sql::ExprNode<'static>>,
+    _types: PhantomData<fn() -> (T, V)>,
+}
+
+impl<T, V> Clone for TableColumn<T, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, V> Copy for TableColumn<T, V> {}
+
+impl<T, V> TableColumn<T, V> {
+    #[doc(hidden)]
+    pub const fn _new(
+        name: &'static str,
+        cast: Option<&'static str>,
+        into_expr: fn(&V) -> Result<sql::ExprNode<'static>>,
+    ) -> Self {
+        Self {
+            name,
+            cast,
+            into_expr,
+            _types: PhantomData,
+        }
+    }
+
+    /// Name of the column in the table
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn col(&self) -> sql::ExprNode<'static> {
+        sql::col(self.name)
+    }
+
+    /// Values are casted to the column type because literals are parsed as I64, F64 or text
+    /// while index lookups compare values of the exact column type
+    fn value(&self, value: impl Into<V>) -> Result<sql::ExprNode<'static>> {
+        self.value_ref(&value.into())
+    }
+
+    fn value_ref(&self, value: &V) -> Result<sql::ExprNode<'static>> {
+        let value = (self.into_expr)(value)?;
+        Ok(match self.cast {
+            Some(sql_type) => value.cast(sql_type),
+            None => value,
+        })
+    }
+
+    fn compare(
+        &self,
+        value: impl Into<V>,
+        op: fn(sql::ExprNode<'static>, sql::ExprNode<'static>) -> sql::ExprNode<'static>,
+    ) -> Filter<T> {
+        Filter::new(self.value(value).map(|value| op(self.col(), value)))
+    }
+
+    pub fn eq(&self, value: impl Into<V>) -> Filter<T> {
+        self.compare(value, sql::ExprNode::eq)
+    }
+
+    /// Same as `eq` for the values which aren't `Clone`
+    #[doc(hidden)]
+    pub fn _eq_ref(&self, value: &V) -> Filter<T> {
+        Filter::new(self.value_ref(value).map(|value| self.col().eq(value)))
+    }
+
+    pub fn ne(&self, value: impl Into<V>) -> Filter<T> {
+        self.compare(value, sql::ExprNode::neq)
+    }
+
+    pub fn gt(&self, value: impl Into<V>) -> Filter<T> {
+        self.compare(value, sql::ExprNode::gt)
+    }
+
+    pub fn gte(&self, value: impl Into<V>) -> Filter<T> {
+        self.compare(value, sql::ExprNode::gte)
+    }
+
+    pub fn lt(&self, value: impl Into<V>) -> Filter<T> {
+        self.compare(value, sql::ExprNode::lt)
+    }
+
+    pub fn lte(&self, value: impl Into<V>) -> Filter<T> {
+        self.compare(value, sql::ExprNode::lte)
+    }
+
+    /// Inclusive range, built from `gte` and `lte` so that indexes are used for lookups
+    pub fn between(&self, min: impl Into<V>, max: impl Into<V>) -> Filter<T> {
+        self.gte(min).and(self.lte(max))
+    }
+
+    pub fn in_list<I: Into<V>>(&self, values: impl IntoIterator<Item = I>) -> Filter<T> {
+        let list = values
+            .into_iter()
+            .map(|value| self.value(value))
+            .collect::<Result<Vec<_>>>();
+        Filter::new(list.map(|list| self.col().in_list(list)))
+    }
+
+    pub fn asc(&self) -> Order<T> {
+        Order::new(self.name, false)
+    }
+
+    pub fn desc(&self) -> Order<T> {
+        Order::new(self.name, true)
+    }
+
+    pub fn is_null(&self) -> Filter<T> {
+        Filter::new(Ok(self.col().is_null()))
+    }
+
+    pub fn is_not_null(&self) -> Filter<T> {
+        Filter::new(Ok(self.col().is_not_null()))
+    }
+}
+
+impl<T: Table, V> TableColumn<T, V> {
+    /// Cursor over the rows following the last one in the ascending order, starts from the first row if `None`
+    ///
+    /// Rows with equal values of the column are ordered by their primary keys so none of them are skipped,
+    /// while `NULL` values aren't ordered and rows holding them are left out of the cursors
+    pub fn after(&self, last: Option<&T>) -> Cursor<T> {
+        self.cursor(last, false)
+    }
+
+    /// Cursor over the rows preceding the last one in the descending order, starts from the last row if `None`
+    pub fn before(&self, last: Option<&T>) -> Cursor<T> {
+        self.cursor(last, true)
+    }
+
+    fn cursor(&self, last: Option<&T>, desc: bool) -> Cursor<T> {
+        // primary key columns order the rows with equal values
+        let keys = T::COLUMN_SCHEMAS
+            .iter()
+            .filter(|col| col.pkey && col.name != self.name);
+        let columns: Vec<&ColumnSchema> = T::COLUMN_SCHEMAS
+            .iter()
+            .filter(|col| col.name == self.name)
+            .chain(keys)
+            .collect();
+        let order = columns
+            .iter()
+            .map(|col| Order::new(col.name, desc))
+            .reduce(Order::then)
+            .expect("cursor column should be in the schema");
+        Cursor {
+            filter: last.map(|last| Filter::new(following(last, &columns, desc))),
+            order,
+        }
+    }
+}
+
+/// `col > v OR (col = v AND pkey > k)` for the values of the last row, reversed for the descending order.
+/// The leading `col >= v` lets the planner use the column's index
+fn following<T: Table>(
+    last: &T,
+    columns: &[&ColumnSchema],
+    desc: bool,
+) -> Result<sql::ExprNode<'static>> {
+    let sql::ExprList::Exprs(values) = last.into_row()? else {
+        let table = T::TABLE_NAME;
+        return Err(e!("row of {table} isn't a list of values"));
+    };
+    let mut compared: Vec<(sql::ExprNode<'static>, sql::ExprNode<'static>)> = columns
+        .iter()
+        .map(|col| {
+            let position = T::COLUMN_SCHEMAS.iter().position(|c| c.name == col.name);
+            let value = values[position.expect("cursor column")].clone();
+            (sql::col(col.name), value.cast(col.sql_type))
+        })
+        .collect();
+    let (past, up_to): (fn(_, _) -> _, fn(_, _) -> _) = match desc {
+        false => (sql::ExprNode::gt, sql::ExprNode::gte),
+        true => (sql::ExprNode::lt, sql::ExprNode::lte),
+    };
+    let (col, value) = compared.pop().expect("cursor columns");
+    let mut expr = past(col, value);
+    if compared.is_empty() {
+        return Ok(expr);
+    }
+    let (leading, leading_value) = compared[0].clone();
+    while let Some((col, value)) = compared.pop() {
+        expr = past(col.clone(), value.clone()).or(col.eq(value).and(expr));
+    }
+    Ok(up_to(leading, leading_value).and(expr))
+}
+
+/// Condition over the rows of a [`Table`] built from its [`TableColumn`]s
+pub struct Filter<T> {
+    expr: Result<sql::ExprNode<'static>>,
+    _table: PhantomData<fn() -> T>,
+}
+
+impl<T> Filter<T> {
+    fn new(expr: Result<sql::ExprNode<'static>>) -> Self {
+        Self {
+            expr,
+            _table: PhantomData,
+        }
+    }
+
+    pub fn and(self, other: Filter<T>) -> Self {
+        Self::new(self.expr.and_then(|expr| Ok(expr.and(other.expr?))))
+    }
+
+    pub fn or(self, other: Filter<T>) -> Self {
+        Self::new(self.expr.and_then(|expr| Ok(expr.or(other.expr?))))
+    }
+
+    /// Returns the expression to use with the sql builders like `Todo::select().filter(...)`
+    pub fn into_expr(self) -> Result<sql::ExprNode<'static>> {
+        self.expr
+    }
+}
+
+impl<T> std::ops::Not for Filter<T> {
+    type Output = Self;
+    fn not(self) -> Self {
+        Self::new(self.expr.map(sql::not))
+    }
+}
+
+/// Ordering of the rows of a [`Table`] built from its [`TableColumn`]s like `Todo::cols.task.asc()`
+pub struct Order<T> {
+    exprs: Vec<String>,
+    _table: PhantomData<fn() -> T>,
+}
+
+impl<T> Order<T> {
+    pub(crate) fn new(column: &str, desc: bool) -> Self {
+        let direction = if desc { "DESC" } else { "ASC" };
+        Self {
+            exprs: vec![format!("\"{column}\" {direction}")],
+            _table: PhantomData,
+        }
+    }
+
+    /// Orders the rows with equal values of the previous columns
+    pub fn then(mut self, other: Order<T>) -> Self {
+        self.exprs.extend(other.exprs);
+        self
+    }
+
+    /// Returns the ordering to use with the sql builders like `Todo::select().order_by(...)`
+    pub fn into_exprs(self) -> String {
+        self.exprs.join(", ")
+    }
+}
+
+/// Position in the ordered rows of a [`Table`] to continue keyset pagination from, built like `Todo::cols.id.after(last)`
+pub struct Cursor<T> {
+    pub(crate) filter: Option<Filter<T>>,
+    pub(crate) order: Order<T>,
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_filtered_items")]
+    struct FilteredItem {
+        id: u32,
+        #[index_column]
+        score: i16,
+        name: String,
+        note: Option<String>,
+    }
+
+    async fn ids(filter: Filter<FilteredItem>) -> Result<Vec<u32>> {
+        let mut ids: Vec<u32> = FilteredItem::select_where(filter)
+            .await?
+            .into_iter()
+            .map(|item| item.id)
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    #[test]
+    fn filters_select_matching_rows() {
+        test_db(&[FilteredItem::schema()], async {
+            let items = (1..=5).map(|id| FilteredItem {
+                id,
+                score: id as i16 * 10,
+                name: format!("item {id}"),
+                note: (id % 2 == 0).then(|| "even".to_owned()),
+            });
+            FilteredItem::insert_many(&items.collect::<Vec<_>>()).await?;
+            let cols = FilteredItem::cols;
+
+            // indexed lookups compare values of the column type
+            assert_eq!(ids(cols.score.eq(30i16)).await?, vec![3]);
+            assert_eq!(ids(cols.score.between(20i16, 40i16)).await?, vec![2, 3, 4]);
+            assert_eq!(
+                ids(cols.score.gt(40i16).or(cols.score.lt(20i16))).await?,
+                vec![1, 5]
+            );
+            assert_eq!(ids(cols.id.in_list([1u32, 4, 9])).await?, vec![1, 4]);
+            assert_eq!(
+                ids(cols.name.ne("item 1").and(cols.score.lte(20i16))).await?,
+                vec![2]
+            );
+            assert_eq!(ids(cols.note.is_null()).await?, vec![1, 3, 5]);
+            assert_eq!(
+                ids(!cols.note.is_null()).await?,
+                ids(cols.note.is_not_null()).await?
+            );
+
+            let order = cols.note.desc().then(cols.id.asc()).into_exprs();
+            assert_eq!(order, r#""note" DESC, "id" ASC"#);
+            OK
+        })
+    }
+}
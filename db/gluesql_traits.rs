@@ -1,17 +1,23 @@
 use crate::*;
 
-use super::DbStorage::*;
+use super::{
+    changes::{self, ChangeKind},
+    DbStorage::*,
+};
 
 use gluesql::core::{
     ast::{ColumnDef, IndexOperator, OrderByExpr},
-    data::{CustomFunction as StructCustomFunction, Key, Schema},
+    data::{CustomFunction as StructCustomFunction, Key, Schema, Value},
     error::Error as GlueError,
+    executor::ValidateError,
     store::{
         AlterTable, CustomFunction, CustomFunctionMut, DataRow, Index, IndexMut, Metadata, RowIter,
         Store, StoreMut, Transaction,
     },
 };
 
+use std::collections::{HashMap, HashSet};
+
 type GResult<T> = core::result::Result<T, GlueError>;
 
 #[async_trait(?Send)]
@@ -62,6 +68,19 @@ impl StoreMut for DbStorage {
     }
 
     async fn append_data(&mut self, table_name: &str, rows: Vec<DataRow>) -> GResult<()> {
+        if let Some(positions) = self.composite_pkey(table_name).await? {
+            let rows = rows
+                .into_iter()
+                .map(|row| Ok((composite_key(table_name, &positions, &row)?, row)))
+                .collect::<GResult<Vec<_>>>()?;
+            self.check_new_keys(table_name, &rows, &[]).await?;
+            return self.store_rows(table_name, rows).await;
+        }
+        if changes::watched(table_name) {
+            for row in &rows {
+                changes::capture(table_name, None, ChangeKind::Inserted, row);
+            }
+        }
         match self {
             Memory(s) => s.append_data(table_name, rows).await,
             Persistent(s) => s.append_data(table_name, rows).await,
@@ -69,13 +88,21 @@ impl StoreMut for DbStorage {
     }
 
     async fn insert_data(&mut self, table_name: &str, rows: Vec<(Key, DataRow)>) -> GResult<()> {
-        match self {
-            Memory(s) => s.insert_data(table_name, rows).await,
-            Persistent(s) => s.insert_data(table_name, rows).await,
-        }
+        let rows = match self.composite_pkey(table_name).await? {
+            Some(positions) => self.move_changed_keys(table_name, &positions, rows).await?,
+            None => rows,
+        };
+        self.store_rows(table_name, rows).await
     }
 
     async fn delete_data(&mut self, table_name: &str, keys: Vec<Key>) -> GResult<()> {
+        if changes::watched(table_name) {
+            for key in &keys {
+                if let Some(row) = self.fetch_data(table_name, key).await? {
+                    changes::capture(table_name, Some(key), ChangeKind::Deleted, &row);
+                }
+            }
+        }
         match self {
             Memory(s) => s.delete_data(table_name, keys).await,
             Persistent(s) => s.delete_data(table_name, keys).await,
@@ -83,6 +110,129 @@ impl StoreMut for DbStorage {
     }
 }
 
+/// GlueSQL supports only single-column primary keys so the rows of the tables with composite ones are stored
+/// under the keys made of their key columns, which lets the storage find the duplicates without scanning the table
+impl DbStorage {
+    /// Positions of the key columns if the table has a composite primary key
+    async fn composite_pkey(&self, table_name: &str) -> GResult<Option<Vec<usize>>> {
+        let Some(table) = DB.all_tables().into_iter().find(|t| t.name() == table_name) else {
+            return Ok(None);
+        };
+        let pkeys: Vec<&str> = table
+            .columns()
+            .iter()
+            .filter(|c| c.pkey)
+            .map(|c| c.name)
+            .collect();
+        if pkeys.len() < 2 {
+            return Ok(None);
+        }
+        let Some(column_defs) = self
+            .fetch_schema(table_name)
+            .await?
+            .and_then(|s| s.column_defs)
+        else {
+            return Ok(None);
+        };
+        let positions = pkeys
+            .iter()
+            .filter_map(|name| column_defs.iter().position(|c| c.name == *name))
+            .collect();
+        Ok(Some(positions))
+    }
+
+    /// Fails if any of the keys of the rows repeats or is stored already unless it's one of the `replaced` ones
+    async fn check_new_keys(
+        &self,
+        table_name: &str,
+        rows: &[(Key, DataRow)],
+        replaced: &[Key],
+    ) -> GResult<()> {
+        let replaced: HashSet<&Key> = replaced.iter().collect();
+        let mut keys = HashSet::new();
+        for (key, _) in rows {
+            if !keys.insert(key) {
+                return Err(duplicate_key(table_name, key));
+            }
+            if !replaced.contains(key) && self.fetch_data(table_name, key).await?.is_some() {
+                return Err(duplicate_key(table_name, key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rows updated with other values of the key columns are moved to their new keys
+    async fn move_changed_keys(
+        &mut self,
+        table_name: &str,
+        positions: &[usize],
+        rows: Vec<(Key, DataRow)>,
+    ) -> GResult<Vec<(Key, DataRow)>> {
+        let mut replaced = vec![];
+        let mut updated = vec![];
+        for (key, row) in rows {
+            updated.push((composite_key(table_name, positions, &row)?, row));
+            replaced.push(key);
+        }
+        if updated
+            .iter()
+            .zip(&replaced)
+            .all(|((new, _), old)| new == old)
+        {
+            return Ok(updated);
+        }
+        self.check_new_keys(table_name, &updated, &replaced).await?;
+        let new_keys: HashSet<&Key> = updated.iter().map(|(key, _)| key).collect();
+        let moved = replaced
+            .into_iter()
+            .filter(|key| !new_keys.contains(key))
+            .collect();
+        self.delete_data(table_name, moved).await?;
+        Ok(updated)
+    }
+
+    /// Writes the rows under their keys capturing the changes of the watched tables
+    async fn store_rows(&mut self, table_name: &str, rows: Vec<(Key, DataRow)>) -> GResult<()> {
+        if changes::watched(table_name) {
+            for (key, row) in &rows {
+                let kind = match self.fetch_data(table_name, key).await? {
+                    Some(_) => ChangeKind::Updated,
+                    None => ChangeKind::Inserted,
+                };
+                changes::capture(table_name, Some(key), kind, row);
+            }
+        }
+        match self {
+            Memory(s) => s.insert_data(table_name, rows).await,
+            Persistent(s) => s.insert_data(table_name, rows).await,
+        }
+    }
+}
+
+fn composite_key(table_name: &str, positions: &[usize], row: &DataRow) -> GResult<Key> {
+    let DataRow::Vec(values) = row else {
+        return Err(GlueError::StorageMsg(format!(
+            "{table_name} row isn't a list of values"
+        )));
+    };
+    let keys = positions
+        .iter()
+        .map(|i| Key::try_from(values.get(*i).cloned().unwrap_or(Value::Null)))
+        .collect::<GResult<Vec<Key>>>()?;
+    let key = bincode::serialize(&keys).map_err(|e| GlueError::StorageMsg(e.to_string()))?;
+    Ok(Key::Bytea(key))
+}
+
+fn duplicate_key(table_name: &str, key: &Key) -> GlueError {
+    let Key::Bytea(key) = key else {
+        return GlueError::StorageMsg(format!("{table_name} row has an invalid key"));
+    };
+    let first = bincode::deserialize::<Vec<Key>>(key)
+        .ok()
+        .and_then(|mut keys| (!keys.is_empty()).then(|| keys.swap_remove(0)));
+    ValidateError::DuplicateEntryOnPrimaryKeyField(first.unwrap_or(Key::None)).into()
+}
+
 #[async_trait(?Send)]
 impl AlterTable for DbStorage {
     async fn rename_schema(&mut self, _table_name: &str, _new_table_name: &str) -> GResult<()> {
@@ -140,6 +290,7 @@ impl Transaction for DbStorage {
     }
 
     async fn rollback(&mut self) -> GResult<()> {
+        changes::discard_pending(tx_scope());
         match self {
             Memory(s) => s.rollback().await,
             Persistent(s) => s.rollback().await,
@@ -148,9 +299,11 @@ impl Transaction for DbStorage {
 
     async fn commit(&mut self) -> GResult<()> {
         match self {
-            Memory(s) => s.commit().await,
-            Persistent(s) => s.commit().await,
+            Memory(s) => s.commit().await?,
+            Persistent(s) => s.commit().await?,
         }
+        changes::publish_pending(tx_scope());
+        Ok(())
     }
 }
 
@@ -233,7 +386,6 @@ impl IndexMut for DbStorage {
 }
 
 type ObjectName = String;
-use std::collections::HashMap;
 type _MetaIter = Box<dyn Iterator<Item = GResult<(ObjectName, HashMap<String, sql::Value>)>>>;
 
 #[async_trait(?Send)]
@@ -0,0 +1,117 @@
+use crate::*;
+
+/// Point in the history of the [`DB`] for the time-travel reads like [`Table::select_as_of`]
+#[derive(Debug, Clone, Copy)]
+pub enum AsOf {
+    Time(NaiveDateTime),
+    /// right after the transaction with this id, like the ones in [`RowVersion`]s
+    Txid(u64),
+}
+
+impl From<NaiveDateTime> for AsOf {
+    fn from(time: NaiveDateTime) -> Self {
+        AsOf::Time(time)
+    }
+}
+
+impl From<u64> for AsOf {
+    fn from(txid: u64) -> Self {
+        AsOf::Txid(txid)
+    }
+}
+
+/// Version of a [`Table`] row returned by [`Table::history`]
+#[derive(Debug, Clone)]
+pub struct RowVersion<T> {
+    /// transaction which wrote this version
+    pub txid: u64,
+    /// start of the transaction which wrote this version, `None` if it's older than the retention window
+    pub written_at: Option<NaiveDateTime>,
+    /// transaction which updated or deleted this version, `None` for the current one
+    pub replaced_by: Option<u64>,
+    pub item: T,
+}
+
+impl<T> RowVersion<T> {
+    pub fn try_map<U>(self, f: impl FnOnce(T) -> Result<U>) -> Result<RowVersion<U>> {
+        Ok(RowVersion {
+            txid: self.txid,
+            written_at: self.written_at,
+            replaced_by: self.replaced_by,
+            item: f(self.item)?,
+        })
+    }
+}
+
+impl Db {
+    pub(crate) async fn select_as_of<T: Table>(&self, at: AsOf) -> Result<Vec<T>> {
+        #[cfg(host)]
+        if let DbStorage::Persistent(storage) = self.storage() {
+            let txid = match at {
+                AsOf::Txid(txid) => txid,
+                AsOf::Time(time) => {
+                    let timestamp = time.and_utc().timestamp_millis().max(0) as u128;
+                    storage.txid_at(timestamp).await?
+                }
+            };
+            let rows = storage.scan_as_of(T::TABLE_NAME, txid).await?;
+            return rows
+                .into_iter()
+                .filter_map(values)
+                .map(T::from_row)
+                .collect();
+        }
+        let _ = at;
+        Err(e!("time-travel reads require the persistent storage"))
+    }
+
+    pub(crate) async fn history<T: Table>(&self, item: &T) -> Result<Vec<RowVersion<T>>> {
+        #[cfg(host)]
+        if let DbStorage::Persistent(storage) = self.storage() {
+            let pkey = item.get_pkey();
+            let mut versions = vec![];
+            for row_versions in storage.scan_history(T::TABLE_NAME).await? {
+                let row_versions = row_versions
+                    .into_iter()
+                    .filter_map(|(txid, replaced_by, row)| {
+                        let item = values(row).map(T::from_row)?;
+                        Some(item.map(|item| (txid, replaced_by, item)))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                // deleted and inserted again rows without a single primary key column are stored separately
+                if row_versions
+                    .iter()
+                    .any(|(.., item)| item.get_pkey() == pkey)
+                {
+                    versions.extend(row_versions);
+                }
+            }
+            versions.sort_by_key(|(txid, ..)| *txid);
+
+            let mut history = vec![];
+            for (txid, replaced_by, item) in versions {
+                let written_at = storage.tx_timestamp(txid).await?.and_then(|timestamp| {
+                    chrono::DateTime::from_timestamp_millis(timestamp as i64)
+                        .map(|time| time.naive_utc())
+                });
+                history.push(RowVersion {
+                    txid,
+                    written_at,
+                    replaced_by,
+                    item,
+                });
+            }
+            return Ok(history);
+        }
+        let _ = item;
+        Err(e!("row history requires the persistent storage"))
+    }
+}
+
+#[cfg(host)]
+pub(super) fn values(row: gluesql::core::store::DataRow) -> Option<Vec<sql::Value>> {
+    match row {
+        gluesql::core::store::DataRow::Vec(values) => Some(values),
+        gluesql::core::store::DataRow::Map(_) => None,
+    }
+}
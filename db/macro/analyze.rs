@@ -1,5 +1,55 @@
 use super::*;
 
+pub fn table_attrs(attrs: &[Attribute]) -> syn::Result<TableAttrs> {
+    let mut table_attrs = TableAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("table")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                table_attrs.name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else if meta.path.is_ident("index") {
+                let mut fields = vec![];
+                meta.parse_nested_meta(|field| {
+                    fields.push(field.path.require_ident()?.clone());
+                    Ok(())
+                })?;
+                table_attrs.indexes.push(fields);
+                Ok(())
+            } else if meta.path.is_ident("dropped") {
+                meta.parse_nested_meta(|column| {
+                    let name = column.path.require_ident()?.to_string();
+                    table_attrs.dropped.push(name);
+                    Ok(())
+                })
+            } else if meta.path.is_ident("sync") {
+                let mut policy = SyncPolicy::ClientWins;
+                if meta.input.peek(syn::token::Paren) {
+                    meta.parse_nested_meta(|option| {
+                        if option.path.is_ident("updated_at") {
+                            let field = option.value()?.parse::<Ident>()?;
+                            policy = SyncPolicy::UpdatedAt(field.to_string());
+                            Ok(())
+                        } else if option.path.is_ident("resolve") {
+                            policy = SyncPolicy::Resolve(option.value()?.parse()?);
+                            Ok(())
+                        } else if option.path.is_ident("authorize") {
+                            table_attrs.sync_authorize = Some(option.value()?.parse()?);
+                            Ok(())
+                        } else {
+                            Err(option.error("unsupported sync option"))
+                        }
+                    })?;
+                }
+                table_attrs.sync = Some(policy);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported table attribute"))
+            }
+        })?;
+    }
+    Ok(table_attrs)
+}
+
 pub fn from_field(field: Field) -> Column {
     let pkey = field
         .attrs
@@ -11,30 +61,99 @@ pub fn from_field(field: Field) -> Column {
         .attrs
         .iter()
         .find(|a| a.path().to_token_stream().to_string() == "unique_column")
-        .is_some()
-        || pkey;
+        .is_some();
+
+    let indexed = field
+        .attrs
+        .iter()
+        .find(|a| a.path().to_token_stream().to_string() == "index_column")
+        .is_some();
+
+    let searchable = field.attrs.iter().any(|a| a.path().is_ident("searchable"));
+
+    let enumerated = field.attrs.iter().any(|a| a.path().is_ident("enum_column"));
+
+    let version = field
+        .attrs
+        .iter()
+        .any(|a| a.path().is_ident("version_column"));
+
+    let created_at = field.attrs.iter().any(|a| a.path().is_ident("created_at"));
 
+    let updated_at = field.attrs.iter().any(|a| a.path().is_ident("updated_at"));
+
+    let default = field
+        .attrs
+        .iter()
+        .find(|a| a.path().is_ident("default_column"))
+        .map(|a| {
+            a.parse_args::<syn::Expr>()
+                .expect("default_column should contain an expression")
+        });
+
+    let mut column_name = None;
+    let mut renamed_from = None;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("column")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                column_name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else if meta.path.is_ident("renamed_from") {
+                renamed_from = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported column attribute"))
+            }
+        })
+        .expect("column attribute should be valid");
+    }
+
+    let mut references = None;
+    let mut on_delete_cascade = false;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("references"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("on_delete") {
+                let policy = meta.value()?.parse::<Ident>()?;
+                on_delete_cascade = match policy.to_string().as_str() {
+                    "cascade" => true,
+                    "restrict" => false,
+                    _ => return Err(meta.error("on_delete should be either cascade or restrict")),
+                };
+            } else {
+                references = Some(meta.path);
+            }
+            Ok(())
+        })
+        .expect("references attribute should be valid");
+    }
+
+    let vis = field.vis;
     let field_name = field.ident.expect("only named structs");
     let field_name_str = field_name.to_string();
+    let column_name = column_name.unwrap_or_else(|| field_name_str.clone());
     let full_type = field.ty;
     let type_str = full_type.to_token_stream().to_string();
     let type_str = type_str.as_str();
 
-    let (inner_type_str, optional, list) =
-        if type_str.starts_with("Option < ") && type_str.ends_with(" >") {
-            let inner_type_str = type_str
-                .trim_start_matches("Option < ")
-                .trim_end_matches(" >");
-            (inner_type_str, true, false)
-        } else if type_str.starts_with("Vec < ") && type_str.ends_with(" >") {
-            let inner_type_str = type_str.trim_start_matches("Vec < ").trim_end_matches(" >");
-            (inner_type_str, false, true)
-        } else {
-            (type_str, false, false)
-        };
+    let unwrap = |wrapper: &str| {
+        type_str
+            .strip_prefix(wrapper)
+            .and_then(|t| t.strip_suffix(" >"))
+    };
+    let (inner_type_str, optional, list) = if let Some(inner_type_str) = unwrap("Option < ") {
+        (inner_type_str, true, false)
+    } else if let Some(inner_type_str) = unwrap("Vec < ").filter(|t| *t != "u8") {
+        (inner_type_str, false, true)
+    } else {
+        (type_str, false, false)
+    };
 
     if pkey && optional || pkey && list {
-        panic!("Primary Key (first attribute by default) cannot be Option<...> or Vec<...>")
+        panic!("Primary Key columns (first attribute by default) cannot be Option<...> or Vec<...>")
     }
 
     let inner_type: syn::Type = syn::parse_str(inner_type_str).unwrap();
@@ -43,6 +162,14 @@ pub fn from_field(field: Field) -> Column {
     let sql_type = match inner_type_str {
         "Uuid" => Uuid,
         "NaiveDateTime" => Timestamp,
+        // lists of these are stored as serialized text
+        "NaiveDate" if !list => Date,
+        "NaiveTime" if !list => Time,
+        "DateTime < Utc >" if !list => Timestamp,
+        "Decimal" if !list => Decimal,
+        "Vec < u8 >" => Bytea,
+        "JsonValue" | "serde_json :: Value" if !list => Map,
+        map if !list && (map.starts_with("HashMap < ") || map.starts_with("BTreeMap < ")) => Map,
         "bool" => Boolean,
         "u128" => Uint128,
         "u64" => Uint64,
@@ -59,23 +186,73 @@ pub fn from_field(field: Field) -> Column {
         _ => Text, // string, custom types and fallback for others
     };
 
-    let serialized = match inner_type_str {
-        "Uuid" | "String" | "NaiveDateTime" | "bool" | "u128" | "u64" | "u32" | "u16" | "u8"
-        | "i128" | "i64" | "i32" | "i16" | "i8" | "f64" | "f32" => false,
-        _ => true,
-    };
+    if searchable && (list || inner_type_str != "String") {
+        panic!("only String and Option<String> columns can be #[searchable]")
+    }
+
+    if enumerated && (list || !matches!(sql_type, Text)) {
+        panic!("only TableEnum and Option<TableEnum> columns can be #[enum_column]")
+    }
+
+    if version && (pkey || optional || !sql_type.integer()) {
+        panic!(
+            "#[version_column] should be a non-optional integer column outside of the primary key"
+        )
+    }
+
+    if (created_at || updated_at) && (optional || !matches!(sql_type, Timestamp)) {
+        panic!("#[created_at] and #[updated_at] should be NaiveDateTime or DateTime<Utc> columns")
+    }
+
+    let serialized = matches!(sql_type, Text) && inner_type_str != "String" && !enumerated;
 
     Column {
+        vis,
         full_type_str: type_str.replace(' ', ""),
         sql_type,
         field_name,
         field_name_str,
+        column_name,
         full_type,
         inner_type,
         pkey,
         optional,
         list,
         unique,
+        indexed,
+        searchable,
+        enumerated,
+        version,
+        created_at,
+        updated_at,
+        default,
         serialized,
+        renamed_from,
+        references,
+        on_delete_cascade,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn indexes(attr: Attribute) -> syn::Result<Vec<Vec<String>>> {
+        let indexes = table_attrs(&[attr])?.indexes.into_iter();
+        Ok(indexes
+            .map(|fields| fields.iter().map(|f| f.to_string()).collect())
+            .collect())
+    }
+
+    #[test]
+    fn table_indexes_hold_fields() {
+        let indexes = indexes(syn::parse_quote!(#[table(index(a), index(b, c))])).unwrap();
+        assert_eq!(indexes, vec![vec!["a"], vec!["b", "c"]]);
+    }
+
+    #[test]
+    fn invalid_indexes_are_errors() {
+        assert!(indexes(syn::parse_quote!(#[table(index())])).is_err());
+        assert!(indexes(syn::parse_quote!(#[table(index(a::b))])).is_err());
     }
 }
@@ -0,0 +1,581 @@
+use super::*;
+use gluesql_core::{
+    ast::{self as glue, ToSql},
+    parse_sql::parse,
+    sqlparser::{
+        dialect::PostgreSqlDialect,
+        tokenizer::{Token, TokenWithLocation, Tokenizer},
+    },
+    translate::translate,
+};
+use proc_macro2::TokenStream;
+use syn::{parse::ParseStream, Token};
+
+const TABLE_MARKER: &str = "__prest_table_";
+const PARAM_MARKER: &str = "__prest_param_";
+
+pub struct SqlInput {
+    // struct of the selected rows in query_as!
+    output: Option<Type>,
+    sql: LitStr,
+    params: Vec<Expr>,
+}
+
+pub fn untyped(input: ParseStream) -> syn::Result<SqlInput> {
+    parse_input(input, None)
+}
+
+pub fn typed(input: ParseStream) -> syn::Result<SqlInput> {
+    let output = input.parse()?;
+    input.parse::<Token![,]>()?;
+    parse_input(input, Some(output))
+}
+
+fn parse_input(input: ParseStream, output: Option<Type>) -> syn::Result<SqlInput> {
+    let sql = input.parse()?;
+    let mut params = vec![];
+    while !input.is_empty() {
+        input.parse::<Token![,]>()?;
+        if input.is_empty() {
+            break;
+        }
+        params.push(input.parse()?);
+    }
+    Ok(SqlInput {
+        output,
+        sql,
+        params,
+    })
+}
+
+pub fn expand(input: SqlInput) -> proc_macro2::TokenStream {
+    let SqlInput {
+        output,
+        sql,
+        params,
+    } = input;
+
+    let text = with_param_markers(&sql.value(), params.len());
+    let statements = parse(&text).unwrap_or_else(|e| panic!("invalid SQL: {e}"));
+    let [statement] = statements.as_slice() else {
+        panic!("expected a single SQL statement")
+    };
+    let mut statement = translate(statement).unwrap_or_else(|e| panic!("unsupported SQL: {e}"));
+
+    let mut checker = Checker {
+        span: sql.span(),
+        tables: vec![],
+        checks: vec![],
+    };
+    let tables = checker.statement(&mut statement);
+    let Checker {
+        tables: types,
+        checks,
+        ..
+    } = checker;
+
+    let template = template(&statement);
+    let execute = q! {
+        let statement = prest::_sql_statement(
+            #template,
+            &[#(<#types as prest::Table>::TABLE_NAME ,)*],
+            params,
+        )?;
+        let payload = prest::_sql(statement).await?;
+    };
+
+    let projection = match &statement {
+        glue::Statement::Query(glue::Query {
+            body: glue::SetExpr::Select(select),
+            ..
+        }) => Some(&select.projection),
+        _ => None,
+    };
+    let result = match (output, projection) {
+        (Some(output), Some(projection)) => {
+            let ty = selected_table(projection, &tables);
+            q! {
+                // selected rows should be of the provided table
+                let _ = |row: #ty| -> #output { row };
+                #execute
+                <#output as prest::Table>::from_rows(prest::_sql_rows(payload)?)
+            }
+        }
+        (None, Some(projection)) => {
+            let values: Vec<TokenStream> = projection
+                .iter()
+                .map(|item| selected_value(item, &tables, checker.span))
+                .collect();
+            let row = match values.as_slice() {
+                [value] => q!(#value),
+                _ => q!((#(#values ,)*)),
+            };
+            q! {
+                #execute
+                prest::_sql_rows(payload)?
+                    .into_iter()
+                    .map(|row| -> prest::Result<_> {
+                        let mut values = row.into_iter();
+                        Ok(#row)
+                    })
+                    .collect::<prest::Result<Vec<_>>>()
+            }
+        }
+        (Some(_), None) => panic!("query_as! expects a SELECT statement"),
+        (None, None) => q! {
+            #execute
+            Ok::<_, prest::Error>(payload)
+        },
+    };
+
+    q! {{
+        #(#checks)*
+        let params: Vec<prest::Result<prest::sql::Expr>> = vec![#(prest::_SqlParam::_sql_param(&(#params)) ,)*];
+        async move {
+            #result
+        }
+    }}
+}
+
+/// SQL of the checked statement with the quoted markers of the tables and parameters
+fn template(statement: &glue::Statement) -> String {
+    match statement {
+        glue::Statement::Query(query) => query_sql(query),
+        glue::Statement::Insert {
+            table_name,
+            columns,
+            source,
+        } => {
+            let columns = match columns.is_empty() {
+                true => String::new(),
+                false => {
+                    let columns: Vec<String> =
+                        columns.iter().map(|c| format!(r#""{c}""#)).collect();
+                    format!("({}) ", columns.join(", "))
+                }
+            };
+            format!(
+                r#"INSERT INTO "{table_name}" {columns}{}"#,
+                query_sql(source)
+            )
+        }
+        _ => statement.to_sql(),
+    }
+}
+
+/// `Query::to_sql` separates several ordering expressions with spaces only
+fn query_sql(query: &glue::Query) -> String {
+    let mut sql = query.body.to_sql();
+    if !query.order_by.is_empty() {
+        let order_by: Vec<String> = query
+            .order_by
+            .iter()
+            .map(|order| match order.asc {
+                Some(true) => format!("{} ASC", order.expr.to_sql()),
+                Some(false) => format!("{} DESC", order.expr.to_sql()),
+                None => order.expr.to_sql(),
+            })
+            .collect();
+        sql += &format!(" ORDER BY {}", order_by.join(", "));
+    }
+    if let Some(limit) = &query.limit {
+        sql += &format!(" LIMIT {}", limit.to_sql());
+    }
+    if let Some(offset) = &query.offset {
+        sql += &format!(" OFFSET {}", offset.to_sql());
+    }
+    sql
+}
+
+/// Replaces `$1`, `$2`, ... placeholders with the identifiers of the parameters
+fn with_param_markers(sql: &str, count: usize) -> String {
+    let tokens = Tokenizer::new(&PostgreSqlDialect {}, sql)
+        .tokenize_with_location()
+        .unwrap_or_else(|e| panic!("invalid SQL: {e}"));
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(sql.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+
+    let mut text = sql.to_owned();
+    let mut used = vec![false; count];
+    // replaced from the end to keep the positions of the previous ones
+    for TokenWithLocation { token, location } in tokens.into_iter().rev() {
+        let Token::Placeholder(placeholder) = token else {
+            continue;
+        };
+        let index = placeholder
+            .strip_prefix('$')
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|n| (1..=count).contains(n))
+            .unwrap_or_else(|| panic!("{placeholder} doesn't match any of {count} parameters"));
+        used[index - 1] = true;
+        let line_start = line_starts[location.line as usize - 1];
+        let column = sql[line_start..]
+            .char_indices()
+            .nth(location.column as usize - 1)
+            .map(|(i, _)| i)
+            .expect("placeholder position");
+        let start = line_start + column;
+        let marker = format!("{PARAM_MARKER}{}", index - 1);
+        text.replace_range(start..start + placeholder.len(), &marker);
+    }
+    if let Some(unused) = used.iter().position(|used| !used) {
+        panic!("parameter ${} isn't used in the statement", unused + 1)
+    }
+    text
+}
+
+/// Tables of a statement by their aliases, `None` for the derived ones without checked columns
+type Tables = Vec<(String, Option<Ident>)>;
+
+struct Scope<'a> {
+    tables: Tables,
+    // aliases of the selected values which can be used for ordering
+    labels: Vec<String>,
+    parent: Option<&'a Scope<'a>>,
+}
+
+impl Scope<'_> {
+    fn table(&self, alias: &str) -> Option<&Option<Ident>> {
+        let table = self.tables.iter().find(|(name, _)| name == alias);
+        match table {
+            Some((_, ty)) => Some(ty),
+            None => self.parent.and_then(|parent| parent.table(alias)),
+        }
+    }
+}
+
+/// Replaces struct names of the tables with markers of their `TABLE_NAME`s
+/// and collects checks of the referenced columns against the derived `_SQL_COLUMNS`
+struct Checker {
+    span: Span,
+    tables: Vec<Ident>,
+    checks: Vec<TokenStream>,
+}
+
+impl Checker {
+    fn statement(&mut self, statement: &mut glue::Statement) -> Tables {
+        match statement {
+            glue::Statement::Query(query) => self.query(query, None),
+            glue::Statement::Insert {
+                table_name,
+                columns,
+                source,
+            } => {
+                let scope = self.single_table(table_name);
+                for column in columns.iter() {
+                    self.column(&scope, None, column);
+                }
+                self.query(source, None);
+                vec![]
+            }
+            glue::Statement::Update {
+                table_name,
+                assignments,
+                selection,
+            } => {
+                let scope = self.single_table(table_name);
+                for glue::Assignment { id, value } in assignments {
+                    self.column(&scope, None, id);
+                    self.expr(value, &scope);
+                }
+                if let Some(selection) = selection {
+                    self.expr(selection, &scope);
+                }
+                vec![]
+            }
+            glue::Statement::Delete {
+                table_name,
+                selection,
+            } => {
+                let scope = self.single_table(table_name);
+                if let Some(selection) = selection {
+                    self.expr(selection, &scope);
+                }
+                vec![]
+            }
+            _ => panic!("sql! supports only SELECT, INSERT, UPDATE and DELETE statements"),
+        }
+    }
+
+    fn single_table(&mut self, name: &mut String) -> Scope<'static> {
+        let ty = self.table(name);
+        Scope {
+            tables: vec![(ty.to_string(), Some(ty))],
+            labels: vec![],
+            parent: None,
+        }
+    }
+
+    fn table(&mut self, name: &mut String) -> Ident {
+        let Ok(ty) = syn::parse_str::<Ident>(name) else {
+            panic!("tables should be referenced by the names of their structs, got {name}")
+        };
+        let ty = Ident::new(&ty.to_string(), self.span);
+        let index = match self.tables.iter().position(|table| *table == ty) {
+            Some(index) => index,
+            None => {
+                self.tables.push(ty.clone());
+                self.tables.len() - 1
+            }
+        };
+        *name = format!("{TABLE_MARKER}{index}");
+        ty
+    }
+
+    fn query(&mut self, query: &mut glue::Query, parent: Option<&Scope>) -> Tables {
+        let glue::Query {
+            body,
+            order_by,
+            limit,
+            offset,
+        } = query;
+        let mut scope = Scope {
+            tables: vec![],
+            labels: vec![],
+            parent,
+        };
+        let mut labels = vec![];
+        match body {
+            glue::SetExpr::Select(select) => {
+                let glue::Select {
+                    projection,
+                    from,
+                    selection,
+                    group_by,
+                    having,
+                } = select.as_mut();
+                self.table_factor(&mut from.relation, &mut scope);
+                for join in from.joins.iter_mut() {
+                    self.table_factor(&mut join.relation, &mut scope);
+                }
+                for join in from.joins.iter_mut() {
+                    let (glue::JoinOperator::Inner(constraint)
+                    | glue::JoinOperator::LeftOuter(constraint)) = &mut join.join_operator;
+                    if let glue::JoinConstraint::On(expr) = constraint {
+                        self.expr(expr, &scope);
+                    }
+                }
+                for item in projection.iter_mut() {
+                    match item {
+                        glue::SelectItem::Expr { expr, label } => {
+                            if !matches!(expr, glue::Expr::Identifier(name) if name == label) {
+                                labels.push(label.clone());
+                            }
+                            self.expr(expr, &scope);
+                        }
+                        glue::SelectItem::QualifiedWildcard(alias) => {
+                            if scope.table(alias).is_none() {
+                                panic!("unknown table {alias} of {alias}.*")
+                            }
+                        }
+                        glue::SelectItem::Wildcard => {}
+                    }
+                }
+                let exprs = selection
+                    .iter_mut()
+                    .chain(group_by)
+                    .chain(having.iter_mut());
+                for expr in exprs {
+                    self.expr(expr, &scope);
+                }
+            }
+            glue::SetExpr::Values(glue::Values(rows)) => {
+                for expr in rows.iter_mut().flatten() {
+                    self.expr(expr, &scope);
+                }
+            }
+        }
+        for expr in limit.iter_mut().chain(offset.iter_mut()) {
+            self.expr(expr, &scope);
+        }
+        scope.labels = labels;
+        for order in order_by.iter_mut() {
+            self.expr(&mut order.expr, &scope);
+        }
+        scope.tables
+    }
+
+    fn table_factor(&mut self, factor: &mut glue::TableFactor, scope: &mut Scope) {
+        match factor {
+            glue::TableFactor::Table { name, alias, .. } => {
+                let ty = self.table(name);
+                // the struct name stays usable as the alias of the renamed table
+                let alias = alias.get_or_insert_with(|| glue::TableAlias {
+                    name: ty.to_string(),
+                    columns: vec![],
+                });
+                scope.tables.push((alias.name.clone(), Some(ty)));
+            }
+            glue::TableFactor::Derived { subquery, alias } => {
+                self.query(subquery, None);
+                scope.tables.push((alias.name.clone(), None));
+            }
+            glue::TableFactor::Series { alias, .. }
+            | glue::TableFactor::Dictionary { alias, .. } => {
+                scope.tables.push((alias.name.clone(), None));
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut glue::Expr, scope: &Scope) {
+        use glue::Expr::*;
+        match expr {
+            Identifier(column) => {
+                self.column(scope, None, column);
+            }
+            CompoundIdentifier { alias, ident } => {
+                self.column(scope, Some(alias), ident);
+            }
+            IsNull(expr) | IsNotNull(expr) | Nested(expr) | UnaryOp { expr, .. } => {
+                self.expr(expr, scope)
+            }
+            Interval { expr, .. } => self.expr(expr, scope),
+            InList { expr, list, .. } => {
+                self.expr(expr, scope);
+                for item in list {
+                    self.expr(item, scope);
+                }
+            }
+            InSubquery { expr, subquery, .. } => {
+                self.expr(expr, scope);
+                self.query(subquery, Some(scope));
+            }
+            Exists { subquery, .. } | Subquery(subquery) => {
+                self.query(subquery, Some(scope));
+            }
+            Between {
+                expr, low, high, ..
+            } => {
+                self.expr(expr, scope);
+                self.expr(low, scope);
+                self.expr(high, scope);
+            }
+            Like { expr, pattern, .. } | ILike { expr, pattern, .. } => {
+                self.expr(expr, scope);
+                self.expr(pattern, scope);
+            }
+            BinaryOp { left, right, .. } => {
+                self.expr(left, scope);
+                self.expr(right, scope);
+            }
+            Case {
+                operand,
+                when_then,
+                else_result,
+            } => {
+                for expr in operand.iter_mut().chain(else_result.iter_mut()) {
+                    self.expr(expr, scope);
+                }
+                for (when, then) in when_then {
+                    self.expr(when, scope);
+                    self.expr(then, scope);
+                }
+            }
+            ArrayIndex { obj, indexes } => {
+                self.expr(obj, scope);
+                for index in indexes {
+                    self.expr(index, scope);
+                }
+            }
+            Array { elem } => {
+                for item in elem {
+                    self.expr(item, scope);
+                }
+            }
+            Aggregate(aggregate) => match aggregate.as_mut() {
+                glue::Aggregate::Count(glue::CountArgExpr::Wildcard) => {}
+                glue::Aggregate::Count(glue::CountArgExpr::Expr(expr))
+                | glue::Aggregate::Sum(expr)
+                | glue::Aggregate::Max(expr)
+                | glue::Aggregate::Min(expr)
+                | glue::Aggregate::Avg(expr)
+                | glue::Aggregate::Variance(expr)
+                | glue::Aggregate::Stdev(expr) => self.expr(expr, scope),
+            },
+            Function(function) => {
+                for arg in function.as_exprs() {
+                    // arguments are only checked so their tables can't be renamed
+                    let mut checked = arg.clone();
+                    self.expr(&mut checked, scope);
+                    if checked != *arg {
+                        panic!("subqueries in function arguments aren't supported by sql!")
+                    }
+                }
+            }
+            Literal(_) | TypedString { .. } => {}
+        }
+    }
+
+    /// Checks that the column exists in the table of the alias or in the only table of the query
+    fn column(&mut self, scope: &Scope, alias: Option<&str>, column: &str) -> Option<Ident> {
+        let table = match alias {
+            Some(alias) => scope
+                .table(alias)
+                .unwrap_or_else(|| panic!("unknown table {alias} of {alias}.{column}")),
+            None if column.starts_with(PARAM_MARKER) => return None,
+            None if scope.labels.iter().any(|label| label == column) => return None,
+            None => match scope.tables.as_slice() {
+                [] => return None,
+                [(_, table)] => table,
+                _ => panic!("{column} should be qualified like table.{column} in the queries over several tables"),
+            },
+        };
+        let ty = table.clone()?;
+        let mut field = column_ident(column);
+        field.set_span(self.span);
+        self.checks.push(q!(let _ = &#ty::_SQL_COLUMNS.#field;));
+        Some(ty)
+    }
+}
+
+/// Table which rows are selected by `*` or `alias.*` for query_as!
+fn selected_table(projection: &[glue::SelectItem], tables: &Tables) -> Ident {
+    let table = match projection {
+        [glue::SelectItem::Wildcard] => match tables.as_slice() {
+            [(_, table)] => table,
+            _ => panic!(
+                "query_as! over several tables should select the rows of one of them like alias.*"
+            ),
+        },
+        [glue::SelectItem::QualifiedWildcard(alias)] => tables
+            .iter()
+            .find(|(name, _)| name == alias)
+            .map(|(_, table)| table)
+            .unwrap_or_else(|| panic!("unknown table {alias} of {alias}.*")),
+        _ => panic!("query_as! expects SELECT * or alias.*, use sql! to select columns"),
+    };
+    table
+        .clone()
+        .unwrap_or_else(|| panic!("query_as! expects rows of a table struct"))
+}
+
+/// Decodes selected columns into their types while other expressions are left as `sql::Value`s
+fn selected_value(item: &glue::SelectItem, tables: &Tables, span: Span) -> TokenStream {
+    let (alias, column) = match item {
+        glue::SelectItem::Expr {
+            expr: glue::Expr::Identifier(column),
+            ..
+        } => (None, column),
+        glue::SelectItem::Expr {
+            expr: glue::Expr::CompoundIdentifier { alias, ident },
+            ..
+        } => (Some(alias), ident),
+        glue::SelectItem::Expr { .. } => {
+            return q!(values.next().unwrap_or(prest::sql::Value::Null))
+        }
+        _ => panic!("sql! selects columns and expressions, use query_as! to select whole rows"),
+    };
+    let table = match alias {
+        Some(alias) => tables.iter().find(|(name, _)| name == alias),
+        None => tables.first(),
+    };
+    match table {
+        Some((_, Some(ty))) => {
+            let mut field = column_ident(column);
+            field.set_span(span);
+            q!(#ty::_SQL_COLUMNS.#field._decode(values.next())?)
+        }
+        _ => q!(values.next().unwrap_or(prest::sql::Value::Null)),
+    }
+}
@@ -1,53 +1,259 @@
-use super::{from_glue_value::from_glue_value, into_glue_expr::into_glue_expr, *};
+use super::{
+    from_glue_value::{from_glue_value, optional_from_glue_value},
+    into_glue_expr::into_glue_expr,
+    *,
+};
 use proc_macro2::TokenStream;
 
-pub fn impl_table(struct_ident: Ident, table_name: String, columns: Vec<Column>) -> TokenStream {
+pub fn impl_table(
+    vis: Visibility,
+    struct_ident: Ident,
+    table_name: String,
+    table_attrs: TableAttrs,
+    columns: Vec<Column>,
+) -> TokenStream {
     let fields_idents = columns.iter().map(|col| col.field_name.clone());
     let table_schema = columns.iter().map(column_schema);
     let from_row_extractions = columns.iter().enumerate().rev().map(from_glue_value);
     let into_row_items = columns.iter().rev().map(into_row_item);
+    let new_row_fn = new_row(&columns);
+    let default_fns = columns.iter().filter_map(default_fn);
+    let editor_defaults = columns
+        .iter()
+        .filter_map(|col| editor_default(&struct_ident, col));
     let find_fns = columns.iter().map(select_by);
+    let index_fns = table_attrs
+        .indexes
+        .iter()
+        .filter(|fields| fields.len() > 1)
+        .map(|fields| select_by_index(&index_columns(&columns, fields)));
     let check_fns = columns.iter().filter(|col| !col.pkey).map(check);
-    let update_fns = columns.iter().filter(|col| !col.pkey).map(update);
+    let version_column = columns.iter().find(|col| col.version);
+    let updated_at_columns: Vec<&Column> = columns.iter().filter(|c| c.updated_at).collect();
+    let key_names: Vec<&Ident> = columns
+        .iter()
+        .filter(|c| c.pkey)
+        .map(|c| &c.field_name)
+        .collect();
+    let update_fns = columns
+        .iter()
+        .filter(|col| !col.pkey && !col.version && !col.updated_at)
+        .map(|col| update(col, version_column, &updated_at_columns, &key_names));
     let get_all_as_strings = columns.iter().map(get_as_string);
     let range_fns = columns.iter().filter(|col| col.comparable()).map(in_range);
+    let key_count = columns.iter().filter(|c| c.pkey).count();
+    let aggregate_fns = columns
+        .iter()
+        .enumerate()
+        .filter(|(_, col)| !col.list)
+        .map(|(index, col)| aggregates(index, col, key_count));
+    let columns_fields = columns
+        .iter()
+        .map(|col| typed_column_field(&struct_ident, col));
+    let columns_values = columns.iter().map(typed_column);
+    let sql_columns_fields = columns.iter().map(|col| {
+        let Column {
+            column_name,
+            full_type,
+            ..
+        } = col;
+        let column = column_ident(column_name);
+        q!(pub #column: prest::SqlColumn<#full_type>)
+    });
+    let sql_columns_values = columns.iter().enumerate().map(sql_column);
+    let reference_columns: Vec<&Column> =
+        columns.iter().filter(|c| c.references.is_some()).collect();
+    let reference_checks = reference_columns.iter().map(|col| {
+        let field_name = &col.field_name;
+        reference_check(col, q!(self.#field_name))
+    });
+    let reference_loaders = reference_columns.iter().map(|col| reference_loader(col));
+    let referencing_loaders = referencing_loaders(&vis, &struct_ident, &reference_columns);
 
-    let pkey_column = columns.iter().find(|c| c.pkey).unwrap();
-    let Column {
-        field_name: key_name_ident,
-        field_name_str: key_name_str,
-        full_type: key_type_token,
-        ..
-    } = &pkey_column;
-    let id_from_str = if pkey_column.full_type_str != "String" {
-        let key_type = pkey_column.full_type.clone();
-        Some(q! {
-            use std::str::FromStr;
-            let id = #key_type::from_str(&id)?;
-        })
+    let pkey_columns: Vec<&Column> = columns.iter().filter(|c| c.pkey).collect();
+    let key_types = pkey_columns.iter().map(|c| &c.full_type);
+
+    // single pkey column is used as is while composite keys are tuples of their columns
+    let (key_type_token, get_pkey, key_pattern) = if key_count == 1 {
+        let key_name = key_names[0];
+        let key_type = &pkey_columns[0].full_type;
+        (q!(#key_type), q!(self.#key_name.clone()), q!(#key_name))
     } else {
-        None
+        (
+            q!((#(#key_types ,)*)),
+            q!((#(self.#key_names.clone() ,)*)),
+            q!((#(#key_names ,)*)),
+        )
     };
 
-    let set_columns = columns.iter().filter(|col| !col.pkey).map(set_column);
+    let key_from_str = pkey_columns.iter().enumerate().map(|(i, col)| {
+        let Column {
+            field_name,
+            full_type,
+            ..
+        } = col;
+        if col.full_type_str != "String" {
+            q!(let #field_name = <#full_type as std::str::FromStr>::from_str(&key_parts[#i])?;)
+        } else {
+            q!(let #field_name = key_parts[#i].clone();)
+        }
+    });
+
+    // creation time stays the same while modification time is refreshed
+    let set_columns = columns
+        .iter()
+        .filter(|col| !col.pkey && !col.version && !col.created_at)
+        .map(|col| match col.updated_at {
+            true => set_now(col),
+            false => set_column(col),
+        })
+        .collect::<Vec<_>>();
+    // rows of the tables with only key columns have nothing to update
+    let update_row = match set_columns.is_empty() && version_column.is_none() {
+        true => q!(),
+        false => update_row(q!(#(#set_columns)*), version_column, &key_names),
+    };
+    let now = (!updated_at_columns.is_empty()).then(|| q!(let now = prest::Utc::now();));
+    let check_references_fn = match reference_columns.is_empty() {
+        true => q!(),
+        false => q!(
+            async fn check_references(&self) -> prest::Result {
+                #(#reference_checks)*
+                Ok(())
+            }
+        ),
+    };
+    let increment_version = increment_version(version_column);
     let save_fn = q!(
-        async fn save(&self) -> prest::Result<&Self> {
-            if Self::select_by_pkey(self.get_pkey().clone()).await?.is_some() {
-                Self::update().filter(Self::pkey_filter(self.get_pkey()))
-                    #(#set_columns)*
-                    .exec()
-                    .await?;
+        async fn save(&mut self) -> prest::Result<&mut Self> {
+            prest::Validate::validate(self)?;
+            self.check_references().await?;
+            if Self::select_by_pkey(self.get_pkey()).await?.is_some() {
+                #now
+                #update_row
+                #increment_version
             } else {
                 self.insert_self().await?;
             }
-            Ok(&self)
+            Ok(self)
         }
     );
 
-    let pkey_expr = into_glue_expr(pkey_column, q!(pkey), false, false);
-    let pkey_filter = q!(sql::col(#key_name_str).eq(#pkey_expr));
+    // key columns are bound by reference from `&Self::Key` so numeric values are dereferenced
+    let pkey_filter = pkey_columns
+        .iter()
+        .map(|col| {
+            let Column {
+                field_name,
+                column_name,
+                ..
+            } = col;
+            let expr = typed_value(col, into_glue_expr(col, q!(#field_name), true, false));
+            q!(sql::col(#column_name).eq(#expr))
+        })
+        .reduce(|filter, next| q!(#filter.and(#next)))
+        .expect("Table must have a primary key");
+
+    // single pkey columns are matched with a list while composite keys are matched one by one
+    let pkeys_filter = if key_count == 1 {
+        let col = pkey_columns[0];
+        let Column {
+            field_name,
+            column_name,
+            ..
+        } = col;
+        let expr = typed_value(col, into_glue_expr(col, q!(#field_name), true, false));
+        q!(sql::col(#column_name).in_list(pkeys.iter().map(|#field_name| #expr).collect::<Vec<_>>()))
+    } else {
+        q!(pkeys
+            .iter()
+            .map(Self::pkey_filter)
+            .reduce(|filter, next| filter.or(next))
+            .unwrap_or_else(|| sql::expr("FALSE")))
+    };
+
+    // tables with composite keys can't be referenced
+    let single_key_table = if key_count == 1 {
+        q!(
+            impl prest::_SingleKeyTable for #struct_ident {
+                type SingleKey = #key_type_token;
+                fn _key(key: Self::SingleKey) -> Self::Key {
+                    key
+                }
+                fn _single_key(&self) -> Self::SingleKey {
+                    self.get_pkey()
+                }
+            }
+        )
+    } else {
+        q!()
+    };
+    let remove_references = if key_count == 1 {
+        let key_name = key_names[0];
+        let key_expr = into_glue_expr(pkey_columns[0], q!(#key_name), true, false);
+        q!(
+            async fn remove_references(pkey: &Self::Key) -> prest::Result {
+                let #key_name = pkey;
+                prest::DB._remove_references(Self::TABLE_NAME, #key_expr).await
+            }
+        )
+    } else {
+        q!()
+    };
 
     let schema_name = ident(&format!("{}Schema", struct_ident.to_string()));
+    let columns_name = ident(&format!("{struct_ident}Columns"));
+    let sql_columns_name = ident(&format!("{struct_ident}SqlColumns"));
+
+    let dropped_columns = table_attrs.dropped;
+    let indexes = table_attrs.indexes.iter().map(|fields| {
+        let names: Vec<String> = index_columns(&columns, fields)
+            .iter()
+            .map(|col| col.column_name.clone())
+            .collect();
+        let name = format!("{table_name}_{}", names.join("_"));
+        q!(IndexSchema { name: #name, columns: &[#(#names ,)*] })
+    });
+
+    let synced = table_attrs.sync.is_some();
+    let sync_items = match &table_attrs.sync {
+        None => q!(),
+        Some(policy) => {
+            let resolve = match policy {
+                SyncPolicy::ClientWins => q!(client),
+                SyncPolicy::UpdatedAt(field) => {
+                    let field = ident(field);
+                    q!(match (server, client) {
+                        (Some(server), Some(client)) if server.#field > client.#field => Some(server),
+                        (_, client) => client,
+                    })
+                }
+                SyncPolicy::Resolve(path) => q!(#path(server, client)),
+            };
+            let authorize = &table_attrs.sync_authorize;
+            q!(
+                const SYNC: bool = true;
+
+                fn resolve_conflict(server: Option<Self>, client: Option<Self>) -> Option<Self> {
+                    #resolve
+                }
+
+                fn authorize_sync(requester: &prest::SyncRequester, row: &Self) -> bool {
+                    #authorize(requester, row)
+                }
+            )
+        }
+    };
+
+    let search_fn = match columns.iter().any(|col| col.searchable) {
+        true => q!(
+            /// Rows which `#[searchable]` fields contain words starting with every word of the query, most relevant first
+            pub async fn search(query: &str, limit: usize) -> prest::Result<Vec<Self>> {
+                prest::DB._search(query, limit).await
+            }
+        ),
+        false => q!(),
+    };
 
     let relative_path = format!("/table/{table_name}");
     let full_path = format!("/admin/db{relative_path}");
@@ -55,9 +261,25 @@ pub fn impl_table(struct_ident: Ident, table_name: String, columns: Vec<Column>)
     let fields_idents2 = fields_idents.clone();
     let fields_idents3 = fields_idents.clone();
     let fields_idents4 = fields_idents.clone();
+    let fields_idents5 = fields_idents.clone();
     let get_all_as_strings2 = get_all_as_strings.clone();
+    let get_all_as_strings3 = get_all_as_strings.clone();
+    let get_all_as_strings4 = get_all_as_strings.clone();
+    let fields_idents6 = fields_idents.clone();
+    let key_from_str2 = key_from_str.clone();
 
     q! {
+        /// Typed columns of the table to build filters like `Struct::cols.field.eq(value)`
+        #vis struct #columns_name {
+            #(#columns_fields ,)*
+        }
+
+        /// Decoders of the columns by their names for the `sql!` checks
+        #[doc(hidden)]
+        #vis struct #sql_columns_name {
+            #(#sql_columns_fields ,)*
+        }
+
         struct #schema_name;
         #[async_trait]
         impl TableSchemaTrait for #schema_name {
@@ -67,15 +289,25 @@ pub fn impl_table(struct_ident: Ident, table_name: String, columns: Vec<Column>)
             fn columns(&self) -> ColumnSchemas {
                 #struct_ident::COLUMN_SCHEMAS
             }
+            fn dropped_columns(&self) -> &'static [&'static str] {
+                &[#(#dropped_columns ,)*]
+            }
+            fn indexes(&self) -> &'static [IndexSchema] {
+                &[#(#indexes ,)*]
+            }
             fn relative_path(&self) -> &'static str {
                 #relative_path
             }
             fn full_path(&self) -> &'static str {
                 #full_path
             }
-            async fn get_all(&self) -> prest::Result<Vec<Vec<String>>> {
+            async fn count(&self) -> prest::Result<usize> {
+                <#struct_ident as prest::Table>::count().await
+            }
+            async fn get_page(&self, offset: usize, limit: usize) -> prest::Result<Vec<Vec<String>>> {
                 let mut rows = vec![];
-                for item in #struct_ident::select_all().await? {
+                let order = #struct_ident::pkey_order();
+                for item in #struct_ident::select_page(limit, offset, order).await? {
                     let #struct_ident { #(#fields_idents3 ,)* } = item;
                     let mut row = vec![];
                     #(#get_all_as_strings)*
@@ -84,24 +316,73 @@ pub fn impl_table(struct_ident: Ident, table_name: String, columns: Vec<Column>)
                 Ok(rows)
             }
             async fn get_row_by_id(&self, id: String) -> prest::Result<Vec<String>> {
-                #id_from_str
-                let Some(#struct_ident { #(#fields_idents4 ,)* }) = #struct_ident::select_by_pkey(id.clone()).await? else {
+                let key_parts = prest::_pkey_parts(&id, #key_count)?;
+                #(#key_from_str)*
+                let Some(#struct_ident { #(#fields_idents4 ,)* }) = #struct_ident::select_by_pkey(#key_pattern).await? else {
                     return Err(prest::e!("expected to find a row by id = {id}"))
                 };
                 let mut row = vec![];
                 #(#get_all_as_strings2)*
                 Ok(row)
             }
+            async fn get_row_history(&self, id: String) -> prest::Result<Vec<prest::RowVersion<Vec<String>>>> {
+                let key_parts = prest::_pkey_parts(&id, #key_count)?;
+                #(#key_from_str2)*
+                let Some(item) = #struct_ident::select_by_pkey(#key_pattern).await? else {
+                    return Err(prest::e!("expected to find a row by id = {id}"))
+                };
+                let mut versions = vec![];
+                for version in item.history().await? {
+                    versions.push(version.try_map(|#struct_ident { #(#fields_idents5 ,)* }| {
+                        let mut row = vec![];
+                        #(#get_all_as_strings3)*
+                        Ok(row)
+                    })?);
+                }
+                Ok(versions)
+            }
+            async fn search(&self, query: String, limit: usize) -> prest::Result<Vec<Vec<String>>> {
+                let mut rows = vec![];
+                for item in prest::DB._search::<#struct_ident>(&query, limit).await? {
+                    let #struct_ident { #(#fields_idents6 ,)* } = item;
+                    let mut row = vec![];
+                    #(#get_all_as_strings4)*
+                    rows.push(row);
+                }
+                Ok(rows)
+            }
             async fn save(&self, req: Request) -> prest::Result<String> {
-                let value: #struct_ident = Vals::from_request(req, &()).await?.0;
+                // checked by the save after the missing fields are filled
+                let mut value: prest::JsonValue = Json::from_request(req, &()).await?.0;
+                if let prest::JsonValue::Object(fields) = &mut value {
+                    #(#editor_defaults)*
+                }
+                let mut value: #struct_ident = prest::from_json_value(value)?;
                 value.save().await?;
-                Ok(value.get_pkey().to_string())
+                Ok(prest::_pkey_id(vec![#(value.#key_names.to_string() ,)*]))
             }
             async fn remove(&self, req: Request) -> prest::Result {
-                let value: #struct_ident = Vals::from_request(req, &()).await?.0;
+                // stored rows are removed even if they fail the checks
+                let mut value: #struct_ident = Json::from_request(req, &()).await?.0;
                 value.remove().await?;
                 Ok(())
             }
+            async fn remove_where(&self, filter: sql::ExprNode<'static>) -> prest::Result {
+                let rows = #struct_ident::select().filter(filter).rows().await?;
+                for item in #struct_ident::from_rows(rows)? {
+                    item.remove().await?;
+                }
+                Ok(())
+            }
+            fn synced(&self) -> bool {
+                #synced
+            }
+            async fn sync_pull(&self, requester: &prest::SyncRequester, since: Option<u64>) -> prest::Result<Vec<u8>> {
+                prest::_sync_pull::<#struct_ident>(requester, since).await
+            }
+            async fn sync_push(&self, requester: &prest::SyncRequester, writes: Vec<u8>) -> prest::Result<Vec<u8>> {
+                prest::_sync_push::<#struct_ident>(requester, writes).await
+            }
         }
 
         #[prest::async_trait]
@@ -109,12 +390,20 @@ pub fn impl_table(struct_ident: Ident, table_name: String, columns: Vec<Column>)
             const TABLE_NAME: &'static str = #table_name;
             const COLUMN_SCHEMAS: prest::ColumnSchemas = &[#(#table_schema),*];
             type Key = #key_type_token;
+            #sync_items
 
-            fn get_pkey(&self) -> &Self::Key {
-                &self.#key_name_ident
+            fn get_pkey(&self) -> Self::Key {
+                #get_pkey
             }
 
-            fn pkey_filter<'a, 'b>(pkey: &'a Self::Key) -> prest::sql::ExprNode<'b> { #pkey_filter }
+            fn pkey_filter<'a, 'b>(pkey: &'a Self::Key) -> prest::sql::ExprNode<'b> {
+                let #key_pattern = pkey;
+                #pkey_filter
+            }
+
+            fn pkeys_filter(pkeys: &[Self::Key]) -> prest::sql::ExprNode<'static> {
+                #pkeys_filter
+            }
 
             fn schema() -> &'static dyn TableSchemaTrait { &#schema_name }
 
@@ -128,21 +417,42 @@ pub fn impl_table(struct_ident: Ident, table_name: String, columns: Vec<Column>)
                 Ok(vec![#(#fields_idents2 ,)*].into())
             }
 
+            #new_row_fn
+            #check_references_fn
             #save_fn
+            #remove_references
         }
 
+        #single_key_table
+
         impl #struct_ident {
+            #[allow(non_upper_case_globals)]
+            #vis const cols: #columns_name = #columns_name {
+                #(#columns_values ,)*
+            };
+
+            #[doc(hidden)]
+            #vis const _SQL_COLUMNS: #sql_columns_name = #sql_columns_name {
+                #(#sql_columns_values ,)*
+            };
+
+            #(#default_fns)*
             #(#find_fns)*
+            #(#index_fns)*
+            #search_fn
             #(#range_fns)*
+            #(#aggregate_fns)*
             #(#update_fns)*
             #(#check_fns)*
+            #(#reference_loaders)*
         }
+
+        #referencing_loaders
     }
 }
 
 fn select_by(column: &Column) -> TokenStream {
     let Column {
-        field_name_str,
         field_name,
         full_type,
         inner_type,
@@ -151,18 +461,13 @@ fn select_by(column: &Column) -> TokenStream {
         ..
     } = column;
 
-    let select = q!(Self::select()
-        .filter(filter)
-        .rows()
-        .await?
-        .into_iter()
-        .map(Self::from_row)
-        .collect::<Result<Vec<Self>>>());
-
     let find_null_fn = if *optional {
         let fn_name = select_by_null_(column);
-        let filter = q!(sql::col(#field_name_str).eq(sql::null()));
-        q!( pub async fn #fn_name() -> prest::Result<Vec<Self>> { let filter = #filter; #select } )
+        q! {
+            pub async fn #fn_name() -> prest::Result<Vec<Self>> {
+                Self::select_where(Self::cols.#field_name.is_null()).await
+            }
+        }
     } else {
         q!()
     };
@@ -172,6 +477,7 @@ fn select_by(column: &Column) -> TokenStream {
         true => q!(Result<Option<Self>>),
         false => q!(Result<Vec<Self>>),
     };
+    let select = q!(Self::select_where(Self::cols.#field_name._eq_ref(#field_name)).await);
     let result = match unique {
         true => q!(Ok(#select?.pop())),
         false => select,
@@ -179,60 +485,348 @@ fn select_by(column: &Column) -> TokenStream {
 
     let fn_arg = if *optional { inner_type } else { full_type };
 
-    let filter_expr = into_glue_expr(column, q!(#field_name), true, true);
-    let filter = q!(sql::col(#field_name_str).eq(#filter_expr));
-
     q! {
         pub async fn #fn_name(#field_name: &#fn_arg) -> #fn_value {
-            let filter = #filter;
             #result
         }
         #find_null_fn
     }
 }
 
+fn index_columns<'a>(columns: &'a [Column], fields: &[Ident]) -> Vec<&'a Column> {
+    fields
+        .iter()
+        .map(|field| columns.iter().find(|c| c.field_name == *field))
+        .map(|col| col.expect("index fields are checked when deriving"))
+        .collect()
+}
+
+/// Composite indexes are stored on the leading column and the rest of them filter its matches
+fn select_by_index(cols: &[&Column]) -> TokenStream {
+    let names: Vec<String> = cols.iter().map(|col| col.field_name_str.clone()).collect();
+    let fn_name = ident(&format!("select_by_{}", names.join("_and_")));
+    let fn_args = cols.iter().map(|col| {
+        let field_name = &col.field_name;
+        let fn_arg = if col.optional {
+            &col.inner_type
+        } else {
+            &col.full_type
+        };
+        q!(#field_name: &#fn_arg)
+    });
+    let mut filters = cols.iter().map(|col| {
+        let field_name = &col.field_name;
+        q!(Self::cols.#field_name._eq_ref(#field_name))
+    });
+    let leading = filters.next();
+    q! {
+        pub async fn #fn_name(#(#fn_args ,)*) -> prest::Result<Vec<Self>> {
+            Self::select_where(#leading #(.and(#filters))*).await
+        }
+    }
+}
+
 fn in_range(col: &Column) -> TokenStream {
     let Column {
+        field_name,
         inner_type,
-        sql_type,
         ..
     } = col;
     let fn_name = find_in_range_(col);
-    let filter_str = if sql_type.quoted() { "'{}'" } else { "{}" };
-    let filter = q!(sql::col("timestamp")
-        .gte(sql::expr(format!(#filter_str, min)))
-        .and(sql::col("timestamp").lte(sql::expr(format!(#filter_str, max)))));
-
-    let values = q!(Self::select()
-        .filter(#filter)
-        .rows()
-        .await?
-        .into_iter()
-        .map(Self::from_row)
-        .collect());
-    q! { pub async fn #fn_name(min: &#inner_type, max: &#inner_type) -> Result<Vec<Self>> { #values } }
-}
-
-fn update(col: &Column) -> TokenStream {
+    let filter = q!(Self::cols.#field_name.between(min.clone(), max.clone()));
+    q! {
+        pub async fn #fn_name(min: &#inner_type, max: &#inner_type) -> Result<Vec<Self>> {
+            Self::select_where(#filter).await
+        }
+    }
+}
+
+/// `sum_{col}` and `avg_{col}` of numeric columns, `min_{col}` and `max_{col}` of comparable ones
+/// and `group_by_{col}_count` of the columns with repeating values, computed by the database
+fn aggregates(index: usize, col: &Column, key_count: usize) -> TokenStream {
+    let Column {
+        field_name,
+        field_name_str,
+        column_name,
+        full_type,
+        inner_type,
+        optional,
+        sql_type,
+        ..
+    } = col;
+    let quoted = format!("\"{column_name}\"");
+    let mut fns = vec![];
+
+    // NULLs are skipped like in SQL
+    let not_null = match optional {
+        true => q!(
+            let not_null = Self::cols.#field_name.is_not_null();
+            let filter = Some(match filter {
+                Some(filter) => filter.and(not_null),
+                None => not_null,
+            });
+        ),
+        false => q!(),
+    };
+
+    if col.numeric() {
+        // small numbers are summed as i64 and f64 to avoid overflows
+        let (sum, sum_type) = match sql_type {
+            Uint64 => (format!("SUM({quoted})"), q!(u64)),
+            Int128 => (format!("SUM({quoted})"), q!(i128)),
+            Uint128 => (format!("SUM({quoted})"), q!(u128)),
+            Decimal => (format!("SUM({quoted})"), q!(prest::Decimal)),
+            Float | Float32 => (format!("SUM(CAST({quoted} AS FLOAT))"), q!(f64)),
+            _ => (format!("SUM(CAST({quoted} AS INT))"), q!(i64)),
+        };
+        let doc = format!("Sum of the `{field_name_str}` values, zero if there are none");
+        fns.push(aggregate_fn(
+            &format!("sum_{field_name_str}"),
+            &doc,
+            sum_type.clone(),
+            q!(
+                #not_null
+                let rows = prest::_aggregate(#sum, None, filter).await?;
+                Ok(prest::_aggregated::<#sum_type, _>(rows)?.unwrap_or_default())
+            ),
+        ));
+
+        let avg = match sql_type {
+            Decimal => format!("AVG({quoted})"),
+            _ => format!("AVG(CAST({quoted} AS FLOAT))"),
+        };
+        let doc = format!("Average of the `{field_name_str}` values, `None` if there are none");
+        fns.push(aggregate_fn(
+            &format!("avg_{field_name_str}"),
+            &doc,
+            q!(Option<f64>),
+            q!(
+                #not_null
+                let rows = prest::_aggregate(#avg, None, filter).await?;
+                prest::_aggregated::<f64, _>(rows)
+            ),
+        ));
+    }
+
+    if col.comparable() {
+        let extraction = optional_from_glue_value(index, col);
+        for (function, name) in [("MIN", "Minimum"), ("MAX", "Maximum")] {
+            let projection = format!("{function}({quoted})");
+            let doc = format!("{name} of the `{field_name_str}` values, `None` if there are none");
+            fns.push(aggregate_fn(
+                &format!("{}_{field_name_str}", function.to_lowercase()),
+                &doc,
+                q!(Option<#inner_type>),
+                q!(
+                    #not_null
+                    let mut rows = prest::_aggregate(#projection, None, filter).await?;
+                    let Some(mut row) = rows.pop() else {
+                        return Ok(None);
+                    };
+                    #extraction
+                    Ok(#field_name)
+                ),
+            ));
+        }
+    }
+
+    // unique values and single primary keys form groups of one row
+    let single_rows = col.unique || (col.pkey && key_count == 1);
+    if !single_rows && !matches!(sql_type, Map) {
+        let extraction = from_glue_value((index, col));
+        let doc =
+            format!("Numbers of rows with each of the `{field_name_str}` values in their order");
+        fns.push(aggregate_fn(
+            &format!("group_by_{field_name_str}_count"),
+            &doc,
+            q!(Vec<(#full_type, usize)>),
+            q!(
+                let mut _groups = vec![];
+                for mut row in prest::_aggregate("COUNT(*)", Some(#column_name), filter).await? {
+                    let _count = match row.pop() {
+                        Some(prest::sql::Value::I64(count)) => count as usize,
+                        other => return Err(prest::e!("unexpected count {other:?}")),
+                    };
+                    #extraction
+                    _groups.push((#field_name, _count));
+                }
+                Ok(_groups)
+            ),
+        ));
+    }
+
+    q!(#(#fns)*)
+}
+
+/// Aggregate over all the rows along with the `{name}_where` variant over the rows matching the filter
+fn aggregate_fn(name: &str, doc: &str, value: TokenStream, body: TokenStream) -> TokenStream {
+    let fn_name = ident(name);
+    let where_fn_name = ident(&format!("{name}_where"));
+    let where_doc = format!("{doc}, among the rows matching the filter");
+    q! {
+        #[doc = #doc]
+        pub async fn #fn_name() -> prest::Result<#value> {
+            let filter: Option<prest::Filter<Self>> = None;
+            #body
+        }
+
+        #[doc = #where_doc]
+        pub async fn #where_fn_name(filter: prest::Filter<Self>) -> prest::Result<#value> {
+            let filter = Some(filter);
+            #body
+        }
+    }
+}
+
+fn typed_column_field(struct_ident: &Ident, col: &Column) -> TokenStream {
+    let Column {
+        vis,
+        field_name,
+        full_type,
+        inner_type,
+        optional,
+        ..
+    } = col;
+    let value_type = if *optional { inner_type } else { full_type };
+    q!(#vis #field_name: prest::TableColumn<#struct_ident, #value_type>)
+}
+
+fn typed_column(col: &Column) -> TokenStream {
+    let Column {
+        field_name,
+        column_name,
+        full_type,
+        inner_type,
+        optional,
+        list,
+        sql_type,
+        ..
+    } = col;
+    let value_type = if *optional { inner_type } else { full_type };
+    // lists are compared as they are without casting
+    let cast = match list {
+        true => q!(None),
+        false => {
+            let sql_type = sql_type.to_string();
+            q!(Some(#sql_type))
+        }
+    };
+    let expr = into_glue_expr(col, q!(#field_name), true, true);
+    q! {
+        #field_name: prest::TableColumn::_new(
+            #column_name,
+            #cast,
+            |#field_name: &#value_type| -> prest::Result<sql::ExprNode<'static>> { Ok(#expr) },
+        )
+    }
+}
+
+fn sql_column((index, col): (usize, &Column)) -> TokenStream {
+    let Column {
+        field_name,
+        column_name,
+        ..
+    } = col;
+    let column = column_ident(column_name);
+    let extraction = from_glue_value((index, col));
+    q! {
+        #column: prest::SqlColumn::_new(|value| {
+            let mut row = vec![value];
+            #extraction
+            Ok(#field_name)
+        })
+    }
+}
+
+fn update(
+    col: &Column,
+    version_column: Option<&Column>,
+    updated_at_columns: &[&Column],
+    key_names: &[&Ident],
+) -> TokenStream {
     let Column {
         field_name,
         full_type,
         ..
     } = col;
     let fn_name = update_(col);
-    let set_column = set_column(col);
+    // the item is changed only once the row is updated
+    let set_value = set_value(col, q!(#field_name));
+    let set_now = updated_at_columns.iter().map(|c| set_now(c));
+    let update_row = update_row(q!(#set_value #(#set_now)*), version_column, key_names);
+    let (now, touch) = match updated_at_columns.is_empty() {
+        true => (q!(), q!()),
+        false => {
+            let fields = updated_at_columns.iter().map(|c| &c.field_name);
+            let values = updated_at_columns.iter().map(|c| now_value(c));
+            (
+                q!(let now = prest::Utc::now();),
+                q!(#(self.#fields = #values;)*),
+            )
+        }
+    };
+    let reference_check = col
+        .references
+        .is_some()
+        .then(|| reference_check(col, q!(#field_name)));
+    let increment_version = increment_version(version_column);
     q! {
         pub async fn #fn_name(&mut self, #field_name: #full_type) -> prest::Result<&mut Self> {
+            #reference_check
+            #now
+            #update_row
             self.#field_name = #field_name;
-            Self::update().filter(Self::pkey_filter(self.get_pkey()))
-                #set_column
-                .exec()
-                .await?;
+            #touch
+            #increment_version
             Ok(self)
         }
     }
 }
 
+/// Bumps the version of the item after its row was updated
+fn increment_version(version_column: Option<&Column>) -> Option<TokenStream> {
+    version_column.map(|version| {
+        let version = &version.field_name;
+        q!(self.#version += 1;)
+    })
+}
+
+/// Updates the row with the same pkey, and the same version if the table has a version column
+fn update_row(
+    set_columns: TokenStream,
+    version_column: Option<&Column>,
+    key_names: &[&Ident],
+) -> TokenStream {
+    let pkey_filter = q!(Self::pkey_filter(&self.get_pkey()));
+    let Some(version) = version_column else {
+        return q!(Self::update().filter(#pkey_filter) #set_columns .exec().await?;);
+    };
+    let Column {
+        field_name,
+        column_name,
+        ..
+    } = version;
+    let current = typed_value(
+        version,
+        into_glue_expr(version, q!(self.#field_name), false, false),
+    );
+    let next = into_glue_expr(version, q!((self.#field_name + 1)), false, false);
+    q! {
+        let updated = Self::update()
+            .filter(#pkey_filter.and(sql::col(#column_name).eq(#current)))
+            #set_columns
+            .set(#column_name, #next)
+            .exec()
+            .await?;
+        if let prest::sql::Payload::Update(0) = updated {
+            return Err(prest::Error::StaleWrite {
+                table: Self::TABLE_NAME.to_owned(),
+                id: prest::_pkey_id(vec![#(self.#key_names.to_string() ,)*]),
+            });
+        }
+    }
+}
+
 fn check(col: &Column) -> TokenStream {
     let Column {
         field_name,
@@ -242,7 +836,7 @@ fn check(col: &Column) -> TokenStream {
     let fn_name = check_(col);
     q! {
         pub async fn #fn_name(&self, value: #full_type) -> prest::Result<bool> {
-            if let Some(item) = Self::select_by_pkey(self.get_pkey().clone()).await? {
+            if let Some(item) = Self::select_by_pkey(self.get_pkey()).await? {
                 Ok(item.#field_name == value)
             } else {
                 Err(prest::Error::NotFound)
@@ -257,10 +851,14 @@ fn get_as_string(col: &Column) -> TokenStream {
         list,
         optional,
         serialized,
+        enumerated,
+        sql_type,
         ..
     } = col;
 
-    let preprocessing = (*serialized || *list || *optional)
+    // enums are rendered as json to be deserialized back by the admin editor
+    let displayable = !matches!(sql_type, SqlType::Bytea | SqlType::Map) && !*enumerated;
+    let preprocessing = (*serialized || *list || *optional || !displayable)
         .then(|| q!(let #field_name = prest::to_json_string(&#field_name)?;));
 
     q! {
@@ -270,6 +868,97 @@ fn get_as_string(col: &Column) -> TokenStream {
     }
 }
 
+/// Value of the `#[default_column(...)]` field
+fn default_fn(col: &Column) -> Option<TokenStream> {
+    let Column {
+        field_name_str,
+        full_type,
+        default,
+        ..
+    } = col;
+    let fn_name = ident(&format!("_default_{field_name_str}"));
+    let expr = default.as_ref()?;
+    Some(q! {
+        #[doc(hidden)]
+        pub fn #fn_name() -> #full_type {
+            #expr
+        }
+    })
+}
+
+/// Fills the default and timestamp fields which the DB editor doesn't send
+fn editor_default(struct_ident: &Ident, col: &Column) -> Option<TokenStream> {
+    let Column {
+        field_name_str,
+        full_type,
+        created_at,
+        updated_at,
+        ..
+    } = col;
+    let value = match col.default.is_some() {
+        true => {
+            let fn_name = ident(&format!("_default_{field_name_str}"));
+            q!(#struct_ident::#fn_name())
+        }
+        // replaced by the time of the write
+        false if *created_at || *updated_at => q!(<#full_type as Default>::default()),
+        false => return None,
+    };
+    Some(q! {
+        if !fields.contains_key(#field_name_str) {
+            fields.insert(#field_name_str.to_owned(), prest::json!(#value));
+        }
+    })
+}
+
+/// Overrides the default `new_row` if some columns are filled with the insertion time
+fn new_row(columns: &[Column]) -> TokenStream {
+    if !columns.iter().any(|col| col.created_at || col.updated_at) {
+        return q!();
+    }
+    let fields_idents = columns
+        .iter()
+        .map(|col| &col.field_name)
+        .collect::<Vec<_>>();
+    let items = columns
+        .iter()
+        .rev()
+        .map(|col| match col.created_at || col.updated_at {
+            true => timestamp_item(col, now_value(col)),
+            false => into_row_item(col),
+        });
+    // creation time of the replaced row is kept while modification time is refreshed
+    let replacing_items = columns
+        .iter()
+        .rev()
+        .map(|col| match (col.created_at, col.updated_at) {
+            (true, _) => {
+                let field_name = &col.field_name;
+                timestamp_item(col, q!(stored.#field_name))
+            }
+            (_, true) => timestamp_item(col, now_value(col)),
+            _ => into_row_item(col),
+        });
+    q! {
+        fn new_row(&self) -> prest::Result<sql::ExprList<'static>> {
+            let now = prest::Utc::now();
+            #(#items)*
+            Ok(vec![#(#fields_idents ,)*].into())
+        }
+        fn replacing_row(&self, stored: &Self) -> prest::Result<sql::ExprList<'static>> {
+            let now = prest::Utc::now();
+            #(#replacing_items)*
+            Ok(vec![#(#fields_idents ,)*].into())
+        }
+    }
+}
+
+fn timestamp_item(column: &Column, value: TokenStream) -> TokenStream {
+    let field_name = &column.field_name;
+    let sql_expr = into_glue_expr(column, value, false, false);
+    q!( let #field_name: sql::ExprNode<'static> = #sql_expr; )
+}
+
 fn into_row_item(column: &Column) -> TokenStream {
     let Column { field_name, .. } = column;
 
@@ -278,14 +967,167 @@ fn into_row_item(column: &Column) -> TokenStream {
 }
 
 fn set_column(column: &Column) -> TokenStream {
+    let field_name = &column.field_name;
+    set_value(column, q!(self.#field_name))
+}
+
+fn set_value(column: &Column, path: TokenStream) -> TokenStream {
+    let column_name = &column.column_name;
+    let col: Expr = parse_quote!(#column_name);
+    let value = into_glue_expr(column, path, false, false);
+    q! { .set(#col, #value) }
+}
+
+fn set_now(column: &Column) -> TokenStream {
+    let column_name = &column.column_name;
+    let value = into_glue_expr(column, now_value(column), false, false);
+    q! { .set(#column_name, #value) }
+}
+
+/// Value of the `now` timestamp in the type of the column
+fn now_value(column: &Column) -> TokenStream {
+    match column.utc() {
+        true => q!(now),
+        false => q!(now.naive_utc()),
+    }
+}
+
+/// Checks that the referenced row exists for the value at the path
+fn reference_check(col: &Column, path: TokenStream) -> TokenStream {
+    let Column {
+        column_name,
+        optional,
+        references,
+        ..
+    } = col;
+    let target = references.as_ref().expect("referenced table");
+    let check = q!(
+        let key = <#target as prest::_SingleKeyTable>::_key(key.clone());
+        if <#target as prest::Table>::select_by_pkey(key).await?.is_none() {
+            return Err(prest::Error::ForeignKeyViolation {
+                table: Self::TABLE_NAME.to_owned(),
+                column: #column_name.to_owned(),
+            });
+        }
+    );
+    if *optional {
+        q!( if let Some(key) = &#path { #check } )
+    } else {
+        q!({ let key = &#path; #check })
+    }
+}
+
+fn reference_loader(col: &Column) -> TokenStream {
     let Column {
         field_name,
-        field_name_str,
+        optional,
+        references,
         ..
-    } = column;
-    let col: Expr = parse_quote!(#field_name_str);
-    let value = into_glue_expr(column, q!(self.#field_name), false, false);
-    q! { .set(#col, #value) }
+    } = col;
+    let target = references.as_ref().expect("referenced table");
+    let load = if *optional {
+        q!(match &self.#field_name {
+            Some(key) => {
+                let key = <#target as prest::_SingleKeyTable>::_key(key.clone());
+                <#target as prest::Table>::select_by_pkey(key).await
+            }
+            None => Ok(None),
+        })
+    } else {
+        q!({
+            let key = <#target as prest::_SingleKeyTable>::_key(self.#field_name.clone());
+            <#target as prest::Table>::select_by_pkey(key).await
+        })
+    };
+    q! {
+        pub async fn #field_name(&self) -> prest::Result<Option<#target>> {
+            #load
+        }
+    }
+}
+
+/// Generates `{Target}{Struct}s` extension traits with `target.{structs}()` loaders,
+/// or `target.{structs}_by_{field}()` if there are several references to the same table
+fn referencing_loaders(vis: &Visibility, struct_ident: &Ident, columns: &[&Column]) -> TokenStream {
+    use syn::spanned::Spanned;
+
+    let mut targets: Vec<(&syn::Path, Vec<&Column>)> = vec![];
+    for col in columns {
+        let target = col.references.as_ref().expect("referenced table");
+        let same = |path: &syn::Path| {
+            path.to_token_stream().to_string() == target.to_token_stream().to_string()
+        };
+        match targets.iter_mut().find(|(path, _)| same(path)) {
+            Some((_, cols)) => cols.push(col),
+            None => targets.push((target, vec![col])),
+        }
+    }
+
+    let plural = snake_case(&struct_ident.to_string()) + "s";
+    let traits = targets.into_iter().map(|(target, cols)| {
+        let target_name = &target.segments.last().expect("referenced table name").ident;
+        let trait_name = ident(&format!("{target_name}{struct_ident}s"));
+        let doc = format!("Loaders of the [`{struct_ident}`]s referencing the [`{target_name}`]");
+
+        let fn_names: Vec<Ident> = cols
+            .iter()
+            .map(|col| match cols.len() {
+                1 => ident(&plural),
+                _ => ident(&format!("{plural}_by_{}", col.field_name_str)),
+            })
+            .collect();
+        let results: Vec<TokenStream> = cols
+            .iter()
+            .map(|col| match col.unique {
+                true => q!(Option<#struct_ident>),
+                false => q!(Vec<#struct_ident>),
+            })
+            .collect();
+        let select_by = cols.iter().map(|col| select_by_(col));
+
+        // keys of the composite ones can't be stored in a single column
+        let single_key = quote::quote_spanned! {target.span()=>
+            const _: () = {
+                fn referenced<T: prest::_SingleKeyTable>() {}
+                let _ = referenced::<#target>;
+            };
+        };
+
+        q! {
+            #single_key
+
+            #[doc = #doc]
+            #[prest::async_trait]
+            #vis trait #trait_name {
+                #(async fn #fn_names(&self) -> prest::Result<#results>;)*
+            }
+
+            #[prest::async_trait]
+            impl #trait_name for #target {
+                #(async fn #fn_names(&self) -> prest::Result<#results> {
+                    #struct_ident::#select_by(&<Self as prest::_SingleKeyTable>::_single_key(self)).await
+                })*
+            }
+        }
+    });
+    q!(#(#traits)*)
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i != 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// Key and index lookups compare values of the exact column type while literals are parsed as I64, F64 or text
+fn typed_value(col: &Column, expr: TokenStream) -> TokenStream {
+    let sql_type = col.sql_type.to_string();
+    q!(prest::sql::ExprNode::from(#expr).cast(#sql_type))
 }
 
 fn select_by_(col: &Column) -> Ident {
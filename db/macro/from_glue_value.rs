@@ -2,11 +2,19 @@ use super::*;
 use proc_macro2::TokenStream;
 
 pub fn from_glue_value((index, col): (usize, &Column)) -> TokenStream {
+    extract_value(index, col, col.optional)
+}
+
+/// Extracts the value of the column as `Option` of the inner type, for example as a result of `MIN` or `MAX`
+pub fn optional_from_glue_value(index: usize, col: &Column) -> TokenStream {
+    extract_value(index, col, true)
+}
+
+fn extract_value(index: usize, col: &Column, optional: bool) -> TokenStream {
     let Column {
         field_name,
         inner_type,
         sql_type,
-        optional,
         list,
         ..
     } = col;
@@ -15,6 +23,14 @@ pub fn from_glue_value((index, col): (usize, &Column)) -> TokenStream {
 
     let transform = match col.from_row_transform() {
         FromRowTransform::UuidFromU128 => q!(let v = prest::Uuid::from_u128(v)),
+        FromRowTransform::UtcFromNaive => q!(let v = v.and_utc()),
+        FromRowTransform::FromMap => q!(
+            let v = prest::sql::Value::Map(v);
+            let v = prest::from_json_value(prest::JsonValue::try_from(v)?)?
+        ),
+        FromRowTransform::FromVariant => {
+            q!(let v = <#inner_type as prest::TableEnum>::from_variant(&v)?)
+        }
         FromRowTransform::Deserialize => q!(let v = from_json_str(&v)?),
         FromRowTransform::None => q!(),
     };
@@ -69,7 +85,7 @@ pub fn from_glue_value((index, col): (usize, &Column)) -> TokenStream {
             };
         )
     } else {
-        let res = if *optional { q!(Some(v)) } else { q!(v) };
+        let res = if optional { q!(Some(v)) } else { q!(v) };
         let null_arm = match optional {
             true => q!( Some(prest::sql::Value::Null) => None, ),
             false => q!(),
@@ -7,6 +7,7 @@ pub fn into_glue_expr(column: &Column, path: TokenStream, deref: bool, inner: bo
         list,
         optional,
         serialized,
+        inner_type,
         ..
     } = column;
     let optional = *optional && !inner;
@@ -37,11 +38,22 @@ pub fn into_glue_expr(column: &Column, path: TokenStream, deref: bool, inner: bo
         ),
         (false, true, false) => {
             let inner = match sql_type {
+                _ if column.enumerated => {
+                    q!(sql::text(<#inner_type as prest::TableEnum>::to_variant(v)))
+                }
                 SqlType::Text => q!(sql::text(v.clone())),
                 SqlType::Uuid => q!(sql::uuid(v.to_string())),
                 SqlType::Boolean => node_literal(q!(Boolean(*v))),
+                SqlType::Date => q!(sql::date(v.to_string())),
+                SqlType::Time => q!(sql::time(v.to_string())),
+                SqlType::Timestamp if column.utc() => q!(sql::timestamp(v.naive_utc().to_string())),
+                SqlType::Timestamp => q!(sql::timestamp(v.to_string())),
+                SqlType::Bytea => q!(sql::bytea(v)),
+                SqlType::Map => q!(sql::map(prest::to_json_string(v)?)),
                 // these do not implement Into<NumNode>
-                SqlType::Int128 | SqlType::Uint128 => q!(sql::num(v.to_string())),
+                SqlType::Int128 | SqlType::Uint128 | SqlType::Decimal => {
+                    q!(sql::num(v.to_string()))
+                }
                 _ if sql_type.numeric() => q!(sql::num(*v)),
                 _ => q!(sql::expr(v.to_string())),
             };
@@ -49,11 +61,24 @@ pub fn into_glue_expr(column: &Column, path: TokenStream, deref: bool, inner: bo
         }
         (false, false, false) => {
             match sql_type {
+                _ if column.enumerated => {
+                    q!(sql::text(<#inner_type as prest::TableEnum>::to_variant(&#path)))
+                }
                 SqlType::Boolean => node_literal(q!(Boolean(#path.clone()))),
                 SqlType::Text => q!(sql::text(#path.clone())),
                 SqlType::Uuid => q!(sql::uuid(#path.to_string())),
+                SqlType::Date => q!(sql::date(#path.to_string())),
+                SqlType::Time => q!(sql::time(#path.to_string())),
+                SqlType::Timestamp if column.utc() => {
+                    q!(sql::timestamp(#path.naive_utc().to_string()))
+                }
+                SqlType::Timestamp => q!(sql::timestamp(#path.to_string())),
+                SqlType::Bytea => q!(sql::bytea(&#path)),
+                SqlType::Map => q!(sql::map(prest::to_json_string(&#path)?)),
                 // these do not implement Into<NumNode>
-                SqlType::Int128 | SqlType::Uint128 => q!(sql::num(#path.to_string())),
+                SqlType::Int128 | SqlType::Uint128 | SqlType::Decimal => {
+                    q!(sql::num(#path.to_string()))
+                }
                 _ if sql_type.numeric() && deref => q!(sql::num(*#path)),
                 _ if sql_type.numeric() => q!(sql::num(#path)),
                 _ => q!(sql::expr(format!("'{}'", &#path))),
@@ -1,24 +1,56 @@
 mod analyze;
+mod checked_sql;
 mod expand;
 mod from_glue_value;
 mod into_glue_expr;
+mod table_enum;
+mod validate;
 
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::{quote as q, ToTokens};
 use syn::{
-    parse_macro_input, parse_quote, Data, DataStruct, DeriveInput, Expr, Field, Fields, Ident, Type,
+    parse_macro_input, parse_quote, Attribute, Data, DataStruct, DeriveInput, Expr, Field, Fields,
+    Ident, LitStr, Type, Visibility,
 };
 
 pub(crate) use gluesql_core::ast::DataType as SqlType;
 use SqlType::*;
 
 /// Generates schema and helper functions to use struct as a table in the embedded database
-#[proc_macro_derive(Table, attributes(pkey_column, unique_column))]
+#[proc_macro_derive(
+    Table,
+    attributes(
+        table,
+        pkey_column,
+        unique_column,
+        index_column,
+        searchable,
+        enum_column,
+        version_column,
+        created_at,
+        updated_at,
+        default_column,
+        column,
+        references,
+        validate
+    )
+)]
 pub fn table_derive(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
+    let validate_impl = match validate::impl_validate(&ast) {
+        Ok(validate_impl) => validate_impl,
+        Err(e) => return e.into_compile_error().into(),
+    };
     let struct_ident = ast.ident;
-    let table_name = struct_ident.to_string() + "s";
+    let mut table_attrs = match analyze::table_attrs(&ast.attrs) {
+        Ok(table_attrs) => table_attrs,
+        Err(e) => return e.into_compile_error().into(),
+    };
+    let table_name = match &table_attrs.name {
+        Some(name) => name.clone(),
+        None => struct_ident.to_string() + "s",
+    };
 
     // supports only struct with named fields
     let fields = match ast.data {
@@ -32,23 +64,134 @@ pub fn table_derive(input: TokenStream) -> TokenStream {
     // decompose
     let mut columns: Vec<Column> = fields.named.into_iter().map(analyze::from_field).collect();
 
-    // can't have multiple primary keys
+    // first column is the primary key by default, several pkey columns form a composite key
     match columns.iter().filter(|c| c.pkey).count() {
         0 => {
             columns[0].pkey = true;
             columns[0].unique = true;
         }
-        1 => {}
-        _ => panic!("Table macro doesn't support more than one pkey at the moment"),
+        1 => columns
+            .iter_mut()
+            .filter(|c| c.pkey)
+            .for_each(|c| c.unique = true),
+        _ => {}
     };
 
+    // single column indexes go first, composite ones index their leading column
+    let single_indexes = columns
+        .iter()
+        .filter(|c| c.indexed)
+        .map(|c| vec![c.field_name.clone()]);
+    let mut indexes: Vec<Vec<Ident>> = vec![];
+    for index in single_indexes.chain(table_attrs.indexes) {
+        if !indexes.contains(&index) {
+            indexes.push(index);
+        }
+    }
+    table_attrs.indexes = indexes;
+
+    if columns.iter().filter(|c| c.version).count() > 1 {
+        panic!("Table can have only one #[version_column]")
+    }
+
+    if table_attrs.sync.is_some() && table_attrs.sync_authorize.is_none() {
+        panic!("sync requires an authorize = fn(&SyncRequester, &Self) -> bool hook which checks the pulled and pushed rows")
+    }
+
+    if let Some(SyncPolicy::UpdatedAt(name)) = &table_attrs.sync {
+        if !columns.iter().any(|c| c.field_name_str == *name) {
+            panic!("sync references unknown field {name}")
+        }
+    }
+
+    for field in table_attrs.indexes.iter().flatten() {
+        let Some(column) = columns.iter().find(|c| c.field_name == *field) else {
+            let msg = format!("index references unknown field {field}");
+            return syn::Error::new(field.span(), msg).into_compile_error().into();
+        };
+        if column.list {
+            let msg = "Vec<...> columns can't be indexed";
+            return syn::Error::new(field.span(), msg).into_compile_error().into();
+        }
+    }
+
     // expand
-    TokenStream::from(expand::impl_table(struct_ident, table_name, columns))
+    let table_impl = expand::impl_table(ast.vis, struct_ident, table_name, table_attrs, columns);
+    TokenStream::from(q!(#table_impl #validate_impl))
+}
+
+/// Implements `TableEnum` to store unit variants and variants with a single value as compact text in `#[enum_column]`s
+#[proc_macro_derive(TableEnum)]
+pub fn table_enum_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let Data::Enum(data) = ast.data else {
+        panic!("TableEnum can only be derived for enums")
+    };
+    TokenStream::from(table_enum::impl_table_enum(ast.ident, data))
+}
+
+/// Implements `Validate` with the `#[validate(...)]` checks of the fields: `length(min = .., max = ..)`,
+/// `range(min = .., max = ..)`, `regex = ".."`, `email` and `custom = path` to a `fn(&T) -> Result<(), String>`.
+/// `Table` derives implement it as well and shouldn't derive it again
+#[proc_macro_derive(Validate, attributes(validate))]
+pub fn validate_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let validate_impl = validate::impl_validate(&ast).unwrap_or_else(syn::Error::into_compile_error);
+    TokenStream::from(validate_impl)
+}
+
+/// Runs the SQL statement with `$1`, `$2`, ... parameters over the `Table` structs referenced by their names,
+/// checking the used columns at compile time. Selected columns are decoded into a `Vec` of their types
+/// (tuples for several), other selected expressions into `sql::Value`s and other statements return the `sql::Payload`
+///
+/// ```rust,ignore
+/// let tasks: Vec<(Uuid, String)> = sql!("SELECT t.id, t.task FROM Todo t WHERE t.done = $1", false).await?;
+/// ```
+#[proc_macro]
+pub fn sql(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input with checked_sql::untyped);
+    TokenStream::from(checked_sql::expand(input))
+}
+
+/// Like `sql!` but selects whole rows of the table with `SELECT *` or `SELECT alias.*` into a `Vec` of its struct
+///
+/// ```rust,ignore
+/// let todos = query_as!(Todo, "SELECT t.* FROM Todo t JOIN Tag g ON g.todo = t.id WHERE g.name = $1", name).await?;
+/// ```
+#[proc_macro]
+pub fn query_as(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input with checked_sql::typed);
+    TokenStream::from(checked_sql::expand(input))
+}
+
+#[derive(Default)]
+struct TableAttrs {
+    // custom table name instead of the default struct name + "s"
+    name: Option<String>,
+    // columns removed from the struct which should be dropped from the stored data
+    dropped: Vec<String>,
+    // fields of the secondary indexes, GlueSQL indexes the leading one
+    indexes: Vec<Vec<Ident>>,
+    // synced with the service workers and resolves conflicting writes with this policy
+    sync: Option<SyncPolicy>,
+    // fn(requester: &SyncRequester, row: &Self) -> bool which allows pulling and pushing rows
+    sync_authorize: Option<syn::Path>,
+}
+
+enum SyncPolicy {
+    ClientWins,
+    // field which keeps the time of the last update
+    UpdatedAt(String),
+    // fn(server: Option<Self>, client: Option<Self>) -> Option<Self>
+    Resolve(syn::Path),
 }
 
 struct Column {
+    vis: Visibility,
     field_name: Ident,
     field_name_str: String,
+    // name of the column in the table, same as the field name by default
+    column_name: String,
     full_type: Type,
     full_type_str: String,
     // type inside Option or Vec
@@ -63,14 +206,37 @@ struct Column {
     list: bool,
     // should be UNIQUE
     unique: bool,
+    // is the leading column of a secondary index
+    indexed: bool,
+    // words of the values are kept in the inverted index
+    searchable: bool,
+    // TableEnum stored as the text of its variant
+    enumerated: bool,
+    // compared and incremented by the updates to reject stale writes
+    version: bool,
+    // set to the current time by inserts
+    created_at: bool,
+    // set to the current time by inserts and updates
+    updated_at: bool,
+    // value of the missing field and DEFAULT of the column
+    default: Option<syn::Expr>,
     // requires serialization/deserialization
     serialized: bool,
+    // previous name of the column in the stored data
+    renamed_from: Option<String>,
+    // table which primary key is stored in this column
+    references: Option<syn::Path>,
+    // remove referencing rows along with the referenced one instead of restricting removal
+    on_delete_cascade: bool,
 }
 
 impl Column {
     fn from_row_transform(&self) -> FromRowTransform {
         match self.sql_type {
             Uuid => FromRowTransform::UuidFromU128,
+            Timestamp if self.utc() => FromRowTransform::UtcFromNaive,
+            Map => FromRowTransform::FromMap,
+            _ if self.enumerated => FromRowTransform::FromVariant,
             _ if self.serialized => FromRowTransform::Deserialize,
             _ => FromRowTransform::None,
         }
@@ -81,6 +247,11 @@ impl Column {
             Uuid => "Uuid",
             Text => "Str",
             Timestamp => "Timestamp",
+            Date => "Date",
+            Time => "Time",
+            Decimal => "Decimal",
+            Bytea => "Bytea",
+            Map => "Map",
             Boolean => "Bool",
             Uint128 => "U128",
             Uint64 => "U64",
@@ -97,10 +268,18 @@ impl Column {
             _ => "Str",
         }
     }
+
+    /// Is `DateTime<Utc>` stored as the naive timestamp
+    fn utc(&self) -> bool {
+        self.full_type_str.contains("DateTime<Utc>")
+    }
 }
 
 enum FromRowTransform {
     UuidFromU128,
+    UtcFromNaive,
+    FromMap,
+    FromVariant,
     Deserialize,
     None,
 }
@@ -109,19 +288,19 @@ fn ident(name: &str) -> Ident {
     Ident::new(name, Span::call_site())
 }
 
+/// Identifier of the column name, raw if it's a keyword
+fn column_ident(name: &str) -> Ident {
+    syn::parse_str(name).unwrap_or_else(|_| Ident::new_raw(name, Span::call_site()))
+}
+
 trait TypeProps {
-    fn impl_into_exprnode(&self) -> bool;
     fn int_or_smaller(&self) -> bool;
     fn integer(&self) -> bool;
     fn numeric(&self) -> bool;
     fn comparable(&self) -> bool;
-    fn quoted(&self) -> bool;
 }
 
 impl TypeProps for SqlType {
-    fn impl_into_exprnode(&self) -> bool {
-        matches!(self, Boolean | Int)
-    }
     fn int_or_smaller(&self) -> bool {
         matches!(self, Uint32 | Uint16 | Uint8 | Int | Int32 | Int16 | Int8)
     }
@@ -129,20 +308,14 @@ impl TypeProps for SqlType {
         self.int_or_smaller() || matches!(self, Uint128 | Uint64 | Int128)
     }
     fn numeric(&self) -> bool {
-        self.integer() || matches!(self, Float | Float32)
+        self.integer() || matches!(self, Float | Float32 | Decimal)
     }
     fn comparable(&self) -> bool {
         self.numeric() || matches!(self, Timestamp | Date | Time)
     }
-    fn quoted(&self) -> bool {
-        !self.impl_into_exprnode() && !self.numeric()
-    }
 }
 
 impl TypeProps for Column {
-    fn impl_into_exprnode(&self) -> bool {
-        self.sql_type.impl_into_exprnode()
-    }
     fn int_or_smaller(&self) -> bool {
         self.sql_type.int_or_smaller()
     }
@@ -155,14 +328,12 @@ impl TypeProps for Column {
     fn comparable(&self) -> bool {
         self.sql_type.comparable()
     }
-    fn quoted(&self) -> bool {
-        self.sql_type.quoted()
-    }
 }
 
 fn column_schema(col: &Column) -> proc_macro2::TokenStream {
     let Column {
         field_name_str,
+        column_name,
         full_type_str,
         sql_type,
         pkey,
@@ -170,14 +341,50 @@ fn column_schema(col: &Column) -> proc_macro2::TokenStream {
         list,
         optional,
         serialized,
+        searchable,
+        enumerated,
+        version,
+        created_at,
+        updated_at,
+        default,
+        full_type,
+        inner_type,
+        renamed_from,
+        references,
+        on_delete_cascade,
         ..
     } = col;
     let numeric = sql_type.numeric();
     let comparable = sql_type.comparable();
     let sql_type = sql_type.to_string();
+    let renamed_from = match renamed_from {
+        Some(name) => q!(Some(#name)),
+        None => q!(None),
+    };
+    let variants = match enumerated {
+        true => q!(<#inner_type as prest::TableEnum>::VARIANTS),
+        false => q!(&[]),
+    };
+    let timestamp = *created_at || *updated_at;
+    let default = match default {
+        _ if timestamp => q!(Some(|| Ok("NOW()".to_owned()))),
+        Some(expr) => {
+            let value = into_glue_expr::into_glue_expr(col, q!(value), false, false);
+            q!(Some(|| {
+                let value: #full_type = #expr;
+                prest::_sql_text(#value)
+            }))
+        }
+        None => q!(None),
+    };
+    let references = match references {
+        Some(path) => q!(Some(<#path as prest::Table>::TABLE_NAME)),
+        None => q!(None),
+    };
     q! {
         ColumnSchema {
-            name: #field_name_str,
+            name: #column_name,
+            field_name: #field_name_str,
             rust_type: #full_type_str,
             sql_type: #sql_type,
             unique: #unique,
@@ -185,8 +392,16 @@ fn column_schema(col: &Column) -> proc_macro2::TokenStream {
             list: #list,
             optional: #optional,
             serialized: #serialized,
+            searchable: #searchable,
+            variants: #variants,
+            version: #version,
+            timestamp: #timestamp,
+            default: #default,
             numeric: #numeric,
             comparable: #comparable,
+            renamed_from: #renamed_from,
+            references: #references,
+            on_delete_cascade: #on_delete_cascade,
         }
     }
 }
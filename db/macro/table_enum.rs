@@ -0,0 +1,59 @@
+use super::*;
+use proc_macro2::TokenStream;
+
+pub fn impl_table_enum(enum_ident: Ident, data: syn::DataEnum) -> TokenStream {
+    let enum_name = enum_ident.to_string();
+    let mut variants = vec![];
+    let mut to_variant_arms = vec![];
+    let mut from_variant_arms = vec![];
+    for variant in data.variants {
+        let ident = variant.ident;
+        let name = ident.to_string();
+        match variant.fields {
+            Fields::Unit => {
+                to_variant_arms.push(q!(Self::#ident => #name.to_owned()));
+                from_variant_arms.push(q!(#name => Ok(Self::#ident)));
+                variants.push(name);
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let prefix = format!("{name}(");
+                to_variant_arms.push(q!(Self::#ident(value) => format!("{}{value})", #prefix)));
+                from_variant_arms.push(q!(
+                    _ if variant.starts_with(#prefix) && variant.ends_with(')') => {
+                        let value = &variant[#prefix.len()..variant.len() - 1];
+                        match value.parse() {
+                            Ok(value) => Ok(Self::#ident(value)),
+                            Err(_) => Err(prest::e!("invalid value of the {variant} variant")),
+                        }
+                    }
+                ));
+                variants.push(format!("{name}(..)"));
+            }
+            _ => panic!(
+                "TableEnum supports only unit variants and variants with a single unnamed value"
+            ),
+        }
+    }
+
+    q! {
+        impl prest::TableEnum for #enum_ident {
+            const VARIANTS: &'static [&'static str] = &[#(#variants),*];
+
+            fn to_variant(&self) -> String {
+                match self {
+                    #(#to_variant_arms,)*
+                }
+            }
+
+            fn from_variant(variant: &str) -> prest::Result<Self> {
+                match variant {
+                    #(#from_variant_arms,)*
+                    _ => {
+                        let enum_name = #enum_name;
+                        Err(prest::e!("unknown variant {variant} of {enum_name}"))
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use super::*;
+use proc_macro2::TokenStream;
+use syn::meta::ParseNestedMeta;
+
+/// `prest::Validate` impl from the `#[validate(...)]` attributes of the fields
+pub fn impl_validate(ast: &DeriveInput) -> syn::Result<TokenStream> {
+    let Data::Struct(DataStruct { fields, .. }) = &ast.data else {
+        return Err(syn::Error::new_spanned(
+            &ast.ident,
+            "Validate can only be derived for structs",
+        ));
+    };
+    let mut checks = vec![];
+    for field in fields.iter().enumerate() {
+        checks.extend(field_checks(field)?);
+    }
+
+    let ident = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let body = match checks.is_empty() {
+        true => q!(Ok(())),
+        false => q! {
+            let mut errors = prest::ValidationErrors::default();
+            #(#checks)*
+            errors._into_result()
+        },
+    };
+    Ok(q! {
+        #[automatically_derived]
+        impl #impl_generics prest::Validate for #ident #ty_generics #where_clause {
+            fn validate(&self) -> prest::Result<(), prest::ValidationErrors> {
+                #body
+            }
+        }
+    })
+}
+
+fn field_checks((index, field): (usize, &Field)) -> syn::Result<Option<TokenStream>> {
+    let mut checks = vec![];
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("validate")) {
+        attr.parse_nested_meta(|meta| {
+            checks.push(check(&meta)?);
+            Ok(())
+        })?;
+    }
+    if checks.is_empty() {
+        return Ok(None);
+    }
+
+    let (name, member) = match &field.ident {
+        Some(ident) => (ident.to_string(), q!(#ident)),
+        None => (
+            index.to_string(),
+            syn::Index::from(index).into_token_stream(),
+        ),
+    };
+    let checks = checks.into_iter().map(|check| {
+        q! {
+            if let Err(message) = #check {
+                errors.add(#name, message);
+            }
+        }
+    });
+    // missing optional values are valid
+    Ok(Some(match optional(&field.ty) {
+        true => q!(if let Some(value) = &self.#member { #(#checks)* }),
+        false => q!({ let value = &self.#member; #(#checks)* }),
+    }))
+}
+
+fn check(meta: &ParseNestedMeta) -> syn::Result<TokenStream> {
+    if meta.path.is_ident("length") {
+        let (min, max) = bounds(meta)?;
+        Ok(q! {{
+            use prest::_ValidateLength;
+            prest::_validate_length(value._length(), #min, #max)
+        }})
+    } else if meta.path.is_ident("range") {
+        let (min, max) = bounds(meta)?;
+        Ok(q!(prest::_validate_range(value, #min, #max)))
+    } else if meta.path.is_ident("regex") {
+        let pattern: LitStr = meta.value()?.parse()?;
+        if let Err(e) = regex::Regex::new(&pattern.value()) {
+            return Err(syn::Error::new(
+                pattern.span(),
+                format!("invalid regex: {e}"),
+            ));
+        }
+        Ok(q! {{
+            static PATTERN: prest::Lazy<prest::_Regex> =
+                prest::Lazy::new(|| prest::_Regex::new(#pattern).expect("regex checked by the derive"));
+            prest::_validate_regex(value, &PATTERN)
+        }})
+    } else if meta.path.is_ident("email") {
+        Ok(q!(prest::_validate_email(value)))
+    } else if meta.path.is_ident("custom") {
+        let path: syn::ExprPath = meta.value()?.parse()?;
+        Ok(q!(#path(value)))
+    } else {
+        Err(meta.error("expected length, range, regex, email or custom validation"))
+    }
+}
+
+/// `min = ..` and `max = ..` as optional values
+fn bounds(meta: &ParseNestedMeta) -> syn::Result<(TokenStream, TokenStream)> {
+    let mut min = q!(None);
+    let mut max = q!(None);
+    meta.parse_nested_meta(|bound| {
+        let value: Expr = bound.value()?.parse()?;
+        if bound.path.is_ident("min") {
+            min = q!(Some(#value));
+        } else if bound.path.is_ident("max") {
+            max = q!(Some(#value));
+        } else {
+            return Err(bound.error("expected min or max"));
+        }
+        Ok(())
+    })?;
+    Ok((min, max))
+}
+
+fn optional(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn invalid_patterns_are_compile_errors() {
+        let ast: DeriveInput = syn::parse_quote! {
+            struct Signup {
+                #[validate(regex = "^[a-z+$")]
+                handle: String,
+            }
+        };
+        let error = impl_validate(&ast).expect_err("invalid pattern");
+        assert!(error.to_string().starts_with("invalid regex"));
+
+        let ast: DeriveInput = syn::parse_quote! {
+            struct Signup {
+                #[validate(regex = "^[a-z]+$")]
+                handle: String,
+            }
+        };
+        assert!(impl_validate(&ast).is_ok());
+    }
+}
@@ -0,0 +1,329 @@
+use crate::*;
+
+use futures::lock::{Mutex as AsyncMutex, OwnedMutexGuard};
+use gluesql::{
+    core::{
+        ast::ColumnDef,
+        data::{Key, Schema, Value},
+        error::Result as GlueResult,
+        store::{AlterTable, DataRow, RowIter, Store, StoreMut, Transaction},
+    },
+    gluesql_memory_storage::{Item, MemoryStorage as GlueMemoryStorage},
+    gluesql_shared_memory_storage::SharedMemoryStorage,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// GlueSQL shared memory storage with transactions which undo their writes on rollback
+#[doc(hidden)]
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    shared: SharedMemoryStorage,
+    lock: Arc<TxLock>,
+}
+
+#[derive(Debug, Default)]
+struct TxLock {
+    mutex: Arc<AsyncMutex<()>>,
+    /// guard of the running statement or transaction
+    guard: std::sync::Mutex<Option<OwnedMutexGuard<()>>>,
+    /// scope which holds the lock, 0 if none
+    owner: AtomicU64,
+    /// previous state of the data written by the running transaction
+    undo: std::sync::Mutex<Option<Vec<Undo>>>,
+}
+
+/// Previous state of the data changed by a write
+#[derive(Debug)]
+enum Undo {
+    Row {
+        table: String,
+        key: Key,
+        row: Option<DataRow>,
+    },
+    /// schema changes restore the whole table along with its metadata
+    Table {
+        name: String,
+        item: Option<Item>,
+        meta: Option<HashMap<String, Value>>,
+    },
+    IdCounter(i64),
+}
+
+impl Deref for MemoryStorage {
+    type Target = SharedMemoryStorage;
+    fn deref(&self) -> &Self::Target {
+        &self.shared
+    }
+}
+
+impl DerefMut for MemoryStorage {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.shared
+    }
+}
+
+impl MemoryStorage {
+    /// Statements inside of the transaction scope reuse its lock while others wait for it
+    async fn acquire(&self) -> bool {
+        let scope = tx_scope();
+        if scope != 0 && self.lock.owner.load(Ordering::SeqCst) == scope {
+            return false;
+        }
+        let guard = self.lock.mutex.clone().lock_owned().await;
+        *self.lock.guard.lock().expect("memory tx guard") = Some(guard);
+        self.lock.owner.store(scope, Ordering::SeqCst);
+        true
+    }
+
+    fn release(&self) {
+        self.lock.owner.store(0, Ordering::SeqCst);
+        self.lock.guard.lock().expect("memory tx guard").take();
+    }
+
+    /// Runs the future inside of a single transaction, changes are reverted if it fails
+    pub(crate) async fn transaction<T, F>(&self, fut: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        // nested transactions are merged into the outer one
+        let current = tx_scope();
+        if current != 0 && self.lock.owner.load(Ordering::SeqCst) == current {
+            return fut.await;
+        }
+        TxScoped::new(async move {
+            self.acquire().await;
+            *self.undo_log() = Some(vec![]);
+            // dropped futures shouldn't keep the lock or their changes
+            let _rollback = Rollback { storage: self };
+            let result = fut.await;
+            let undo = self.undo_log().take().unwrap_or_default();
+            if result.is_err() {
+                undo_writes(&mut *self.shared.database.write().await, undo);
+            }
+            result
+        })
+        .await
+    }
+
+    fn undo_log(&self) -> std::sync::MutexGuard<'_, Option<Vec<Undo>>> {
+        self.lock.undo.lock().expect("memory undo log")
+    }
+
+    /// Keeps the previous state of the data about to be written while a transaction runs
+    async fn record(&self, undo: impl FnOnce(&GlueMemoryStorage) -> Vec<Undo>) {
+        if self.undo_log().is_none() {
+            return;
+        }
+        let entries = undo(&*self.shared.database.read().await);
+        if let Some(log) = self.undo_log().as_mut() {
+            log.extend(entries);
+        }
+    }
+
+    async fn record_rows(&self, table: &str, keys: impl Iterator<Item = Key>) {
+        self.record(|db| {
+            let item = db.items.get(table);
+            keys.map(|key| Undo::Row {
+                table: table.to_owned(),
+                row: item.and_then(|item| item.rows.get(&key).cloned()),
+                key,
+            })
+            .collect()
+        })
+        .await
+    }
+
+    async fn record_tables(&self, tables: &[&str]) {
+        self.record(|db| {
+            let undo = |name: &&str| Undo::Table {
+                name: name.to_string(),
+                item: db.items.get(*name).cloned(),
+                meta: db.metadata.get(*name).cloned(),
+            };
+            tables.iter().map(undo).collect()
+        })
+        .await
+    }
+
+    /// Last generated key of the rows in tables without primary keys
+    #[cfg(any(sw, test))]
+    pub(crate) async fn id_counter(&self) -> i64 {
+        self.shared.database.read().await.id_counter
+    }
+
+    /// Accesses the data once the running transaction is finished
+    #[cfg(any(sw, test))]
+    pub(crate) async fn committed<T>(&self, f: impl FnOnce(&mut GlueMemoryStorage) -> T) -> T {
+        let acquired = self.acquire().await;
+        let result = f(&mut *self.shared.database.write().await);
+        if acquired {
+            self.release();
+        }
+        result
+    }
+}
+
+/// Releases the lock of the finished transaction and undoes the writes if its future was dropped
+struct Rollback<'a> {
+    storage: &'a MemoryStorage,
+}
+
+impl Drop for Rollback<'_> {
+    fn drop(&mut self) {
+        if let Some(undo) = self.storage.undo_log().take() {
+            // nothing else accesses the data while the lock is held
+            match self.storage.shared.database.try_write() {
+                Ok(mut database) => undo_writes(&mut database, undo),
+                Err(_) => warn!(target: "db", "dropped transaction's changes weren't rolled back"),
+            }
+        }
+        self.storage.release();
+    }
+}
+
+/// Restores the recorded state from the latest write to the earliest one
+fn undo_writes(db: &mut GlueMemoryStorage, undo: Vec<Undo>) {
+    for entry in undo.into_iter().rev() {
+        match entry {
+            Undo::Row { table, key, row } => {
+                let Some(item) = db.items.get_mut(&table) else {
+                    continue;
+                };
+                match row {
+                    Some(row) => item.rows.insert(key, row),
+                    None => item.rows.remove(&key),
+                };
+            }
+            Undo::Table { name, item, meta } => {
+                match item {
+                    Some(item) => db.items.insert(name.clone(), item),
+                    None => db.items.remove(&name),
+                };
+                match meta {
+                    Some(meta) => db.metadata.insert(name, meta),
+                    None => db.metadata.remove(&name),
+                };
+            }
+            Undo::IdCounter(id_counter) => db.id_counter = id_counter,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Store for MemoryStorage {
+    async fn fetch_schema(&self, table_name: &str) -> GlueResult<Option<Schema>> {
+        self.shared.fetch_schema(table_name).await
+    }
+
+    async fn fetch_all_schemas(&self) -> GlueResult<Vec<Schema>> {
+        self.shared.fetch_all_schemas().await
+    }
+
+    async fn fetch_data(&self, table_name: &str, key: &Key) -> GlueResult<Option<DataRow>> {
+        self.shared.fetch_data(table_name, key).await
+    }
+
+    async fn scan_data(&self, table_name: &str) -> GlueResult<RowIter> {
+        self.shared.scan_data(table_name).await
+    }
+}
+
+#[async_trait(?Send)]
+impl StoreMut for MemoryStorage {
+    async fn insert_schema(&mut self, schema: &Schema) -> GlueResult<()> {
+        self.record_tables(&[&schema.table_name]).await;
+        self.shared.insert_schema(schema).await
+    }
+
+    async fn delete_schema(&mut self, table_name: &str) -> GlueResult<()> {
+        self.record_tables(&[table_name]).await;
+        self.shared.delete_schema(table_name).await
+    }
+
+    async fn append_data(&mut self, table_name: &str, rows: Vec<DataRow>) -> GlueResult<()> {
+        // appended rows get the following keys of the counter
+        self.record(|db| {
+            let keys = (1..=rows.len() as i64).map(|offset| Undo::Row {
+                table: table_name.to_owned(),
+                key: Key::I64(db.id_counter + offset),
+                row: None,
+            });
+            std::iter::once(Undo::IdCounter(db.id_counter))
+                .chain(keys)
+                .collect()
+        })
+        .await;
+        self.shared.append_data(table_name, rows).await
+    }
+
+    async fn insert_data(&mut self, table_name: &str, rows: Vec<(Key, DataRow)>) -> GlueResult<()> {
+        let keys = rows.iter().map(|(key, _)| key.clone());
+        self.record_rows(table_name, keys).await;
+        self.shared.insert_data(table_name, rows).await
+    }
+
+    async fn delete_data(&mut self, table_name: &str, keys: Vec<Key>) -> GlueResult<()> {
+        self.record_rows(table_name, keys.iter().cloned()).await;
+        self.shared.delete_data(table_name, keys).await
+    }
+}
+
+#[async_trait(?Send)]
+impl AlterTable for MemoryStorage {
+    async fn rename_schema(&mut self, table_name: &str, new_table_name: &str) -> GlueResult<()> {
+        self.record_tables(&[table_name, new_table_name]).await;
+        self.shared.rename_schema(table_name, new_table_name).await
+    }
+
+    async fn rename_column(
+        &mut self,
+        table_name: &str,
+        old_column_name: &str,
+        new_column_name: &str,
+    ) -> GlueResult<()> {
+        self.record_tables(&[table_name]).await;
+        self.shared
+            .rename_column(table_name, old_column_name, new_column_name)
+            .await
+    }
+
+    async fn add_column(&mut self, table_name: &str, column_def: &ColumnDef) -> GlueResult<()> {
+        self.record_tables(&[table_name]).await;
+        self.shared.add_column(table_name, column_def).await
+    }
+
+    async fn drop_column(
+        &mut self,
+        table_name: &str,
+        column_name: &str,
+        if_exists: bool,
+    ) -> GlueResult<()> {
+        self.record_tables(&[table_name]).await;
+        self.shared
+            .drop_column(table_name, column_name, if_exists)
+            .await
+    }
+}
+
+#[async_trait(?Send)]
+impl Transaction for MemoryStorage {
+    async fn begin(&mut self, _autocommit: bool) -> GlueResult<bool> {
+        // statements of a transaction shouldn't commit it
+        Ok(self.acquire().await)
+    }
+
+    async fn rollback(&mut self) -> GlueResult<()> {
+        self.release();
+        Ok(())
+    }
+
+    async fn commit(&mut self) -> GlueResult<()> {
+        self.release();
+        Ok(())
+    }
+}
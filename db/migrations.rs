@@ -0,0 +1,109 @@
+use crate::*;
+
+use std::{future::Future, pin::Pin};
+
+/// Hand-written migration step, usually defined with the [`migration`] attribute macro which also registers it.
+/// Pending steps run in ascending versions order during [`Db::migrate`], each inside of its own transaction
+#[derive(Clone, Copy)]
+pub struct Migration {
+    pub version: u64,
+    pub name: &'static str,
+    pub run: fn() -> Pin<Box<dyn Future<Output = Result>>>,
+}
+
+/// Describes applied [`Migration`]s
+#[derive(Debug, Table, Clone, Serialize, Deserialize)]
+pub struct MigrationRecord {
+    pub version: u64,
+    pub name: String,
+    pub applied_at: NaiveDateTime,
+}
+
+inventory::collect!(Migration);
+
+impl Db {
+    /// Runs registered migrations which weren't applied yet
+    pub(crate) async fn run_pending_migrations(&self) -> Result {
+        let mut migrations: Vec<Migration> = inventory::iter::<Migration>().copied().collect();
+        migrations.sort_by_key(|m| m.version);
+
+        for pair in migrations.windows(2) {
+            if pair[0].version == pair[1].version {
+                let (version, first, second) = (pair[0].version, pair[0].name, pair[1].name);
+                return Err(e!(
+                    "migrations {first} and {second} share the same version {version}"
+                ));
+            }
+        }
+
+        let applied = MigrationRecord::select_all().await?;
+        for migration in migrations {
+            let Migration { version, name, run } = migration;
+            if let Some(record) = applied.iter().find(|r| r.version == version) {
+                if record.name != name {
+                    warn!(target: "db", "migration {version} was applied as {} but now is named {name}", record.name);
+                }
+                continue;
+            }
+
+            info!(target: "db", "applying migration {version}: {name}");
+            self.in_transaction(async move {
+                run().await?;
+                let mut record = MigrationRecord {
+                    version,
+                    name: name.to_owned(),
+                    applied_at: Utc::now().naive_utc(),
+                };
+                record.save().await?;
+                OK
+            })
+            .await
+            .map_err(|e| e!("migration {version} ({name}) failed: {e}"))?;
+        }
+
+        OK
+    }
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[derive(Table, Debug, Clone, Serialize, Deserialize)]
+    #[table(name = "test_backfilled")]
+    struct Backfilled {
+        id: Uuid,
+        source: String,
+    }
+
+    // migrations are collected from any module
+    mod nested {
+        use super::*;
+
+        #[migration(1)]
+        async fn backfill() -> Result {
+            let id = Uuid::now_v7();
+            let source = "migration".to_owned();
+            Backfilled { id, source }.save().await?;
+            OK
+        }
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let tables = [MigrationRecord::schema(), Backfilled::schema()];
+        test_db(&tables, async {
+            DB.run_pending_migrations().await?;
+            DB.run_pending_migrations().await?;
+
+            assert_eq!(Backfilled::count().await?, 1);
+            let applied = MigrationRecord::select_all().await?;
+            assert_eq!(applied.len(), 1);
+            assert_eq!(
+                (applied[0].version, applied[0].name.as_str()),
+                (1, "backfill")
+            );
+            OK
+        });
+    }
+}
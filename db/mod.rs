@@ -1,4 +1,42 @@
+mod aggregate;
+pub use aggregate::*;
+#[cfg(host)]
+mod backup;
+#[cfg(host)]
+pub(crate) use backup::backups_dir;
+mod changes;
+pub use changes::{Change, Changes};
+mod checked_sql;
+pub use checked_sql::*;
+mod filter;
+pub use filter::*;
 mod gluesql_traits;
+mod history;
+pub use history::*;
+mod memory;
+pub use memory::MemoryStorage;
+mod migrations;
+pub use migrations::*;
+mod schema_diff;
+mod search;
+#[cfg(host)]
+mod storage;
+#[cfg(host)]
+pub use storage::*;
+#[cfg(host)]
+pub(crate) use search::tokenize;
+mod sync;
+#[cfg(host)]
+pub(crate) use sync::routes as sync_routes;
+#[cfg(sw)]
+pub(crate) use sync::{SyncPull, SyncRejected, SyncWrite};
+pub use sync::{SyncRequester, _sync_pull, _sync_push};
+mod transaction;
+pub use transaction::*;
+#[cfg(any(sw, test))]
+mod write_through;
+#[cfg(sw)]
+pub(crate) use write_through::{TableStore, WriteThroughStorage};
 
 mod table;
 pub use table::*;
@@ -6,8 +44,10 @@ pub use table::*;
 use crate::*;
 
 use gluesql::{
-    core::{ast_builder::Build as BuildSQL, store::Transaction},
-    gluesql_shared_memory_storage::SharedMemoryStorage as MemoryStorage,
+    core::{
+        ast_builder::Build as BuildSQL,
+        store::{Store, Transaction},
+    },
     prelude::Glue,
 };
 
@@ -17,8 +57,17 @@ pub mod sql {
     pub use gluesql::core::ast_builder::*;
     pub use gluesql::core::data::Value;
     pub use gluesql::core::executor::Payload;
+
+    /// Typed `MAP` literal of the json object like the [`date`] and [`timestamp`] ones
+    pub fn map<'a, T: Into<std::borrow::Cow<'a, str>>>(json: T) -> ExprNode<'a> {
+        ExprNode::TypedString {
+            data_type: DataType::Map,
+            value: json.into(),
+        }
+    }
 }
-pub use prest_db_macro::Table;
+pub use prest_db_macro::{query_as, sql, Table, TableEnum, Validate};
+pub use rust_decimal::Decimal;
 
 pub(crate) const DB_DIRECTORY_NAME: &str = "db";
 
@@ -35,7 +84,8 @@ state!(DB: Db = {
         let storage = if APP_CONFIG.persistent {
             let mut db_path = APP_CONFIG.data_dir.clone();
             db_path.push(DB_DIRECTORY_NAME);
-            let storage = PersistentStorage::new(db_path).expect("Database storage should initialize");
+            let storage = PersistentStorage::new(db_path, APP_CONFIG.history_retention).expect("Database storage should initialize");
+            storage::schedule_gc();
             Persistent(storage)
         } else {
             Memory(MemoryStorage::default())
@@ -43,7 +93,7 @@ state!(DB: Db = {
 
         use crate::host::analytics::RouteStat;
         #[allow(unused_mut)]
-        let mut internal_schemas = vec![ScheduledJobRecord::schema(), RouteStat::schema(), SystemStat::schema()];
+        let mut internal_schemas = vec![ScheduledJobRecord::schema(), MigrationRecord::schema(), RouteStat::schema(), SystemStat::schema(), GcRun::schema()];
         #[cfg(feature = "auth")] {
             internal_schemas.push(crate::host::auth::SessionRow::schema());
             internal_schemas.push(crate::host::auth::User::schema());
@@ -57,8 +107,8 @@ state!(DB: Db = {
     }
     #[cfg(sw)] {
         Db {
-            storage: Memory(MemoryStorage::default()),
-            internal_schemas: Arc::new(vec![]),
+            storage: Persistent(PersistentStorage::new(IdbTables)),
+            internal_schemas: Arc::new(vec![MigrationRecord::schema()]),
             custom_schemas: Default::default(),
         }
     }
@@ -69,60 +119,171 @@ impl Db {
         self.storage.clone()
     }
     pub fn _register_table(&self, schema: TableSchema) {
-        self.custom_schemas.write().unwrap().push(schema);
+        let mut schemas = self.custom_schemas.write().unwrap();
+        // same struct can be defined several times under different cfgs
+        if !schemas.iter().any(|s| s.name() == schema.name()) {
+            schemas.push(schema);
+        }
     }
     pub(crate) fn custom_tables(&self) -> Vec<TableSchema> {
         self.custom_schemas.read().unwrap().clone()
     }
-    pub async fn migrate(&self) -> Result {
+    pub(crate) fn all_tables(&self) -> Vec<TableSchema> {
         let mut all_tables = (*self.internal_schemas).clone();
-        all_tables.extend(self.custom_tables().into_iter());
+        all_tables.extend(self.custom_tables());
+        all_tables
+    }
+    /// Checks and cascades removal of the `table` row with the `key` to the referencing rows
+    #[doc(hidden)]
+    pub async fn _remove_references(&self, table: &str, key: sql::ExprNode<'static>) -> Result {
+        let mut cascades = vec![];
+        for schema in self.all_tables() {
+            let referencing = schema
+                .columns()
+                .iter()
+                .filter(|c| c.references == Some(table));
+            for column in referencing {
+                let filter = sql::col(column.name).eq(key.clone().cast(column.sql_type));
+                if column.on_delete_cascade {
+                    cascades.push((schema, filter));
+                    continue;
+                }
+                let rows = sql::table(schema.name())
+                    .select()
+                    .filter(filter)
+                    .limit(1)
+                    .rows()
+                    .await?;
+                if !rows.is_empty() {
+                    return Err(Error::ForeignKeyViolation {
+                        table: schema.name().to_owned(),
+                        column: column.name.to_owned(),
+                    });
+                }
+            }
+        }
+        for (schema, filter) in cascades {
+            schema.remove_where(filter).await?;
+        }
+        OK
+    }
+    /// Creates missing tables, applies non-destructive changes to the existing ones and runs pending [`Migration`]s.
+    /// Columns listed in `#[table(dropped(...))]` are removed only after the migrations so they can still read them
+    pub async fn migrate(&self) -> Result {
+        let all_tables = self.all_tables();
+
+        let mut migrations = vec![];
+        let mut issues = vec![];
+        for table in all_tables {
+            let stored = self.storage().fetch_schema(table.name()).await?;
+            let stored_indexes = stored
+                .as_ref()
+                .map(|s| s.indexes.clone())
+                .unwrap_or_default();
+            let mut changes = match stored.and_then(|schema| schema.column_defs) {
+                None => {
+                    Self::create_if_not_exists(table).await?;
+                    vec![]
+                }
+                Some(column_defs) => match schema_diff::diff(table, &column_defs) {
+                    Ok(changes) => changes,
+                    Err(table_issues) => {
+                        issues.extend(table_issues);
+                        continue;
+                    }
+                },
+            };
+            changes.extend(self.index_changes(table, &stored_indexes));
+            migrations.push((table, changes));
+        }
+
+        if !issues.is_empty() {
+            let report = issues.join("\n - ");
+            error!(target: "db", "schema can't be migrated automatically without losing data:\n - {report}");
+            let count = issues.len();
+            return Err(e!(
+                "DB schema migration refused due to {count} destructive changes"
+            ));
+        }
 
-        // let names: Vec<&'static str> = all_tables.iter().map(|t| t.name()).collect();
+        // all the changes are applied or none of them
+        let mut drops = vec![];
+        self.in_transaction(async {
+            for (table, changes) in migrations {
+                for change in changes {
+                    if let schema_diff::SchemaChange::Drop(_) = change {
+                        drops.push((table, change));
+                        continue;
+                    }
+                    info!(target: "db", "migrating {}: {change}", table.name());
+                    change.apply(table.name()).await?;
+                }
+            }
+            OK
+        })
+        .await?;
 
-        // let DbStorage::Persistent(PersistentStorage { state }) = &DB.storage() else {
-        //     return Ok(());
-        // };
+        self.run_pending_migrations().await?;
 
-        // for name in names {
-        //     let tree = state
-        //         .db
-        //         .read()
-        //         .await
-        //         .tree
-        //         .transaction(move |tx_tree| sled::fetch_schema(tx_tree, name));
-        //     info!("{:?}", tree);
-        // }
+        self.in_transaction(async {
+            for (table, change) in drops {
+                info!(target: "db", "migrating {}: {change}", table.name());
+                change.apply(table.name()).await?;
+            }
+            OK
+        })
+        .await?;
 
-        // naive migration
-        for table in all_tables {
-            Self::create_if_not_exists(table).await?;
+        #[cfg(host)]
+        ScheduledJobRecord::fill_durations().await?;
+
+        let mut unknown_variants = vec![];
+        for table in self.all_tables() {
+            unknown_variants.extend(schema_diff::migrate_variants(table).await?);
+        }
+        if !unknown_variants.is_empty() {
+            let report = unknown_variants.join("\n - ");
+            error!(target: "db", "stored values aren't variants of their enum columns:\n - {report}");
+            let count = unknown_variants.len();
+            return Err(e!("DB schema migration refused due to {count} unknown enum variants"));
+        }
+
+        #[cfg(host)]
+        if let Persistent(storage) = self.storage() {
+            for table in self.all_tables() {
+                let searchable = table.columns().iter().enumerate();
+                let columns: Vec<usize> = searchable
+                    .filter(|(_, column)| column.searchable)
+                    .map(|(i, _)| i)
+                    .collect();
+                if !columns.is_empty() {
+                    storage.register_search(table.name(), columns).await?;
+                }
+            }
         }
 
         Ok(())
     }
 
+    fn index_changes(
+        &self,
+        table: TableSchema,
+        stored: &[gluesql::core::data::SchemaIndex],
+    ) -> Vec<schema_diff::SchemaChange> {
+        // GlueSQL memory storage doesn't implement indexes
+        match cfg!(host) && matches!(self.storage, Persistent(_)) {
+            true => schema_diff::index_changes(table, stored),
+            false => vec![],
+        }
+    }
+
     async fn create_if_not_exists(table: TableSchema) -> Result {
+        // GlueSQL supports only single-column primary keys, composite ones are kept unique by the storage
+        let single_pkey = table.columns().iter().filter(|c| c.pkey).count() == 1;
         let mut stmt = sql::table(table.name()).create_table_if_not_exists();
-        for ColumnSchema {
-            name,
-            sql_type,
-            unique,
-            pkey,
-            list,
-            optional,
-            ..
-        } in table.columns()
-        {
-            let col = if *list {
-                format!("{name} LIST")
-            } else {
-                let unique = if !*pkey && *unique { " UNIQUE" } else { "" };
-                let pkey = if *pkey { " PRIMARY KEY" } else { "" };
-                let optional = if *optional { "" } else { " NOT NULL" };
-                format!("{name} {sql_type}{pkey}{unique}{optional}")
-            };
-            stmt = stmt.add_column(col.as_str());
+        for column in table.columns() {
+            let column_def = schema_diff::column_def(column, single_pkey)?;
+            stmt = stmt.add_column(column_def.as_str());
         }
         stmt.exec().await?;
         Ok(())
@@ -149,7 +310,9 @@ pub trait DbAccess {
 impl DbAccess for Lazy<Db> {
     async fn query(&self, query: &str) -> Result<Vec<sql::Payload>> {
         // temporary workaround until Glue futures implement Send https://github.com/gluesql/gluesql/issues/1265
-        let payload = await_blocking(async move { Glue::new(DB.storage()).execute(query).await })?;
+        let payload = await_blocking(statement_scope(async move {
+            Glue::new(DB.storage()).execute(query).await
+        }))?;
         Ok(payload)
     }
 
@@ -177,6 +340,57 @@ impl DbAccess for Lazy<Db> {
     }
 }
 
+/// Typed [`Error`]s of the constraints violated by the write, others are kept as they are
+fn constraint_error(error: gluesql::core::error::Error, statement: &sql::Statement) -> Error {
+    use gluesql::core::{
+        data::ValueError,
+        error::Error as GlueError,
+        executor::{InsertError, ValidateError},
+    };
+
+    let table = match statement {
+        sql::Statement::Insert { table_name, .. } | sql::Statement::Update { table_name, .. } => {
+            table_name.clone()
+        }
+        _ => return error.into(),
+    };
+    match error {
+        GlueError::Validate(ValidateError::DuplicateEntryOnUniqueField(_, column)) => {
+            Error::Conflict { table, column }
+        }
+        GlueError::Validate(ValidateError::DuplicateEntryOnPrimaryKeyField(_)) => {
+            let schema = DB.all_tables().into_iter().find(|s| s.name() == table);
+            // composite keys are reported as the list of their columns
+            let pkeys = schema.map(|s| s.columns().iter().filter(|c| c.pkey).map(|c| c.name));
+            let column = pkeys.map(|names| names.collect::<Vec<_>>().join(", "));
+            Error::Conflict {
+                table,
+                column: column.unwrap_or_default(),
+            }
+        }
+        GlueError::Value(ValueError::NullValueOnNotNullField)
+        | GlueError::Insert(InsertError::LackOfRequiredColumn(_)) => {
+            Error::NotNullViolation { table }
+        }
+        error => error.into(),
+    }
+}
+
+/// Plans and executes the statement with [`DB`]
+pub(crate) async fn execute(statement: sql::Statement) -> Result<sql::Payload> {
+    // temporary workaround until Glue futures implement Send https://github.com/gluesql/gluesql/issues/1265
+    let payload = await_blocking(statement_scope(async move {
+        let storage = DB.storage();
+        // built statements skip the planner which picks primary key and index lookups
+        let statement = gluesql::core::plan::plan(&storage, statement).await?;
+        Glue::new(storage)
+            .execute_stmt(&statement)
+            .await
+            .map_err(|e| constraint_error(e, &statement))
+    }))?;
+    Ok(payload)
+}
+
 /// Simplified interface for queries to run with [`DB`]
 #[async_trait]
 pub trait DbExecutable {
@@ -188,11 +402,7 @@ pub trait DbExecutable {
 #[async_trait]
 impl<Q: BuildSQL + Send> DbExecutable for Q {
     async fn exec(self) -> Result<sql::Payload> {
-        let statement = self.build()?;
-        // temporary workaround until Glue futures implement Send https://github.com/gluesql/gluesql/issues/1265
-        let payload =
-            await_blocking(async move { Glue::new(DB.storage()).execute_stmt(&statement).await })?;
-        Ok(payload)
+        execute(self.build()?).await
     }
 
     async fn rows(self) -> Result<Vec<Vec<sql::Value>>> {
@@ -213,3 +423,189 @@ impl<Q: BuildSQL + Send> DbExecutable for Q {
         rows.into_iter().map(T::from_row).collect()
     }
 }
+
+/// Runs the test against the in-memory [`DB`] with the tables created
+#[cfg(all(test, host))]
+pub(crate) fn test_db(tables: &[TableSchema], test: impl std::future::Future<Output = Result>) {
+    static CONFIG: std::sync::Once = std::sync::Once::new();
+    CONFIG.call_once(|| {
+        APP_CONFIG._init(env!("CARGO_MANIFEST_DIR"), "prest-tests", "0.0.0", false, None, 60)
+    });
+    // queries block in place which requires the multi-threaded runtime
+    let runtime = tokio::runtime::Runtime::new().expect("test runtime should start");
+    let result = runtime.block_on(async {
+        for table in tables {
+            DB._register_table(*table);
+            Db::create_if_not_exists(*table).await?;
+        }
+        test.await
+    });
+    result.expect("test should succeed");
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_constrained_accounts")]
+    struct ConstrainedAccount {
+        id: u32,
+        #[unique_column]
+        email: String,
+        name: String,
+    }
+
+    fn account(id: u32, email: &str) -> ConstrainedAccount {
+        ConstrainedAccount {
+            id,
+            email: email.to_owned(),
+            name: "name".to_owned(),
+        }
+    }
+
+    fn status(error: Error) -> StatusCode {
+        error.into_response().status()
+    }
+
+    #[test]
+    fn violated_constraints_return_typed_errors() {
+        test_db(&[ConstrainedAccount::schema()], async {
+            account(1, "a@example.com").insert_self().await?;
+
+            let Err(error) = account(1, "b@example.com").insert_self().await else {
+                panic!("expected a primary key conflict")
+            };
+            assert!(matches!(&error, Error::Conflict { column, .. } if column == "id"));
+            assert_eq!(status(error), StatusCode::CONFLICT);
+
+            let Err(error) = account(2, "a@example.com").insert_self().await else {
+                panic!("expected a unique column conflict")
+            };
+            assert!(matches!(&error, Error::Conflict { column, .. } if column == "email"));
+
+            let row = vec![sql::num(3), sql::text("c@example.com"), sql::null()];
+            let Err(error) = ConstrainedAccount::insert().values(vec![row]).exec().await else {
+                panic!("expected a not null violation")
+            };
+            let table = "test_constrained_accounts".to_owned();
+            assert!(matches!(&error, Error::NotNullViolation { table: t } if *t == table));
+            assert_eq!(status(error), StatusCode::UNPROCESSABLE_ENTITY);
+
+            let stored = ConstrainedAccount::select_all().await?;
+            assert_eq!(stored, vec![account(1, "a@example.com")]);
+            OK
+        })
+    }
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_indexed_events", index(kind, day))]
+    struct IndexedEvent {
+        id: u32,
+        #[index_column]
+        owner: String,
+        kind: String,
+        day: u16,
+    }
+
+    #[test]
+    fn indexes_are_created_and_used_by_lookups() {
+        test_db(&[IndexedEvent::schema()], async {
+            let path = std::env::temp_dir().join(format!("prest-index-test-{}", Uuid::now_v7()));
+            let db = Db {
+                storage: Persistent(PersistentStorage::new(path, std::time::Duration::ZERO)?),
+                internal_schemas: Default::default(),
+                custom_schemas: Default::default(),
+            };
+            let storage = db.storage();
+            let mut glue = Glue::new(storage.clone());
+            let table = IndexedEvent::schema();
+            let mut create = sql::table(table.name()).create_table();
+            for column in table.columns() {
+                create = create.add_column(schema_diff::column_def(column, true)?.as_str());
+            }
+            glue.execute_stmt(&create.build()?).await?;
+            let values = "(1, 'a', 'x', 1), (2, 'a', 'x', 2), (3, 'b', 'x', 1)";
+            glue.execute(format!("INSERT INTO {} VALUES {values}", table.name()))
+                .await?;
+
+            // composite indexes are created on their leading column
+            let changes = db.index_changes(table, &[]);
+            let created: Vec<String> = changes.iter().map(ToString::to_string).collect();
+            assert_eq!(
+                created,
+                [
+                    "create index test_indexed_events_owner on owner",
+                    "create index test_indexed_events_kind_day on kind"
+                ]
+            );
+            assert!(DB.index_changes(table, &[]).is_empty());
+            for change in changes {
+                let schema_diff::SchemaChange::CreateIndex { name, column } = change else {
+                    unreachable!()
+                };
+                let create_index = sql::table(table.name()).create_index(name, column);
+                glue.execute_stmt(&create_index.build()?).await?;
+            }
+            let stored = storage.fetch_schema(table.name()).await?.unwrap().indexes;
+            assert!(db.index_changes(table, &stored).is_empty());
+
+            // same filters as the generated select_by_owner and select_by_kind_and_day
+            let lookups = [
+                (
+                    IndexedEvent::cols.owner._eq_ref(&"a".to_owned()),
+                    "test_indexed_events_owner",
+                    2,
+                ),
+                (
+                    IndexedEvent::cols
+                        .kind
+                        ._eq_ref(&"x".to_owned())
+                        .and(IndexedEvent::cols.day._eq_ref(&1)),
+                    "test_indexed_events_kind_day",
+                    2,
+                ),
+            ];
+            for (filter, index, count) in lookups {
+                let select = IndexedEvent::select().filter(filter.into_expr()?).build()?;
+                let planned = gluesql::core::plan::plan(&storage, select).await?;
+                let sql::Statement::Query(query) = &planned else {
+                    unreachable!()
+                };
+                let sql::SetExpr::Select(select) = &query.body else {
+                    unreachable!()
+                };
+                let sql::TableFactor::Table {
+                    index: Some(sql::IndexItem::NonClustered { name, .. }),
+                    ..
+                } = &select.from.relation
+                else {
+                    panic!("{index} isn't used")
+                };
+                assert_eq!(name, index);
+                let sql::Payload::Select { rows, .. } = glue.execute_stmt(&planned).await? else {
+                    unreachable!()
+                };
+                assert_eq!(rows.len(), count);
+            }
+
+            // composite lookups filter the matches of the leading column by the rest of them
+            for (id, owner, day) in [(1, "a", 1), (2, "a", 2), (3, "b", 1)] {
+                let (owner, kind) = (owner.to_owned(), "x".to_owned());
+                IndexedEvent {
+                    id,
+                    owner,
+                    kind,
+                    day,
+                }
+                .insert_self()
+                .await?;
+            }
+            let found = IndexedEvent::select_by_kind_and_day(&"x".to_owned(), &1).await?;
+            let mut ids: Vec<u32> = found.iter().map(|event| event.id).collect();
+            ids.sort();
+            assert_eq!(ids, [1, 3]);
+            OK
+        })
+    }
+}
@@ -0,0 +1,715 @@
+use crate::*;
+
+use gluesql::core::{
+    ast::{ColumnDef, ColumnUniqueOption, DataType, Expr},
+    data::{Key, SchemaIndex},
+    store::{DataRow, Store, StoreMut},
+};
+
+/// Operation required to bring the stored table in line with the derived [`TableSchema`]
+#[derive(Debug)]
+pub(crate) enum SchemaChange {
+    Rename {
+        from: String,
+        to: &'static str,
+    },
+    Drop(String),
+    Add(String),
+    /// required column which allows nulls now, stored values stay the same
+    MakeOptional(&'static str),
+    /// column which was stored in another format, see [`converted_type`]
+    Convert {
+        column: &'static str,
+        from: String,
+        to: DataType,
+        optional: bool,
+    },
+    CreateIndex {
+        name: &'static str,
+        column: &'static str,
+    },
+    DropIndex(String),
+}
+
+impl SchemaChange {
+    pub(crate) async fn apply(self, table: &str) -> Result {
+        let alter = sql::table(table).alter_table();
+        match self {
+            SchemaChange::Rename { from, to } => alter.rename_column(&from, to).exec().await?,
+            SchemaChange::Drop(column) => alter.drop_column(&column).exec().await?,
+            SchemaChange::Add(column_def) => alter.add_column(column_def.as_str()).exec().await?,
+            SchemaChange::MakeOptional(column) => {
+                rewrite_column(table, column, |def| def.nullable = true, Ok).await?;
+                return OK;
+            }
+            SchemaChange::Convert {
+                column,
+                to,
+                optional,
+                ..
+            } => {
+                let update_def = |def: &mut ColumnDef| {
+                    def.data_type = to.clone();
+                    def.nullable = optional;
+                };
+                let convert = |value| convert_value(value, &to, optional);
+                rewrite_column(table, column, update_def, convert).await?;
+                return OK;
+            }
+            SchemaChange::CreateIndex { name, column } => {
+                sql::table(table).create_index(name, column).exec().await?
+            }
+            SchemaChange::DropIndex(name) => sql::table(table).drop_index(&name).exec().await?,
+        };
+        OK
+    }
+}
+
+impl std::fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaChange::Rename { from, to } => write!(f, "rename column {from} to {to}"),
+            SchemaChange::Drop(column) => write!(f, "drop column {column}"),
+            SchemaChange::Add(column_def) => write!(f, "add column {column_def}"),
+            SchemaChange::MakeOptional(column) => write!(f, "make column {column} optional"),
+            SchemaChange::Convert {
+                column, from, to, ..
+            } => {
+                write!(f, "convert column {column} from {from} to {to}")
+            }
+            SchemaChange::CreateIndex { name, column } => {
+                write!(f, "create index {name} on {column}")
+            }
+            SchemaChange::DropIndex(name) => write!(f, "drop index {name}"),
+        }
+    }
+}
+
+/// Compares stored column definitions with the derived ones and returns either the changes
+/// that can be applied without losing data or the list of the destructive ones
+pub(crate) fn diff(
+    table: TableSchema,
+    stored: &[ColumnDef],
+) -> std::result::Result<Vec<SchemaChange>, Vec<String>> {
+    let name = table.name();
+    let single_pkey = table.columns().iter().filter(|c| c.pkey).count() == 1;
+
+    let mut renames = vec![];
+    let mut alters = vec![];
+    let mut drops = vec![];
+    let mut adds = vec![];
+    let mut issues = vec![];
+
+    // stored column names as they will be after applying the changes
+    let mut resulting: Vec<String> = stored.iter().map(|c| c.name.clone()).collect();
+
+    for column in table.columns() {
+        let stored_column = stored.iter().find(|c| c.name == column.name);
+        let renamed_column = column
+            .renamed_from
+            .and_then(|old| stored.iter().find(|c| c.name == old));
+
+        if let Some(stored_column) = stored_column.or(renamed_column) {
+            issues.extend(
+                incompatibilities(column, stored_column, single_pkey)
+                    .into_iter()
+                    .map(|issue| format!("{name}.{}: {issue}", column.name)),
+            );
+            if stored_column.name != column.name {
+                let position = resulting.iter().position(|c| *c == stored_column.name);
+                resulting[position.expect("stored column")] = column.name.to_owned();
+                renames.push(SchemaChange::Rename {
+                    from: stored_column.name.clone(),
+                    to: column.name,
+                });
+            }
+            if !stored_column.nullable && (column.list || column.optional) {
+                alters.push(SchemaChange::MakeOptional(column.name));
+            }
+            let from = stored_column.data_type.to_string();
+            if let Some(to) = converted_type(&from, sql_type(column)) {
+                alters.push(SchemaChange::Convert {
+                    column: column.name,
+                    from,
+                    to,
+                    optional: column.optional,
+                });
+            }
+        } else if column.pkey {
+            issues.push(format!(
+                "{name}.{}: new primary key column can't be added to the existing data",
+                column.name
+            ));
+        } else {
+            match column_def_with_default(column, single_pkey) {
+                Ok(Some(column_def)) => {
+                    resulting.push(column.name.to_owned());
+                    adds.push(SchemaChange::Add(column_def));
+                }
+                Ok(None) => issues.push(format!(
+                    "{name}.{}: new required {} column needs a default value for the existing rows, consider wrapping it into Option<...> or adding #[default_column(...)]",
+                    column.name, column.rust_type
+                )),
+                Err(e) => issues.push(format!("{name}.{}: invalid default value: {e}", column.name)),
+            }
+        }
+    }
+
+    for stored_column in stored {
+        if !resulting.contains(&stored_column.name) {
+            // has been renamed
+            continue;
+        }
+        if table.columns().iter().any(|c| c.name == stored_column.name) {
+            continue;
+        }
+        if table
+            .dropped_columns()
+            .contains(&stored_column.name.as_str())
+        {
+            resulting.retain(|c| *c != stored_column.name);
+            drops.push(SchemaChange::Drop(stored_column.name.clone()));
+        } else {
+            issues.push(format!(
+                "{name}.{}: column was removed from the struct, list it in #[table(dropped(...))] to drop its data",
+                stored_column.name
+            ));
+        }
+    }
+
+    // rows are stored and read positionally so the order of columns must match
+    let expected: Vec<&str> = table.columns().iter().map(|c| c.name).collect();
+    if issues.is_empty() && resulting != expected {
+        issues.push(format!(
+            "{name}: stored columns order {resulting:?} doesn't match {expected:?}, new fields should be added after the existing ones"
+        ));
+    }
+
+    if !issues.is_empty() {
+        return Err(issues);
+    }
+
+    Ok(renames
+        .into_iter()
+        .chain(alters)
+        .chain(drops)
+        .chain(adds)
+        .collect())
+}
+
+/// Compares stored indexes with the derived ones, outdated indexes are dropped and created again
+pub(crate) fn index_changes(table: TableSchema, stored: &[SchemaIndex]) -> Vec<SchemaChange> {
+    let matches = |index: &IndexSchema, stored: &SchemaIndex| {
+        index.name == stored.name && stored.expr == Expr::Identifier(index.columns[0].to_owned())
+    };
+
+    let drops = stored
+        .iter()
+        .filter(|stored| !table.indexes().iter().any(|index| matches(index, stored)))
+        .map(|stored| SchemaChange::DropIndex(stored.name.clone()));
+
+    let creates = table
+        .indexes()
+        .iter()
+        .filter(|index| !stored.iter().any(|stored| matches(index, stored)))
+        .map(|index| SchemaChange::CreateIndex {
+            name: index.name,
+            column: index.columns[0],
+        });
+
+    drops.chain(creates).collect()
+}
+
+fn incompatibilities(column: &ColumnSchema, stored: &ColumnDef, single_pkey: bool) -> Vec<String> {
+    let mut issues = vec![];
+
+    let stored_type = stored.data_type.to_string();
+    let sql_type = sql_type(column);
+    if stored_type != sql_type && converted_type(&stored_type, sql_type).is_none() {
+        issues.push(format!("type changed from {stored_type} to {sql_type}"));
+    }
+
+    let nullable = column.list || column.optional;
+    // missing lists are converted into empty values
+    let converted_list = stored_type == "LIST" && converted_type(&stored_type, sql_type).is_some();
+    if stored.nullable && !nullable && !converted_list {
+        issues.push("column became required while stored data may contain nulls".to_owned());
+    }
+
+    let pkey = column.pkey && single_pkey;
+    let unique = !column.pkey && column.unique;
+    match stored.unique {
+        Some(ColumnUniqueOption { is_primary: true }) if !pkey => {
+            issues.push("column is no longer the primary key".to_owned())
+        }
+        Some(ColumnUniqueOption { is_primary: false }) if !unique => {
+            issues.push("column is no longer unique".to_owned())
+        }
+        None if pkey => issues.push("column became the primary key".to_owned()),
+        None if unique => issues.push("column became unique".to_owned()),
+        _ => {}
+    }
+
+    issues
+}
+
+fn sql_type(column: &ColumnSchema) -> &'static str {
+    if column.list {
+        "LIST"
+    } else {
+        column.sql_type
+    }
+}
+
+/// Type of the column which values can be converted from the stored ones: dates, times, UTC timestamps, decimals
+/// and maps were stored as json-serialized TEXT and bytes as LIST (or TEXT when optional) before they got native types
+fn converted_type(stored: &str, sql_type: &str) -> Option<DataType> {
+    match (stored, sql_type) {
+        ("TEXT", "DATE") => Some(DataType::Date),
+        ("TEXT", "TIME") => Some(DataType::Time),
+        ("TEXT", "TIMESTAMP") => Some(DataType::Timestamp),
+        ("TEXT", "DECIMAL") => Some(DataType::Decimal),
+        ("TEXT", "MAP") => Some(DataType::Map),
+        ("LIST" | "TEXT", "BYTEA") => Some(DataType::Bytea),
+        _ => None,
+    }
+}
+
+/// Stored value converted into the type returned by [`converted_type`]
+fn convert_value(value: sql::Value, to: &DataType, optional: bool) -> Result<sql::Value> {
+    use sql::Value::*;
+    let converted = match (&value, to) {
+        (Null, DataType::Bytea) if !optional => Some(Bytea(vec![])),
+        (Null, _) => Some(Null),
+        (Str(text), DataType::Date) => parse_stored(text).map(Date),
+        (Str(text), DataType::Time) => parse_stored(text).map(Time),
+        (Str(text), DataType::Timestamp) => parse_stored::<DateTime<Utc>>(text)
+            .map(|timestamp| timestamp.naive_utc())
+            .or_else(|| parse_stored(text))
+            .map(Timestamp),
+        (Str(text), DataType::Decimal) => parse_stored(text).map(Decimal),
+        (Str(text), DataType::Map) => sql::Value::parse_json_map(text).ok(),
+        (Str(text), DataType::Bytea) => from_json_str(text).ok().map(Bytea),
+        (List(items), DataType::Bytea) => items
+            .iter()
+            .map(|item| u8::try_from(item).ok())
+            .collect::<Option<Vec<u8>>>()
+            .map(Bytea),
+        _ => None,
+    };
+    converted.ok_or_else(|| e!("stored value {value:?} can't be converted into {to}"))
+}
+
+/// Value serialized into json or stored as plain text
+fn parse_stored<T: serde::de::DeserializeOwned + std::str::FromStr>(text: &str) -> Option<T> {
+    from_json_str(text).ok().or_else(|| text.parse().ok())
+}
+
+/// Replaces the stored definition of the column and converts its values in all the rows.
+/// GlueSQL can't alter column definitions so the schema and the rows are written into the storage directly
+pub(crate) async fn rewrite_column(
+    table: &str,
+    column: &str,
+    update_def: impl FnOnce(&mut ColumnDef),
+    convert: impl Fn(sql::Value) -> Result<sql::Value>,
+) -> Result {
+    let mut storage = DB.storage();
+    await_blocking(async move {
+        let Some(mut schema) = storage.fetch_schema(table).await? else {
+            return Err(e!("table {table} isn't stored"));
+        };
+        let column_defs = schema.column_defs.get_or_insert_with(Vec::new);
+        let Some(position) = column_defs.iter().position(|c| c.name == column) else {
+            return Err(e!("column {table}.{column} isn't stored"));
+        };
+        update_def(&mut column_defs[position]);
+
+        let stored: Vec<(Key, DataRow)> = storage.scan_data(table).await?.try_collect().await?;
+        let mut rows = Vec::with_capacity(stored.len());
+        for (key, row) in stored {
+            let DataRow::Vec(mut values) = row else {
+                return Err(e!("{table} row isn't a list of values"));
+            };
+            let value = std::mem::replace(&mut values[position], sql::Value::Null);
+            values[position] = convert(value)?;
+            rows.push((key, DataRow::Vec(values)));
+        }
+
+        // memory storages drop the rows along with the replaced schema
+        storage.insert_schema(&schema).await?;
+        storage.insert_data(table, rows).await?;
+        OK
+    })
+}
+
+/// Column definition for the `ADD COLUMN` statement which fills existing rows
+fn column_def_with_default(column: &ColumnSchema, single_pkey: bool) -> Result<Option<String>> {
+    let column_def = column_def(column, single_pkey)?;
+    if column.optional || column.default.is_some() {
+        return Ok(Some(column_def));
+    }
+    let default = match column.sql_type {
+        _ if column.list => "'[]'",
+        "BOOLEAN" => "FALSE",
+        "TEXT" if !column.serialized && column.variants.is_empty() => "''",
+        _ if column.numeric => "0",
+        _ => return Ok(None),
+    };
+    Ok(Some(format!("{column_def} DEFAULT {default}")))
+}
+
+/// Column definition as used in `CREATE TABLE` and `ADD COLUMN` statements
+pub(crate) fn column_def(column: &ColumnSchema, single_pkey: bool) -> Result<String> {
+    let ColumnSchema {
+        name,
+        sql_type,
+        unique,
+        pkey,
+        list,
+        optional,
+        default,
+        ..
+    } = column;
+    let default = match default {
+        Some(default) => format!(" DEFAULT {}", default()?),
+        None => String::new(),
+    };
+    Ok(if *list {
+        format!("{name} LIST{default}")
+    } else {
+        let unique = if !*pkey && *unique { " UNIQUE" } else { "" };
+        let pkey = if *pkey && single_pkey {
+            " PRIMARY KEY"
+        } else {
+            ""
+        };
+        let optional = if *optional { "" } else { " NOT NULL" };
+        format!("{name} {sql_type}{pkey}{unique}{optional}{default}")
+    })
+}
+
+/// Rewrites json-serialized values of the `#[enum_column]`s into their variants, returns the unknown ones
+pub(crate) async fn migrate_variants(table: TableSchema) -> Result<Vec<String>> {
+    let mut unknown = vec![];
+    for column in table.columns().iter().filter(|c| !c.variants.is_empty()) {
+        let rows = sql::table(table.name())
+            .select()
+            .project(column.name)
+            .rows()
+            .await?;
+        let mut checked = std::collections::HashSet::new();
+        for row in rows {
+            // nulls of the optional columns
+            let Some(sql::Value::Str(stored)) = row.into_iter().next() else {
+                continue;
+            };
+            if column.allows_variant(&stored) || !checked.insert(stored.clone()) {
+                continue;
+            }
+            match json_variant(&stored).filter(|variant| column.allows_variant(variant)) {
+                Some(variant) => {
+                    sql::table(table.name())
+                        .update()
+                        .filter(sql::col(column.name).eq(sql::text(stored)))
+                        .set(column.name, sql::text(variant))
+                        .exec()
+                        .await?;
+                }
+                None => unknown.push(format!("{}.{}: {stored}", table.name(), column.name)),
+            }
+        }
+    }
+    Ok(unknown)
+}
+
+/// Variant of the enum serialized by serde, which is how these columns were stored before
+fn json_variant(stored: &str) -> Option<String> {
+    match from_json_str(stored).ok()? {
+        JsonValue::String(name) => Some(name),
+        JsonValue::Object(map) if map.len() == 1 => {
+            let (name, value) = map.into_iter().next()?;
+            let value = match value {
+                JsonValue::String(value) => value,
+                value => value.to_string(),
+            };
+            Some(format!("{name}({value})"))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+    use gluesql::core::{data::SchemaIndexOrd, parse_sql::parse, translate::translate};
+
+    #[derive(Table, Debug, Clone, Serialize, Deserialize)]
+    #[table(name = "test_diffs", dropped(legacy))]
+    struct Diffed {
+        #[pkey_column]
+        id: Uuid,
+        #[column(renamed_from = "title")]
+        name: String,
+        owner: Uuid,
+        #[index_column]
+        count: u64,
+        note: Option<String>,
+    }
+
+    /// Definitions of the columns as they are stored for the current struct
+    fn current() -> Vec<String> {
+        let columns = Diffed::schema().columns().iter();
+        columns.map(|c| column_def(c, true).unwrap()).collect()
+    }
+
+    fn parsed(columns: &[String]) -> Vec<ColumnDef> {
+        let sql = format!("CREATE TABLE stored ({})", columns.join(", "));
+        match translate(&parse(sql).unwrap()[0]).unwrap() {
+            sql::Statement::CreateTable { columns, .. } => columns.unwrap(),
+            statement => panic!("unexpected statement: {statement:?}"),
+        }
+    }
+
+    /// Changes from the stored columns to the current ones, with the current definition of the column replaced
+    fn diff_with(
+        column: &str,
+        stored: Option<&str>,
+    ) -> std::result::Result<Vec<String>, Vec<String>> {
+        let mut columns = current();
+        let position = columns
+            .iter()
+            .position(|c| c.starts_with(&format!("{column} ")));
+        match (position, stored) {
+            (Some(i), Some(def)) => columns[i] = def.to_owned(),
+            (Some(i), None) => {
+                columns.remove(i);
+            }
+            (None, Some(def)) => columns.push(def.to_owned()),
+            (None, None) => unreachable!(),
+        }
+        let changes = diff(Diffed::schema(), &parsed(&columns))?;
+        Ok(changes.iter().map(ToString::to_string).collect())
+    }
+
+    fn has_issue(result: std::result::Result<Vec<String>, Vec<String>>, issue: &str) -> bool {
+        let issues = result.expect_err("change should be refused");
+        issues.iter().any(|i| i.contains(issue))
+    }
+
+    #[test]
+    fn same_columns_have_no_changes() {
+        let changes = diff(Diffed::schema(), &parsed(&current())).unwrap();
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn new_columns_are_added() {
+        assert_eq!(
+            diff_with("note", None).unwrap(),
+            vec!["add column note TEXT"]
+        );
+        assert!(has_issue(diff_with("owner", None), "needs a default value"));
+        assert!(has_issue(diff_with("id", None), "new primary key column"));
+    }
+
+    #[test]
+    fn removed_columns_are_dropped_only_when_listed() {
+        let dropped = diff_with("legacy", Some("legacy TEXT")).unwrap();
+        assert_eq!(dropped, vec!["drop column legacy"]);
+        assert!(has_issue(
+            diff_with("other", Some("other TEXT")),
+            "removed from the struct"
+        ));
+    }
+
+    #[test]
+    fn columns_are_renamed() {
+        let renamed = diff_with("name", Some("title TEXT NOT NULL")).unwrap();
+        assert_eq!(renamed, vec!["rename column title to name"]);
+    }
+
+    #[test]
+    fn type_changes_are_refused() {
+        let changed = diff_with("count", Some("count TEXT NOT NULL"));
+        assert!(has_issue(changed, "type changed from TEXT to UINT64"));
+    }
+
+    #[test]
+    fn only_required_columns_can_become_optional() {
+        let relaxed = diff_with("note", Some("note TEXT NOT NULL")).unwrap();
+        assert_eq!(relaxed, vec!["make column note optional"]);
+        assert!(has_issue(
+            diff_with("name", Some("name TEXT")),
+            "became required"
+        ));
+    }
+
+    #[test]
+    fn outdated_indexes_are_recreated() {
+        let index = |name: &str, column: &str| SchemaIndex {
+            name: name.to_owned(),
+            expr: Expr::Identifier(column.to_owned()),
+            order: SchemaIndexOrd::Both,
+            created: Default::default(),
+        };
+        let changes = |stored: &[SchemaIndex]| -> Vec<String> {
+            let changes = index_changes(Diffed::schema(), stored);
+            changes.iter().map(ToString::to_string).collect()
+        };
+
+        let created = "create index test_diffs_count on count";
+        assert_eq!(changes(&[]), vec![created]);
+        assert!(changes(&[index("test_diffs_count", "count")]).is_empty());
+        assert_eq!(
+            changes(&[index("test_diffs_count", "name")]),
+            vec!["drop index test_diffs_count", created]
+        );
+    }
+
+    #[test]
+    fn optional_columns_keep_stored_values() {
+        #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[table(name = "test_relaxed")]
+        struct Relaxed {
+            #[pkey_column]
+            id: u64,
+            note: Option<String>,
+        }
+
+        test_db(&[], async {
+            DB.query("CREATE TABLE test_relaxed (id UINT64 PRIMARY KEY, note TEXT NOT NULL)")
+                .await?;
+            DB.query("INSERT INTO test_relaxed VALUES (1, 'kept')")
+                .await?;
+
+            let stored = await_blocking(DB.storage().fetch_schema("test_relaxed"))?.unwrap();
+            let changes = diff(Relaxed::schema(), &stored.column_defs.unwrap()).unwrap();
+            for change in changes {
+                DB.in_transaction(change.apply("test_relaxed")).await?;
+            }
+
+            Relaxed { id: 2, note: None }.save().await?;
+            let rows = Relaxed::select_all().await?;
+            let kept = Relaxed {
+                id: 1,
+                note: Some("kept".to_owned()),
+            };
+            assert_eq!(rows, vec![kept, Relaxed { id: 2, note: None }]);
+            OK
+        });
+    }
+
+    #[test]
+    fn previously_serialized_types_are_converted() {
+        assert_eq!(
+            diff_with("note", Some("note MAP")).unwrap_err(),
+            vec!["test_diffs.note: type changed from MAP to TEXT"]
+        );
+
+        #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[table(name = "test_converted")]
+        struct Converted {
+            #[pkey_column]
+            id: u64,
+            date: NaiveDate,
+            time: NaiveTime,
+            at: DateTime<Utc>,
+            amount: Decimal,
+            bytes: Vec<u8>,
+            labels: std::collections::HashMap<String, u32>,
+            due: Option<NaiveDate>,
+        }
+
+        test_db(&[], async {
+            DB.query(
+                "CREATE TABLE test_converted (id UINT64 PRIMARY KEY, date TEXT NOT NULL, time TEXT NOT NULL,
+                at TEXT NOT NULL, amount TEXT NOT NULL, bytes LIST, labels TEXT NOT NULL, due TEXT)",
+            )
+            .await?;
+            DB.query(
+                r#"INSERT INTO test_converted VALUES (1, '"2024-02-29"', '"23:59:58.123"',
+                '"2024-02-29T23:59:58.123Z"', '"12.345"', '[0, 7, 255]', '{"a":1}', NULL)"#,
+            )
+            .await?;
+
+            let stored = await_blocking(DB.storage().fetch_schema("test_converted"))?.unwrap();
+            let changes = diff(Converted::schema(), &stored.column_defs.unwrap()).unwrap();
+            let described = changes.iter().map(ToString::to_string).collect::<Vec<_>>();
+            assert!(described.contains(&"convert column date from TEXT to DATE".to_owned()));
+            assert!(described.contains(&"convert column bytes from LIST to BYTEA".to_owned()));
+            for change in changes {
+                DB.in_transaction(change.apply("test_converted")).await?;
+            }
+
+            let at = NaiveDate::from_ymd_opt(2024, 2, 29)
+                .unwrap()
+                .and_hms_milli_opt(23, 59, 58, 123)
+                .unwrap();
+            let converted = Converted {
+                id: 1,
+                date: at.date(),
+                time: at.time(),
+                at: at.and_utc(),
+                amount: "12.345".parse().unwrap(),
+                bytes: vec![0, 7, 255],
+                labels: [("a".to_owned(), 1)].into(),
+                due: None,
+            };
+            assert_eq!(Converted::select_all().await?, vec![converted]);
+            OK
+        });
+    }
+
+    #[derive(TableEnum, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum Role {
+        Admin,
+        Custom(String),
+    }
+
+    #[test]
+    fn enum_columns_store_and_check_variants() {
+        #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[table(name = "test_roles")]
+        struct Member {
+            id: u32,
+            #[enum_column]
+            role: Role,
+            #[enum_column]
+            previous: Option<Role>,
+        }
+
+        assert_eq!(Role::VARIANTS, ["Admin", "Custom(..)"]);
+        assert_eq!(Role::Custom("x".into()).to_variant(), "Custom(x)");
+        assert_eq!(
+            Role::from_variant("Custom(y)").unwrap(),
+            Role::Custom("y".into())
+        );
+        assert!(Role::from_variant("Owner").is_err());
+
+        test_db(&[Member::schema()], async {
+            let mut member = Member {
+                id: 1,
+                role: Role::Custom("editor".into()),
+                previous: Some(Role::Admin),
+            };
+            member.save().await?;
+            assert_eq!(Member::select_by_pkey(1).await?, Some(member));
+            let stored = Member::select().project("role").rows().await?;
+            assert_eq!(stored, vec![vec![sql::Value::Str("Custom(editor)".into())]]);
+
+            // serde json of the variants is converted, unknown variants are reported
+            DB.query(r#"INSERT INTO test_roles VALUES (2, '{"Custom":"viewer"}', '"Admin"')"#)
+                .await?;
+            assert!(migrate_variants(Member::schema()).await?.is_empty());
+            let converted = Member::select_by_pkey(2).await?.expect("converted member");
+            assert_eq!(converted.role, Role::Custom("viewer".into()));
+            assert_eq!(converted.previous, Some(Role::Admin));
+
+            DB.query("INSERT INTO test_roles VALUES (3, 'Owner', NULL)")
+                .await?;
+            let unknown = migrate_variants(Member::schema()).await?;
+            assert_eq!(unknown, vec!["test_roles.role: Owner"]);
+            OK
+        });
+    }
+}
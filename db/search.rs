@@ -0,0 +1,155 @@
+use crate::*;
+
+/// Lowercased alphanumeric words of the text
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+impl Db {
+    /// Rows of the `T` which `#[searchable]` columns contain words starting with every word of the query, most relevant first
+    #[doc(hidden)]
+    pub async fn _search<T: Table>(&self, query: &str, limit: usize) -> Result<Vec<T>> {
+        let terms = tokenize(query);
+        if terms.is_empty() {
+            return Ok(vec![]);
+        }
+        let columns: Vec<usize> = T::COLUMN_SCHEMAS
+            .iter()
+            .enumerate()
+            .filter(|(_, col)| col.searchable)
+            .map(|(i, _)| i)
+            .collect();
+        if columns.is_empty() {
+            let table = T::TABLE_NAME;
+            return Err(e!("{table} has no #[searchable] columns"));
+        }
+
+        let candidates = match self.storage() {
+            #[cfg(host)]
+            DbStorage::Persistent(storage) => storage
+                .search_rows(T::TABLE_NAME, &terms)
+                .await?
+                .into_iter()
+                .filter_map(super::history::values)
+                .collect(),
+            // without the inverted index every row is a candidate
+            _ => T::select().rows().await?,
+        };
+
+        let mut ranked = candidates
+            .into_iter()
+            .filter_map(|row| Some((relevance(&row, &columns, &terms)?, row)))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(_, row)| T::from_row(row))
+            .collect()
+    }
+}
+
+/// Exact matches of the terms weigh twice as much as the prefix ones, scores of the longer texts are lowered.
+/// `None` if some of the terms don't match
+fn relevance(row: &[sql::Value], columns: &[usize], terms: &[String]) -> Option<f64> {
+    let words = columns
+        .iter()
+        .filter_map(|i| match row.get(*i) {
+            Some(sql::Value::Str(text)) => Some(tokenize(text)),
+            _ => None,
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    let mut score = 0.0;
+    for term in terms {
+        let term_score: f64 = words
+            .iter()
+            .map(|word| match word {
+                _ if word == term => 2.0,
+                _ if word.starts_with(term.as_str()) => 1.0,
+                _ => 0.0,
+            })
+            .sum();
+        if term_score == 0.0 {
+            return None;
+        }
+        score += term_score;
+    }
+    Some(score / (words.len() as f64).sqrt())
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_search_posts")]
+    struct SearchPost {
+        id: u32,
+        #[searchable]
+        title: String,
+        #[searchable]
+        body: String,
+    }
+
+    fn row(title: &str, body: &str) -> Vec<sql::Value> {
+        vec![
+            sql::Value::U32(0),
+            sql::Value::Str(title.to_owned()),
+            sql::Value::Str(body.to_owned()),
+        ]
+    }
+
+    #[test]
+    fn text_is_split_into_lowercased_words() {
+        assert_eq!(
+            tokenize("Hello, World! it's 2024-01-02"),
+            ["hello", "world", "it", "s", "2024", "01", "02"]
+        );
+        assert_eq!(tokenize("Größe straße"), ["größe", "straße"]);
+        assert!(tokenize(" ,.! ").is_empty());
+    }
+
+    #[test]
+    fn exact_and_shorter_matches_are_more_relevant() {
+        let columns = [1, 2];
+        let terms = tokenize("rust");
+        let exact = relevance(&row("Rust", "notes"), &columns, &terms).unwrap();
+        let prefix = relevance(&row("Rustacean", "notes"), &columns, &terms).unwrap();
+        let longer = relevance(&row("Rust", "notes about the language"), &columns, &terms).unwrap();
+        assert!(exact > prefix);
+        assert!(exact > longer);
+        assert_eq!(relevance(&row("Go", "notes"), &columns, &terms), None);
+        // every term has to match
+        let terms = tokenize("rust go");
+        assert_eq!(relevance(&row("Rust", "notes"), &columns, &terms), None);
+    }
+
+    #[test]
+    fn searches_match_the_current_rows() {
+        test_db(&[SearchPost::schema()], async {
+            let mut post = SearchPost {
+                id: 1,
+                title: "Unique zebra".to_owned(),
+                body: "stripes".to_owned(),
+            };
+            post.insert_self().await?;
+            assert_eq!(SearchPost::search("zeb", 10).await?, [post.clone()]);
+
+            post.title = "Unique okapi".to_owned();
+            post.save().await?;
+            assert!(SearchPost::search("zebra", 10).await?.is_empty());
+            assert_eq!(
+                SearchPost::search("okapi stripes", 10).await?,
+                [post.clone()]
+            );
+
+            post.remove().await?;
+            assert!(SearchPost::search("okapi", 10).await?.is_empty());
+            OK
+        })
+    }
+}
@@ -0,0 +1,138 @@
+use crate::*;
+
+/// Outcome of a garbage collection of the persistent storage
+#[derive(Debug, Table, Clone, Serialize, Deserialize)]
+pub struct GcRun {
+    pub timestamp: NaiveDateTime,
+    /// removed versions of the rows, schemas and index entries
+    pub snapshots: u64,
+    pub bytes: u64,
+}
+
+/// Stored data of a table in the persistent storage
+#[derive(Debug, Clone)]
+pub struct TableStorage {
+    pub name: &'static str,
+    /// rows visible to the new reads
+    pub rows: u64,
+    /// size of the row versions and the index entries
+    pub bytes: u64,
+    /// versions of the rows kept for the time-travel reads and the running transactions
+    pub versions: u64,
+    /// most versions kept for a single row
+    pub max_depth: u64,
+}
+
+/// Number of the latest [`GcRun`]s which are kept, a day of the scheduled ones
+const GC_RUNS_KEPT: usize = 144;
+
+impl Db {
+    /// Removes the row versions older than the history retention along with the data of the finished transactions
+    /// and records the reclaimed space as a [`GcRun`], keeping only the latest ones. Scheduled every 10 minutes for the persistent storage
+    pub async fn gc(&self) -> Result<GcRun> {
+        let DbStorage::Persistent(storage) = self.storage() else {
+            return Err(e!("gc requires the persistent storage"));
+        };
+        let stats = storage.collect_garbage().await?;
+        let mut run = GcRun {
+            timestamp: Utc::now().naive_utc(),
+            snapshots: stats.snapshots,
+            bytes: stats.bytes,
+        };
+        run.save().await?;
+        prune_gc_runs().await?;
+        Ok(run)
+    }
+
+    /// Rows, sizes and versions of all the tables in the persistent storage, none for the memory one
+    pub async fn tables_storage(&self) -> Result<Vec<TableStorage>> {
+        let DbStorage::Persistent(storage) = self.storage() else {
+            return Ok(vec![]);
+        };
+        let mut tables = vec![];
+        for table in self.all_tables() {
+            let stats = storage.table_stats(table.name()).await?;
+            tables.push(TableStorage {
+                name: table.name(),
+                rows: stats.rows,
+                bytes: stats.bytes,
+                versions: stats.versions,
+                max_depth: stats.max_depth,
+            });
+        }
+        Ok(tables)
+    }
+
+    /// Size of the persistent storage files in bytes, `None` for the memory storage
+    pub async fn size_on_disk(&self) -> Result<Option<u64>> {
+        let DbStorage::Persistent(storage) = self.storage() else {
+            return Ok(None);
+        };
+        Ok(Some(storage.size_on_disk().await?))
+    }
+
+    /// Latest recorded [`GcRun`]
+    pub async fn last_gc(&self) -> Result<Option<GcRun>> {
+        let latest = GcRun::select_page(1, 0, GcRun::cols.timestamp.desc()).await?;
+        Ok(latest.into_iter().next())
+    }
+}
+
+/// Removes the [`GcRun`]s older than the latest [`GC_RUNS_KEPT`] ones
+async fn prune_gc_runs() -> Result {
+    let oldest_kept = GcRun::select_page(1, GC_RUNS_KEPT - 1, GcRun::cols.timestamp.desc()).await?;
+    let Some(oldest_kept) = oldest_kept.into_iter().next() else {
+        return OK;
+    };
+    let older = GcRun::cols
+        .timestamp
+        .lt(oldest_kept.timestamp)
+        .into_expr()?;
+    GcRun::delete().filter(older).exec().await?;
+    OK
+}
+
+pub(crate) fn schedule_gc() {
+    RT.every(10).minutes().schedule("db gc", || async {
+        DB.gc().await?;
+        OK
+    });
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_latest_gc_runs_are_kept() {
+        test_db(&[GcRun::schema()], async {
+            let start = Utc::now().naive_utc();
+            let runs: Vec<GcRun> = (0..GC_RUNS_KEPT as i64 + 6)
+                .map(|minutes| GcRun {
+                    timestamp: start + chrono::Duration::minutes(minutes),
+                    snapshots: minutes as u64,
+                    bytes: 0,
+                })
+                .collect();
+            GcRun::insert_many(&runs).await?;
+            prune_gc_runs().await?;
+
+            assert_eq!(GcRun::count().await?, GC_RUNS_KEPT);
+            let oldest = GcRun::select_page(1, 0, GcRun::cols.timestamp.asc()).await?;
+            assert_eq!(oldest[0].snapshots, 6);
+            let latest = DB.last_gc().await?.expect("latest run");
+            assert_eq!(latest.snapshots, GC_RUNS_KEPT as u64 + 5);
+            OK
+        })
+    }
+
+    #[test]
+    fn memory_storage_has_no_storage_stats() {
+        test_db(&[], async {
+            assert!(DB.tables_storage().await?.is_empty());
+            assert_eq!(DB.size_on_disk().await?, None);
+            assert!(DB.gc().await.is_err());
+            OK
+        })
+    }
+}
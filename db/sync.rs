@@ -0,0 +1,374 @@
+use crate::*;
+
+/// Changes of a `#[table(sync)]` table pulled by the service workers
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SyncPull {
+    /// transaction to pull the next changes after
+    pub txid: u64,
+    /// rows hold the whole table so the local rows missing from it are removed
+    pub full: bool,
+    pub rows: Vec<Vec<sql::Value>>,
+    pub removed: Vec<Vec<sql::Value>>,
+}
+
+/// Write made by a service worker along with the row it was based on, `None`s stand for missing rows
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SyncWrite {
+    pub base: Option<Vec<sql::Value>>,
+    pub row: Option<Vec<sql::Value>>,
+}
+
+/// Client of the `/sync` routes which the `#[table(sync(authorize = ...))]` hooks check for every pulled and pushed row
+pub struct SyncRequester {
+    /// signed in user if there is one
+    #[cfg(all(host, feature = "auth"))]
+    pub user: Option<User>,
+    pub headers: HeaderMap,
+}
+
+#[cfg(host)]
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for SyncRequester {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut http::request::Parts, _: &S) -> Result<Self> {
+        Ok(SyncRequester {
+            #[cfg(feature = "auth")]
+            user: parts
+                .extensions
+                .get::<Auth>()
+                .and_then(|auth| auth.user.clone()),
+            headers: parts.headers.clone(),
+        })
+    }
+}
+
+/// Encoded changes of the table since the transaction which the requester is authorized to see, whole table if they aren't retained
+#[doc(hidden)]
+pub async fn _sync_pull<T: Table>(
+    requester: &SyncRequester,
+    since: Option<u64>,
+) -> Result<Vec<u8>> {
+    let mut pull = pull(T::TABLE_NAME, since).await?;
+    let authorized = |row: &Vec<sql::Value>| {
+        T::from_row(row.clone()).is_ok_and(|item| T::authorize_sync(requester, &item))
+    };
+    pull.rows.retain(authorized);
+    pull.removed.retain(authorized);
+    Ok(bincode::serialize(&pull)?)
+}
+
+async fn pull(table: &str, since: Option<u64>) -> Result<SyncPull> {
+    #[cfg(host)]
+    if let DbStorage::Persistent(storage) = DB.storage() {
+        // removals before the gc'ed transactions are gone
+        let gc_txid = storage.gc_txid().await?;
+        let since = since.filter(|since| gc_txid.is_none_or(|gc_txid| *since >= gc_txid));
+        let mut pull = SyncPull {
+            txid: since.unwrap_or(0),
+            full: since.is_none(),
+            rows: vec![],
+            removed: vec![],
+        };
+        // only the rows written after the pulled transaction unless the whole table is pulled
+        let history = match since {
+            Some(since) => storage.scan_changes(table, since).await?,
+            None => storage.scan_history(table).await?,
+        };
+        for versions in history {
+            let Some((created_by, deleted_by, row)) = versions.into_iter().next() else {
+                continue;
+            };
+            pull.txid = pull.txid.max(deleted_by.unwrap_or(created_by));
+            let Some(row) = super::history::values(row) else {
+                continue;
+            };
+            match (since, deleted_by) {
+                (None, None) => pull.rows.push(row),
+                (Some(since), None) if created_by > since => pull.rows.push(row),
+                (Some(since), Some(deleted_by)) if deleted_by > since => pull.removed.push(row),
+                _ => {}
+            }
+        }
+        // running transactions can still write versions with lower txids
+        if let Some(running) = storage.oldest_running_txid().await? {
+            pull.txid = pull
+                .txid
+                .min(running.saturating_sub(1))
+                .max(since.unwrap_or(0));
+        }
+        return Ok(pull);
+    }
+    let _ = since;
+    Ok(SyncPull {
+        txid: 0,
+        full: true,
+        rows: sql::table(table).select().rows().await?,
+        removed: vec![],
+    })
+}
+
+/// Write of a service worker which wasn't applied along with the stored row it should be reverted to
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SyncRejected {
+    /// index of the write among the pushed ones
+    pub write: usize,
+    /// stored row unless it's missing or the requester isn't authorized to see it
+    pub stored: Option<Vec<sql::Value>>,
+}
+
+/// Applies encoded writes of a service worker, conflicting ones are resolved with [`Table::resolve_conflict`].
+/// Responds with the encoded [`SyncRejected`] writes which weren't applied like the ones of the rows which
+/// the requester isn't authorized to change
+#[doc(hidden)]
+pub async fn _sync_push<T: Table + Sync>(
+    requester: &SyncRequester,
+    writes: Vec<u8>,
+) -> Result<Vec<u8>> {
+    let writes: Vec<SyncWrite> = bincode::deserialize(&writes)?;
+    let mut rejected = vec![];
+    for (index, write) in writes.into_iter().enumerate() {
+        let Some(pkey) = write_pkey::<T>(&write)? else {
+            continue;
+        };
+        if let Err(e) = push::<T>(requester, pkey.clone(), write).await {
+            warn!(target: "sync", "rejected {} write: {e}", T::TABLE_NAME);
+            rejected.push(SyncRejected {
+                write: index,
+                stored: stored::<T>(requester, pkey).await?,
+            });
+        }
+    }
+    Ok(bincode::serialize(&rejected)?)
+}
+
+fn write_pkey<T: Table>(SyncWrite { base, row }: &SyncWrite) -> Result<Option<T::Key>> {
+    match row.as_ref().or(base.as_ref()) {
+        Some(row) => Ok(Some(T::from_row(row.clone())?.get_pkey())),
+        None => Ok(None),
+    }
+}
+
+/// Stored row with the key which the requester is authorized to see
+async fn stored<T: Table>(
+    requester: &SyncRequester,
+    pkey: T::Key,
+) -> Result<Option<Vec<sql::Value>>> {
+    let Some(row) = T::select()
+        .filter(T::pkey_filter(&pkey))
+        .rows()
+        .await?
+        .pop()
+    else {
+        return Ok(None);
+    };
+    let authorized = T::authorize_sync(requester, &T::from_row(row.clone())?);
+    Ok(authorized.then_some(row))
+}
+
+async fn push<T: Table + Sync>(
+    requester: &SyncRequester,
+    pkey: T::Key,
+    SyncWrite { base, row }: SyncWrite,
+) -> Result {
+    let client = row.map(T::from_row).transpose()?;
+    if client
+        .as_ref()
+        .is_some_and(|item| !T::authorize_sync(requester, item))
+    {
+        return Err(Error::Unauthorized);
+    }
+    DB.in_transaction(async move {
+        let current = T::select()
+            .filter(T::pkey_filter(&pkey))
+            .rows()
+            .await?
+            .pop();
+        let exists = current.is_some();
+        let unchanged = current == base;
+        let current = current.map(T::from_row).transpose()?;
+        if current
+            .as_ref()
+            .is_some_and(|item| !T::authorize_sync(requester, item))
+        {
+            return Err(Error::Unauthorized);
+        }
+        let resolved = match unchanged {
+            true => client,
+            false => T::resolve_conflict(current, client),
+        };
+        match resolved {
+            Some(mut item) => {
+                item.save().await?;
+            }
+            None if exists => T::delete_by_pkey(pkey).await?,
+            None => {}
+        }
+        OK
+    })
+    .await
+}
+
+/// Routes which serve changes of the `#[table(sync(...))]` tables and accept writes into them, every row is checked
+/// with the table's `authorize` hook
+#[cfg(host)]
+pub(crate) fn routes() -> Router {
+    route("/:table", get(pull_route).post(push_route))
+}
+
+#[cfg(host)]
+#[derive(Deserialize)]
+struct PullParams {
+    since: Option<u64>,
+}
+
+#[cfg(host)]
+async fn pull_route(
+    requester: SyncRequester,
+    Path(table): Path<String>,
+    extract::Query(PullParams { since }): extract::Query<PullParams>,
+) -> Result<Vec<u8>> {
+    synced_table(&table)?.sync_pull(&requester, since).await
+}
+
+#[cfg(host)]
+async fn push_route(
+    requester: SyncRequester,
+    Path(table): Path<String>,
+    body: axum::body::Bytes,
+) -> Result<Vec<u8>> {
+    synced_table(&table)?
+        .sync_push(&requester, body.to_vec())
+        .await
+}
+
+#[cfg(host)]
+fn synced_table(name: &str) -> Result<TableSchema> {
+    DB.custom_tables()
+        .into_iter()
+        .find(|table| table.name() == name && table.synced())
+        .ok_or(Error::NotFound)
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_sync_notes", sync(authorize = owned))]
+    struct SyncNote {
+        id: u32,
+        owner: String,
+    }
+
+    fn owned(requester: &SyncRequester, note: &SyncNote) -> bool {
+        requester
+            .headers
+            .get("owner")
+            .is_some_and(|owner| *owner == note.owner)
+    }
+
+    fn requester(owner: Option<&str>) -> SyncRequester {
+        let mut headers = HeaderMap::new();
+        if let Some(owner) = owner {
+            headers.insert("owner", HeaderValue::from_str(owner).expect("valid owner"));
+        }
+        SyncRequester {
+            #[cfg(feature = "auth")]
+            user: None,
+            headers,
+        }
+    }
+
+    fn row(id: u32, owner: &str) -> Vec<sql::Value> {
+        vec![sql::Value::U32(id), sql::Value::Str(owner.to_owned())]
+    }
+
+    async fn pulled_ids(requester: &SyncRequester) -> Result<Vec<u32>> {
+        let pull: SyncPull = bincode::deserialize(&_sync_pull::<SyncNote>(requester, None).await?)?;
+        let mut ids = vec![];
+        for row in pull.rows {
+            ids.push(SyncNote::from_row(row)?.id);
+        }
+        Ok(ids)
+    }
+
+    #[test]
+    fn pulls_only_authorized_rows() {
+        test_db(&[SyncNote::schema()], async {
+            for (id, owner) in [(1, "a"), (2, "b")] {
+                SyncNote {
+                    id,
+                    owner: owner.to_owned(),
+                }
+                .insert_self()
+                .await?;
+            }
+
+            let ids = pulled_ids(&requester(Some("a"))).await?;
+            assert!(ids.contains(&1));
+            assert!(!ids.contains(&2));
+            assert!(pulled_ids(&requester(None)).await?.is_empty());
+            OK
+        })
+    }
+
+    #[test]
+    fn pushes_only_authorized_writes() {
+        test_db(&[SyncNote::schema()], async {
+            let stored = SyncNote {
+                id: 10,
+                owner: "b".to_owned(),
+            };
+            stored.insert_self().await?;
+            let own = SyncNote {
+                id: 13,
+                owner: "a".to_owned(),
+            };
+            own.insert_self().await?;
+
+            let writes = vec![
+                // taking over, creating and removing rows of others
+                SyncWrite {
+                    base: Some(row(10, "b")),
+                    row: Some(row(10, "a")),
+                },
+                SyncWrite {
+                    base: None,
+                    row: Some(row(11, "b")),
+                },
+                SyncWrite {
+                    base: Some(row(10, "b")),
+                    row: None,
+                },
+                SyncWrite {
+                    base: None,
+                    row: Some(row(12, "a")),
+                },
+                // giving away own rows
+                SyncWrite {
+                    base: Some(row(13, "a")),
+                    row: Some(row(13, "b")),
+                },
+            ];
+            let requester = requester(Some("a"));
+            let rejected = _sync_push::<SyncNote>(&requester, bincode::serialize(&writes)?).await?;
+            let rejected: Vec<SyncRejected> = bincode::deserialize(&rejected)?;
+
+            // rejected writes are reverted to the stored rows which the requester can see
+            let reverted = rejected
+                .into_iter()
+                .map(|rejected| (rejected.write, rejected.stored))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                reverted,
+                [(0, None), (1, None), (2, None), (4, Some(row(13, "a")))]
+            );
+            assert_eq!(SyncNote::select_by_pkey(13).await?, Some(own));
+            assert_eq!(SyncNote::select_by_pkey(10).await?, Some(stored));
+            assert_eq!(SyncNote::select_by_pkey(11).await?, None);
+            assert!(SyncNote::select_by_pkey(12).await?.is_some());
+            OK
+        })
+    }
+}
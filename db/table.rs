@@ -11,6 +11,8 @@ pub type ColumnSchemas = &'static [ColumnSchema];
 #[derive(Debug, Clone, Copy)]
 pub struct ColumnSchema {
     pub name: &'static str,
+    /// name of the struct field, differs from the column name when `#[column(name = "...")]` is used
+    pub field_name: &'static str,
     pub rust_type: &'static str,
     pub sql_type: &'static str,
     pub unique: bool,
@@ -18,8 +20,52 @@ pub struct ColumnSchema {
     pub list: bool,
     pub optional: bool,
     pub serialized: bool,
+    /// words of the values are kept in the inverted index for `T::search`
+    pub searchable: bool,
+    /// stored forms of the [`TableEnum`] variants in `#[enum_column]`s, empty for other columns
+    pub variants: &'static [&'static str],
+    /// `#[version_column]` which updates compare and increment
+    pub version: bool,
+    /// `#[created_at]` or `#[updated_at]` column filled with the time of the writes
+    pub timestamp: bool,
+    /// SQL expression of the column's DEFAULT
+    pub default: Option<fn() -> Result<String>>,
     pub numeric: bool,
     pub comparable: bool,
+    pub renamed_from: Option<&'static str>,
+    /// name of the table which primary key is stored in this column
+    pub references: Option<&'static str>,
+    pub on_delete_cascade: bool,
+}
+
+impl ColumnSchema {
+    /// Whether the stored text is one of the [`TableEnum`] variants of the column
+    pub fn allows_variant(&self, stored: &str) -> bool {
+        self.variants
+            .iter()
+            .any(|variant| match variant.strip_suffix("..)") {
+                Some(prefix) => stored.starts_with(prefix) && stored.ends_with(')'),
+                None => stored == *variant,
+            })
+    }
+}
+
+/// Enum stored in `#[enum_column]`s as the text of its variant: `Name` for unit variants
+/// and `Name(value)` for the ones holding a single value which implements `Display` and `FromStr`
+pub trait TableEnum: Sized {
+    /// Stored forms of the variants with `..` in place of the values
+    const VARIANTS: &'static [&'static str];
+    fn to_variant(&self) -> String;
+    fn from_variant(variant: &str) -> Result<Self>;
+}
+
+/// Describes [`Table`]-derived secondary index
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct IndexSchema {
+    pub name: &'static str,
+    /// GlueSQL indexes hold a single expression so only the leading column is indexed
+    pub columns: &'static [&'static str],
 }
 
 pub type TableSchema = &'static dyn TableSchemaTrait;
@@ -29,32 +75,73 @@ pub type TableSchema = &'static dyn TableSchemaTrait;
 pub trait TableSchemaTrait: Sync {
     fn name(&self) -> &'static str;
     fn columns(&self) -> ColumnSchemas;
+    fn dropped_columns(&self) -> &'static [&'static str];
+    fn indexes(&self) -> &'static [IndexSchema];
     fn relative_path(&self) -> &'static str;
     fn full_path(&self) -> &'static str;
-    async fn get_all(&self) -> Result<Vec<Vec<String>>>;
+    async fn count(&self) -> Result<usize>;
+    async fn get_page(&self, offset: usize, limit: usize) -> Result<Vec<Vec<String>>>;
     async fn get_row_by_id(&self, id: String) -> Result<Vec<String>>;
+    async fn get_row_history(&self, id: String) -> Result<Vec<RowVersion<Vec<String>>>>;
+    async fn search(&self, query: String, limit: usize) -> Result<Vec<Vec<String>>>;
     async fn save(&self, req: Request) -> Result<String>;
     async fn remove(&self, req: Request) -> Result;
+    async fn remove_where(&self, filter: sql::ExprNode<'static>) -> Result;
+    /// Whether the table is marked with `#[table(sync(...))]`
+    fn synced(&self) -> bool;
+    /// Encoded changes since the transaction for the service workers, see [`Table::SYNC`]
+    async fn sync_pull(&self, requester: &SyncRequester, since: Option<u64>) -> Result<Vec<u8>>;
+    /// Applies encoded writes made by a service worker and encodes the rejected ones, see [`Table::SYNC`]
+    async fn sync_push(&self, requester: &SyncRequester, writes: Vec<u8>) -> Result<Vec<u8>>;
 }
 
 /// Derived interface to interact with structs as tables of their values
 #[async_trait]
-pub trait Table: Sized + Send {
+pub trait Table: Sized + Send + Validate {
     const TABLE_NAME: &'static str;
     const COLUMN_SCHEMAS: ColumnSchemas;
 
-    type Key: std::fmt::Display + Send + Clone;
+    /// Type of the primary key, tuple of the pkey columns' types if there are several of them
+    type Key: std::fmt::Debug + Send + Clone + PartialEq;
+
+    /// Set by `#[table(sync(authorize = ...))]`: service workers which register the table pull its changes from the host
+    /// and push the local writes once the host is reachable and serves `/sync` routes with `.add_sync()`
+    const SYNC: bool = false;
 
     fn schema() -> TableSchema;
 
     fn into_row(&self) -> Result<sql::ExprList<'static>>;
+    /// Row of the inserted item with `#[created_at]` and `#[updated_at]` columns set to the current time
+    fn new_row(&self) -> Result<sql::ExprList<'static>> {
+        self.into_row()
+    }
+    /// Row of the item replacing the stored one which keeps its `#[created_at]` columns and refreshes `#[updated_at]` ones
+    fn replacing_row(&self, stored: &Self) -> Result<sql::ExprList<'static>> {
+        let _ = stored;
+        self.into_row()
+    }
     fn from_row(row: Vec<sql::Value>) -> Result<Self>;
     fn from_rows(rows: Vec<Vec<sql::Value>>) -> Result<Vec<Self>> {
         rows.into_iter().map(Self::from_row).collect()
     }
 
-    fn get_pkey(&self) -> &Self::Key;
+    fn get_pkey(&self) -> Self::Key;
     fn pkey_filter<'a, 'b>(pkey: &'a Self::Key) -> sql::ExprNode<'b>;
+    fn pkeys_filter(pkeys: &[Self::Key]) -> sql::ExprNode<'static>;
+
+    /// Picks the row to keep when the host's row was changed since the service worker's write was based on it,
+    /// `None` stands for the removed row. Client's write wins by default, see `#[table(sync(...))]` for other policies
+    fn resolve_conflict(server: Option<Self>, client: Option<Self>) -> Option<Self> {
+        let _ = server;
+        client
+    }
+
+    /// Whether the requester of the `/sync` routes can pull the row or push writes into it, set by
+    /// `#[table(sync(authorize = my_fn))]` and denies everything by default
+    fn authorize_sync(requester: &SyncRequester, row: &Self) -> bool {
+        let _ = (requester, row);
+        false
+    }
 
     fn select() -> SelectNode<'static> {
         sql::table(Self::TABLE_NAME).select()
@@ -72,14 +159,213 @@ pub trait Table: Sized + Send {
         sql::table(Self::TABLE_NAME).update()
     }
 
-    async fn save(&self) -> Result<&Self>;
+    /// Inserts the item or replaces the stored row with the same primary key, items which fail [`Validate`] checks are refused.
+    /// Tables with a `#[version_column]` replace only the row of the same version and increment it along with
+    /// the version of the item, returning [`Error::StaleWrite`] otherwise
+    async fn save(&mut self) -> Result<&mut Self>;
+
+    /// Checks that the rows referenced by `#[references(...)]` columns exist
+    async fn check_references(&self) -> Result {
+        OK
+    }
+
+    /// Saves all the items in a single transaction, stored rows with the same primary keys are replaced.
+    /// The last of the items with the same primary key wins, versions of the saved items are incremented like with [`Table::save`]
+    async fn save_many(items: &mut [Self]) -> Result
+    where
+        Self: Sync,
+    {
+        if items.is_empty() {
+            return OK;
+        }
+        // keys are only comparable so their debug forms identify the duplicates
+        let mut positions = std::collections::HashMap::new();
+        let mut unique: Vec<usize> = Vec::with_capacity(items.len());
+        for (i, item) in items.iter().enumerate() {
+            match positions.entry(format!("{:?}", item.get_pkey())) {
+                std::collections::hash_map::Entry::Occupied(entry) => unique[*entry.get()] = i,
+                std::collections::hash_map::Entry::Vacant(entry) => {
+                    entry.insert(unique.len());
+                    unique.push(i);
+                }
+            }
+        }
+        DB.in_transaction(async move {
+            // versions of the stored rows are compared one by one
+            if Self::COLUMN_SCHEMAS.iter().any(|col| col.version) {
+                for i in unique {
+                    items[i].save().await?;
+                }
+                return OK;
+            }
+            let unique: Vec<&Self> = unique.into_iter().map(|i| &items[i]).collect();
+            for item in &unique {
+                item.validate()?;
+                item.check_references().await?;
+            }
+            let keys: Vec<Self::Key> = unique.iter().map(|item| item.get_pkey()).collect();
+            let rows = match Self::COLUMN_SCHEMAS.iter().any(|col| col.timestamp) {
+                true => {
+                    let stored = Self::select().filter(Self::pkeys_filter(&keys)).rows().await?;
+                    let stored: std::collections::HashMap<String, Self> = Self::from_rows(stored)?
+                        .into_iter()
+                        .map(|item| (format!("{:?}", item.get_pkey()), item))
+                        .collect();
+                    unique
+                        .iter()
+                        .map(|item| match stored.get(&format!("{:?}", item.get_pkey())) {
+                            Some(stored) => item.replacing_row(stored),
+                            None => item.new_row(),
+                        })
+                        .collect::<Result<Vec<_>>>()?
+                }
+                false => unique
+                    .iter()
+                    .map(|item| item.into_row())
+                    .collect::<Result<Vec<_>>>()?,
+            };
+            Self::delete().filter(Self::pkeys_filter(&keys)).exec().await?;
+            Self::insert().values(rows).exec().await?;
+            OK
+        })
+        .await
+    }
+
+    /// Inserts all the items with a single statement, refusing them like [`Table::save`] if any of them fails
+    /// the [`Validate`] checks or references missing rows
+    async fn insert_many(items: &[Self]) -> Result
+    where
+        Self: Sync,
+    {
+        if items.is_empty() {
+            return OK;
+        }
+        let rows = items
+            .iter()
+            .map(Self::new_row)
+            .collect::<Result<Vec<_>>>()?;
+        DB.in_transaction(async move {
+            for item in items {
+                item.validate()?;
+                item.check_references().await?;
+            }
+            Self::insert().values(rows).exec().await?;
+            OK
+        })
+        .await
+    }
+
+    /// Deletes all the rows with the provided primary keys in a single transaction
+    async fn delete_many(pkeys: &[Self::Key]) -> Result
+    where
+        Self::Key: Sync,
+    {
+        if pkeys.is_empty() {
+            return OK;
+        }
+        DB.in_transaction(async move {
+            for pkey in pkeys {
+                Self::remove_references(pkey).await?;
+            }
+            Self::delete()
+                .filter(Self::pkeys_filter(pkeys))
+                .exec()
+                .await?;
+            OK
+        })
+        .await
+    }
 
     async fn select_all() -> Result<Vec<Self>> {
         Self::from_rows(Self::select().rows().await?)
     }
 
+    /// Selects rows matching the filter built from the typed columns like `Self::cols.field.eq(value)`
+    async fn select_where(filter: Filter<Self>) -> Result<Vec<Self>> {
+        Self::from_rows(Self::select().filter(filter.into_expr()?).rows().await?)
+    }
+
+    /// Ascending order of the primary key columns
+    fn pkey_order() -> Order<Self> {
+        Self::COLUMN_SCHEMAS
+            .iter()
+            .filter(|col| col.pkey)
+            .map(|col| Order::new(col.name, false))
+            .reduce(Order::then)
+            .expect("Table must have a primary key")
+    }
+
+    async fn count() -> Result<usize> {
+        let rows = Self::select().project("COUNT(*)").rows().await?;
+        match rows.first().and_then(|row| row.first()) {
+            Some(sql::Value::I64(count)) => Ok(*count as usize),
+            _ => Err(e!("unexpected count result: {rows:?}")),
+        }
+    }
+
+    /// Number of rows matching the filter built from the typed columns like `Self::cols.field.eq(value)`
+    async fn count_where(filter: Filter<Self>) -> Result<usize> {
+        let rows = _aggregate("COUNT(*)", None, Some(filter)).await?;
+        Ok(_aggregated(rows)?.unwrap_or_default())
+    }
+
+    /// Selects up to `limit` rows after skipping `offset` ones in the provided order like `Self::cols.field.asc()`
+    async fn select_page(limit: usize, offset: usize, order_by: Order<Self>) -> Result<Vec<Self>> {
+        let rows = Self::select()
+            .order_by(order_by.into_exprs().as_str())
+            .offset(sql::num(offset as u64))
+            .limit(sql::num(limit as u64))
+            .rows()
+            .await?;
+        Self::from_rows(rows)
+    }
+
+    /// Selects up to `limit` rows following the cursor like `Self::cols.field.after(Some(&last_row))`
+    ///
+    /// Unlike offsets, cursors skip the previous rows using the filter which avoids scanning them with indexed columns
+    async fn select_after(cursor: Cursor<Self>, limit: usize) -> Result<Vec<Self>> {
+        let Cursor { filter, order } = cursor;
+        let order = order.into_exprs();
+        let limit = sql::num(limit as u64);
+        let rows = match filter {
+            Some(filter) => {
+                Self::select()
+                    .filter(filter.into_expr()?)
+                    .order_by(order.as_str())
+                    .limit(limit)
+                    .rows()
+                    .await?
+            }
+            None => {
+                Self::select()
+                    .order_by(order.as_str())
+                    .limit(limit)
+                    .rows()
+                    .await?
+            }
+        };
+        Self::from_rows(rows)
+    }
+
+    /// Selects the rows as they were at the moment or right after the transaction.
+    /// Requires persistent storage, replaced versions are kept for `history_retention_secs` from the package metadata
+    async fn select_as_of(at: impl Into<AsOf> + Send) -> Result<Vec<Self>> {
+        DB.select_as_of(at.into()).await
+    }
+
+    /// Versions of the row from the oldest to the current one which are still retained
+    async fn history(&self) -> Result<Vec<RowVersion<Self>>>
+    where
+        Self: Sync,
+    {
+        DB.history(self).await
+    }
+
     async fn insert_self(&self) -> Result {
-        Self::insert().values(vec![self.into_row()?]).exec().await?;
+        Self::insert()
+            .values(vec![self.new_row()?])
+            .exec()
+            .await?;
         OK
     }
 
@@ -94,18 +380,621 @@ pub trait Table: Sized + Send {
         }
     }
 
+    /// Applies `on_delete` policies of the columns referencing this table
+    async fn remove_references(_pkey: &Self::Key) -> Result {
+        OK
+    }
+
     async fn delete_by_pkey(pkey: Self::Key) -> Result {
+        Self::remove_references(&pkey).await?;
         let payload = Self::delete()
             .filter(Self::pkey_filter(&pkey))
             .exec()
             .await?;
         match payload {
             sql::Payload::Delete(_) => OK,
-            _ => return Err(e!("Couldn't delete item with pkey = {pkey}")),
+            _ => return Err(e!("Couldn't delete item with pkey = {pkey:?}")),
         }
     }
 
     async fn remove(&self) -> Result {
-        Self::delete_by_pkey(self.get_pkey().clone()).await
+        Self::delete_by_pkey(self.get_pkey()).await
+    }
+
+    /// Subscribes to the inserts, updates and deletes of the rows which are published once they are committed
+    fn subscribe() -> Changes<Self>
+    where
+        Self: Clone + Sync + 'static,
+    {
+        super::changes::subscribe()
+    }
+
+    /// Saves the item as a part of the [`Db::transaction`]
+    async fn save_in(&mut self, tx: &Tx) -> Result<&mut Self>
+    where
+        Self: Sync,
+    {
+        tx.run(self.save()).await
+    }
+
+    /// Removes the item as a part of the [`Db::transaction`]
+    async fn remove_in(&self, tx: &Tx) -> Result
+    where
+        Self: Sync,
+    {
+        tx.run(self.remove()).await
+    }
+}
+
+/// Implemented by the tables with a single primary key column which can be referenced by `#[references(...)]`
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has a composite primary key so it can't be referenced",
+    label = "referenced by `#[references(...)]`"
+)]
+pub trait _SingleKeyTable: Table {
+    /// Type of the only primary key column
+    type SingleKey;
+    fn _key(key: Self::SingleKey) -> Self::Key;
+    fn _single_key(&self) -> Self::SingleKey;
+}
+
+/// Joins string representations of the primary key columns into the id used by the admin routes
+#[doc(hidden)]
+pub fn _pkey_id(mut parts: Vec<String>) -> String {
+    match parts.len() {
+        1 => parts.pop().expect("checked length"),
+        _ => to_json_string(&parts).expect("list of strings should serialize"),
+    }
+}
+
+/// SQL text of the `#[default_column(...)]` values
+#[doc(hidden)]
+pub fn _sql_text(value: sql::ExprNode<'static>) -> Result<String> {
+    use gluesql::core::ast::{Expr, ToSql};
+    Ok(Expr::try_from(value)?.to_sql())
+}
+
+/// Splits the admin routes id back into string representations of the primary key columns
+#[doc(hidden)]
+pub fn _pkey_parts(id: &str, count: usize) -> Result<Vec<String>> {
+    let parts = match count {
+        1 => vec![id.to_owned()],
+        _ => from_json_str::<Vec<String>>(id)?,
+    };
+    if parts.len() != count {
+        return Err(e!("expected {count} primary key values in id = {id}"));
+    }
+    Ok(parts)
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_memberships")]
+    struct Membership {
+        #[pkey_column]
+        user: Uuid,
+        #[pkey_column]
+        project: u64,
+        role: String,
+    }
+
+    fn member(user: Uuid, project: u64, role: &str) -> Membership {
+        Membership {
+            user,
+            project,
+            role: role.to_owned(),
+        }
+    }
+
+    fn is_conflict<T: std::fmt::Debug>(result: Result<T>) -> bool {
+        matches!(result, Err(Error::Conflict { .. }))
+    }
+
+    #[test]
+    fn composite_keys_are_unique() {
+        test_db(&[Membership::schema()], async {
+            let user = Uuid::now_v7();
+            member(user, 1, "owner").save().await?;
+            member(user, 2, "viewer").insert_self().await?;
+
+            assert!(is_conflict(member(user, 1, "editor").insert_self().await));
+            assert!(is_conflict(
+                Membership::insert_many(&[member(user, 3, "a"), member(user, 1, "b")]).await
+            ));
+            assert!(is_conflict(
+                Membership::insert_many(&[member(user, 4, "a"), member(user, 4, "b")]).await
+            ));
+            assert_eq!(Membership::count().await?, 2);
+
+            member(user, 1, "editor").save().await?;
+            let stored = Membership::select_by_pkey((user, 1)).await?;
+            assert_eq!(stored, Some(member(user, 1, "editor")));
+
+            Membership::delete_by_pkey((user, 1)).await?;
+            assert_eq!(Membership::select_all().await?, vec![member(user, 2, "viewer")]);
+            OK
+        });
+    }
+
+    #[test]
+    fn composite_keys_stay_unique_on_updates() {
+        test_db(&[Membership::schema()], async {
+            let user = Uuid::now_v7();
+            Membership::insert_many(&[member(user, 1, "owner"), member(user, 2, "viewer")]).await?;
+            let move_project = |from: u64, to: u64| {
+                Membership::update()
+                    .filter(sql::col("user").eq(sql::uuid(user.to_string())))
+                    .filter(sql::col("project").eq(from.to_string()))
+                    .set("project", to.to_string())
+                    .exec()
+            };
+
+            assert!(is_conflict(move_project(2, 1).await));
+            move_project(2, 3).await?;
+            assert_eq!(Membership::select_by_pkey((user, 2)).await?, None);
+            let moved = Membership::select_by_pkey((user, 3)).await?;
+            assert_eq!(moved, Some(member(user, 3, "viewer")));
+            assert!(is_conflict(member(user, 3, "editor").insert_self().await));
+            let rows = Membership::select()
+                .filter(sql::col("user").eq(sql::uuid(user.to_string())))
+                .rows()
+                .await?;
+            assert_eq!(rows.len(), 2);
+            OK
+        });
+    }
+
+    #[test]
+    fn key_only_tables_are_saved() {
+        #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[table(name = "test_follows")]
+        struct Follow {
+            #[pkey_column]
+            follower: u32,
+            #[pkey_column]
+            followed: u32,
+        }
+
+        test_db(&[Follow::schema()], async {
+            let mut follow = Follow {
+                follower: 1,
+                followed: 2,
+            };
+            follow.save().await?;
+            follow.save().await?;
+            assert_eq!(Follow::select_all().await?, vec![follow]);
+            OK
+        });
+    }
+
+    #[test]
+    fn composite_keys_are_deleted_together() {
+        #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[table(name = "test_daily_counters")]
+        struct DailyCounter {
+            #[pkey_column]
+            day: u32,
+            #[pkey_column]
+            counter: u64,
+            value: i64,
+        }
+
+        test_db(&[DailyCounter::schema()], async {
+            let counters: Vec<DailyCounter> = [(1, 1), (1, 2), (2, 1)]
+                .into_iter()
+                .map(|(day, counter)| DailyCounter { day, counter, value: 0 })
+                .collect();
+            DailyCounter::insert_many(&counters).await?;
+
+            DailyCounter::delete_many(&[(1, 2), (2, 1), (3, 3)]).await?;
+            assert_eq!(DailyCounter::select_all().await?, vec![counters[0].clone()]);
+            OK
+        });
+    }
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_owners")]
+    struct Owner {
+        id: Uuid,
+        name: String,
+    }
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_owned_tasks")]
+    struct OwnedTask {
+        id: Uuid,
+        #[references(Owner)]
+        owner: Uuid,
+        #[references(Owner, on_delete = cascade)]
+        reviewer: Option<Uuid>,
+    }
+
+    #[test]
+    fn references_are_checked_and_loaded() {
+        test_db(&[Owner::schema(), OwnedTask::schema()], async {
+            let mut owner = Owner {
+                id: Uuid::now_v7(),
+                name: "owner".to_owned(),
+            };
+            let mut reviewer = Owner {
+                id: Uuid::now_v7(),
+                name: "reviewer".to_owned(),
+            };
+            let mut task = OwnedTask {
+                id: Uuid::now_v7(),
+                owner: owner.id,
+                reviewer: Some(reviewer.id),
+            };
+            assert!(matches!(
+                task.save().await,
+                Err(Error::ForeignKeyViolation { .. })
+            ));
+            assert!(matches!(
+                OwnedTask::insert_many(&[task.clone()]).await,
+                Err(Error::ForeignKeyViolation { .. })
+            ));
+
+            owner.save().await?;
+            reviewer.save().await?;
+            task.save().await?;
+            assert_eq!(task.owner().await?, Some(owner.clone()));
+            assert_eq!(task.reviewer().await?, Some(reviewer.clone()));
+            assert_eq!(owner.owned_tasks_by_owner().await?, vec![task.clone()]);
+            assert!(owner.owned_tasks_by_reviewer().await?.is_empty());
+
+            // restricted by the owner column and cascaded by the reviewer one
+            assert!(matches!(
+                owner.remove().await,
+                Err(Error::ForeignKeyViolation { .. })
+            ));
+            reviewer.remove().await?;
+            assert!(OwnedTask::select_all().await?.is_empty());
+
+            task.reviewer = None;
+            task.save().await?;
+            assert_eq!(owner.owned_tasks_by_owner().await?, vec![task]);
+            OK
+        });
+    }
+
+    #[test]
+    fn save_many_replaces_stored_rows_and_dedupes() {
+        #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[table(name = "test_notes")]
+        struct Note {
+            id: u32,
+            text: String,
+            #[created_at]
+            created: NaiveDateTime,
+        }
+
+        let note = |id, text: &str| Note {
+            id,
+            text: text.to_owned(),
+            created: NaiveDateTime::default(),
+        };
+
+        test_db(&[Note::schema()], async move {
+            note(1, "stored").save().await?;
+            note(2, "untouched").save().await?;
+            let created = Note::select_by_pkey(1).await?.unwrap().created;
+
+            let mut items = [note(1, "updated"), note(3, "first"), note(3, "last")];
+            Note::save_many(&mut items).await?;
+
+            let mut stored = Note::select_all().await?;
+            stored.sort_by_key(|note| note.id);
+            let texts: Vec<_> = stored.iter().map(|note| note.text.as_str()).collect();
+            assert_eq!(texts, ["updated", "untouched", "last"]);
+            assert_eq!(stored[0].created, created);
+            assert_ne!(stored[2].created, NaiveDateTime::default());
+            OK
+        });
+    }
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_typed_values")]
+    struct Typed {
+        id: u32,
+        date: NaiveDate,
+        time: NaiveTime,
+        at: DateTime<Utc>,
+        amount: Decimal,
+        bytes: Vec<u8>,
+        labels: std::collections::HashMap<String, String>,
+        json: JsonValue,
+        due: Option<NaiveDate>,
+        price: Option<Decimal>,
+    }
+
+    #[test]
+    fn native_types_round_trip() {
+        test_db(&[Typed::schema()], async {
+            let at = NaiveDate::from_ymd_opt(2024, 2, 29)
+                .unwrap()
+                .and_hms_milli_opt(23, 59, 58, 123)
+                .unwrap()
+                .and_utc();
+            let mut typed = Typed {
+                id: 1,
+                date: at.date_naive(),
+                time: at.time(),
+                at,
+                amount: "12.345".parse().unwrap(),
+                bytes: vec![0, 7, 255],
+                labels: [("quoted".to_owned(), "it's \"fine\"".to_owned())].into(),
+                json: json!({ "nested": { "list": [1, 2] }, "flag": true }),
+                due: None,
+                price: None,
+            };
+            typed.insert_self().await?;
+            assert_eq!(Typed::select_by_pkey(1).await?, Some(typed.clone()));
+
+            typed.due = Some(typed.date);
+            typed.price = Some("-0.5".parse().unwrap());
+            typed.bytes.clear();
+            typed.labels.clear();
+            typed.save().await?;
+            assert_eq!(Typed::select_by_pkey(1).await?, Some(typed));
+            OK
+        })
+    }
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_versioned_docs")]
+    struct Doc {
+        id: u32,
+        text: String,
+        #[version_column]
+        version: u64,
+    }
+
+    fn is_stale<T: std::fmt::Debug>(result: Result<T>) -> bool {
+        matches!(result, Err(Error::StaleWrite { .. }))
+    }
+
+    #[test]
+    fn versions_follow_saves_and_updates() {
+        test_db(&[Doc::schema()], async {
+            let mut doc = Doc {
+                id: 1,
+                text: "first".to_owned(),
+                version: 0,
+            };
+            doc.save().await?;
+            doc.text = "second".to_owned();
+            doc.save().await?;
+            doc.text = "third".to_owned();
+            doc.save().await?;
+            assert_eq!(doc.version, 2);
+            doc.update_text("fourth".to_owned()).await?;
+            assert_eq!(doc.version, 3);
+            assert_eq!(Doc::select_by_pkey(1).await?, Some(doc.clone()));
+
+            let mut stale = Doc {
+                version: 1,
+                ..doc.clone()
+            };
+            assert!(is_stale(stale.update_text("lost".to_owned()).await));
+            assert_eq!((stale.text.as_str(), stale.version), ("fourth", 1));
+            stale.text = "lost".to_owned();
+            assert!(is_stale(stale.save().await));
+            assert_eq!(stale.version, 1);
+            assert_eq!(Doc::select_by_pkey(1).await?, Some(doc));
+            OK
+        })
+    }
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_validated_accounts")]
+    struct Account {
+        id: u32,
+        #[validate(length(min = 1))]
+        name: String,
+        #[default_column(7)]
+        credits: u32,
+        #[created_at]
+        created: NaiveDateTime,
+    }
+
+    #[test]
+    fn invalid_rows_are_refused() {
+        test_db(&[Account::schema()], async {
+            let mut account = Account {
+                id: 1,
+                name: String::new(),
+                credits: 0,
+                created: Default::default(),
+            };
+            let Err(Error::Validation(errors)) = account.save().await else {
+                panic!("expected empty name to be refused")
+            };
+            assert_eq!(errors.field("name").len(), 1);
+            let result = Account::save_many(&mut [account.clone()]).await;
+            assert!(matches!(result, Err(Error::Validation(_))));
+            let result = Account::insert_many(&[account.clone()]).await;
+            assert!(matches!(result, Err(Error::Validation(_))));
+            assert_eq!(Account::select_by_pkey(1).await?, None);
+
+            account.name = "ann".to_owned();
+            account.save().await?;
+            assert_eq!(Account::select_by_pkey(1).await?.map(|a| a.name), Some("ann".to_owned()));
+            OK
+        })
+    }
+
+    #[test]
+    fn editor_fills_default_and_timestamp_fields() {
+        test_db(&[Account::schema()], async {
+            let req = Request::builder()
+                .method(Method::PUT)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"id":2,"name":"bob"}"#))
+                .somehow()?;
+            assert_eq!(Account::schema().save(req).await?, "2");
+            let stored = Account::select_by_pkey(2).await?.expect("created account");
+            assert_eq!(stored.credits, 7);
+            assert_ne!(stored.created, NaiveDateTime::default());
+            OK
+        })
+    }
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_labels")]
+    struct Label {
+        #[column(name = "label_id")]
+        id: u32,
+        #[column(name = "label_text")]
+        #[index_column]
+        text: String,
+    }
+
+    #[test]
+    fn custom_names_are_used_by_queries() {
+        test_db(&[Label::schema()], async {
+            assert_eq!(Label::TABLE_NAME, "test_labels");
+            let names: Vec<_> = Label::schema().columns().iter().map(|c| (c.name, c.field_name)).collect();
+            assert_eq!(names, vec![("label_id", "id"), ("label_text", "text")]);
+
+            let mut label = Label {
+                id: 1,
+                text: "first".to_owned(),
+            };
+            label.save().await?;
+            label.update_text("renamed".to_owned()).await?;
+            assert_eq!(Label::select_by_text(&"renamed".to_owned()).await?, vec![label.clone()]);
+            assert_eq!(Label::select_where(Label::cols.id.eq(1u32)).await?, vec![label]);
+
+            let rows = Label::select().project("label_text").rows().await?;
+            assert_eq!(rows, vec![vec![sql::Value::Str("renamed".to_owned())]]);
+            OK
+        })
+    }
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_paged_posts")]
+    struct Post {
+        id: u32,
+        topic: String,
+    }
+
+    fn post_ids(posts: Vec<Post>) -> Vec<u32> {
+        posts.into_iter().map(|post| post.id).collect()
+    }
+
+    #[test]
+    fn pages_and_cursors_walk_ordered_rows() {
+        test_db(&[Post::schema()], async {
+            let posts = (1..=7).map(|id| Post {
+                id,
+                topic: ["b", "a"][id as usize % 2].to_owned(),
+            });
+            Post::insert_many(&posts.collect::<Vec<_>>()).await?;
+            assert_eq!(Post::count().await?, 7);
+
+            let first = Post::select_page(3, 0, Post::cols.id.desc()).await?;
+            assert_eq!(post_ids(first), vec![7, 6, 5]);
+            let last = Post::select_page(3, 6, Post::cols.id.desc()).await?;
+            assert_eq!(post_ids(last), vec![1]);
+            let by_topic = Post::cols.topic.asc().then(Post::cols.id.desc());
+            assert_eq!(post_ids(Post::select_page(4, 0, by_topic).await?), vec![7, 5, 3, 1]);
+
+            let walk = |cursor: fn(Option<&Post>) -> Cursor<Post>| async move {
+                let mut walked = vec![];
+                let mut last = None;
+                loop {
+                    let page = Post::select_after(cursor(last.as_ref()), 3).await?;
+                    let Some(next) = page.last().cloned() else {
+                        break;
+                    };
+                    walked.extend(post_ids(page));
+                    last = Some(next);
+                }
+                Ok::<_, Error>(walked)
+            };
+            assert_eq!(walk(|last| Post::cols.id.after(last)).await?, vec![1, 2, 3, 4, 5, 6, 7]);
+            // pages end between the rows with equal topics
+            let by_topic = walk(|last| Post::cols.topic.after(last)).await?;
+            assert_eq!(by_topic, vec![1, 3, 5, 7, 2, 4, 6]);
+            let by_topic = walk(|last| Post::cols.topic.before(last)).await?;
+            assert_eq!(by_topic, vec![6, 4, 2, 7, 5, 3, 1]);
+
+            let third = Post::select_by_pkey(3).await?;
+            let page = Post::select_after(Post::cols.id.before(third.as_ref()), 5).await?;
+            assert_eq!(post_ids(page), vec![2, 1]);
+            OK
+        })
+    }
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_stamped_tasks")]
+    struct StampedTask {
+        id: u32,
+        title: String,
+        #[created_at]
+        created: NaiveDateTime,
+        #[updated_at]
+        updated: DateTime<Utc>,
+        #[default_column(3)]
+        priority: u8,
+    }
+
+    #[test]
+    fn timestamps_follow_writes_and_defaults_fill_added_columns() {
+        test_db(&[], async {
+            DB.query(
+                "CREATE TABLE test_stamped_tasks (id UINT32 PRIMARY KEY, title TEXT NOT NULL,
+                created TIMESTAMP NOT NULL, updated TIMESTAMP NOT NULL)",
+            )
+            .await?;
+            DB.query("INSERT INTO test_stamped_tasks VALUES (1, 'stored', '2024-01-01', '2024-01-01')")
+                .await?;
+            use gluesql::core::store::Store;
+            let stored = await_blocking(DB.storage().fetch_schema(StampedTask::TABLE_NAME))?;
+            let columns = stored.and_then(|s| s.column_defs).expect("stored columns");
+            let changes = super::super::schema_diff::diff(StampedTask::schema(), &columns);
+            let changes = changes.expect("added column");
+            for change in changes {
+                DB.in_transaction(change.apply(StampedTask::TABLE_NAME)).await?;
+            }
+            let stored = StampedTask::select_by_pkey(1).await?.expect("stored task");
+            assert_eq!(stored.priority, 3);
+
+            let before = Utc::now();
+            let mut task = StampedTask {
+                id: 2,
+                title: "new".to_owned(),
+                created: Default::default(),
+                updated: Default::default(),
+                priority: 1,
+            };
+            task.save().await?;
+            let inserted = StampedTask::select_by_pkey(2).await?.expect("inserted task");
+            assert!(inserted.created >= before.naive_utc() && inserted.updated >= before);
+
+            let mut task = inserted.clone();
+            task.update_title("renamed".to_owned()).await?;
+            assert!(task.updated >= inserted.updated);
+            task.title = "saved".to_owned();
+            task.save().await?;
+            let saved = StampedTask::select_by_pkey(2).await?.expect("saved task");
+            assert_eq!(saved.created, inserted.created);
+            assert!(saved.updated >= task.updated);
+            assert_eq!(saved.title, "saved");
+            OK
+        })
+    }
+
+    #[test]
+    fn composite_ids_round_trip() {
+        let parts = vec!["a/b".to_owned(), "\"c\"".to_owned()];
+        let id = _pkey_id(parts.clone());
+        assert_eq!(_pkey_parts(&id, 2).unwrap(), parts);
+        assert_eq!(_pkey_parts("single", 1).unwrap(), vec!["single"]);
+        assert!(_pkey_parts(&id, 3).is_err());
     }
 }
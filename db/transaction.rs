@@ -0,0 +1,236 @@
+use crate::*;
+
+use std::{
+    cell::Cell,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+thread_local! {
+    static TX_SCOPE: Cell<u64> = const { Cell::new(0) };
+}
+static NEXT_TX_SCOPE: AtomicU64 = AtomicU64::new(1);
+
+/// Id of the transaction scope the current task is running in, 0 if none
+pub(crate) fn tx_scope() -> u64 {
+    TX_SCOPE.with(Cell::get)
+}
+
+/// Future which runs inside of its own transaction scope, like a task-local value that also works in the service worker
+pub(crate) struct TxScoped<F> {
+    scope: u64,
+    fut: Pin<Box<F>>,
+}
+
+impl<F: Future> TxScoped<F> {
+    pub(crate) fn new(fut: F) -> Self {
+        Self {
+            scope: NEXT_TX_SCOPE.fetch_add(1, Ordering::SeqCst),
+            fut: Box::pin(fut),
+        }
+    }
+}
+
+impl<F: Future> Future for TxScoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        struct Restore(u64);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                TX_SCOPE.with(|scope| scope.set(self.0));
+            }
+        }
+        let _restore = Restore(TX_SCOPE.with(|scope| scope.replace(self.scope)));
+        self.fut.as_mut().poll(cx)
+    }
+}
+
+/// Runs the statement in the current transaction scope or in its own one
+/// so that concurrent statements outside of transactions don't publish or discard each other's changes
+pub(crate) async fn statement_scope<F: Future>(fut: F) -> F::Output {
+    match tx_scope() {
+        0 => TxScoped::new(fut).await,
+        _ => fut.await,
+    }
+}
+
+/// Handle of the transaction started with [`Db::transaction`]
+#[derive(Debug, Clone, Copy)]
+pub struct Tx {
+    scope: u64,
+}
+
+impl Tx {
+    /// Runs the future as a part of the transaction, fails if it's awaited outside of the transaction's closure
+    pub async fn run<T>(&self, fut: impl Future<Output = Result<T>>) -> Result<T> {
+        if tx_scope() != self.scope {
+            return Err(e!("transaction handle is used outside of its transaction"));
+        }
+        fut.await
+    }
+}
+
+impl Db {
+    /// Runs the closure inside of a transaction which is committed if it returns `Ok` and rolled back on `Err` or panic.
+    /// Queries awaited by the closure join the transaction while the others wait for it to finish,
+    /// and nested transactions are merged into the outer one.
+    ///
+    /// ```rust,ignore
+    /// DB.transaction(|tx| async move {
+    ///     a.save_in(&tx).await?;
+    ///     b.remove_in(&tx).await?;
+    ///     OK
+    /// })
+    /// .await?;
+    /// ```
+    pub async fn transaction<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(Tx) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.in_transaction(async {
+            let tx = Tx { scope: tx_scope() };
+            f(tx).await
+        })
+        .await
+    }
+
+    /// Runs the future inside of a storage transaction or joins the current one
+    pub(crate) async fn in_transaction<T>(
+        &self,
+        fut: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        // nested transactions are merged into the outer one
+        if tx_scope() != 0 {
+            return fut.await;
+        }
+        let mut scope = 0;
+        let mut panic = None;
+        let fut = async {
+            scope = tx_scope();
+            match AssertUnwindSafe(fut).catch_unwind().await {
+                Ok(result) => result,
+                Err(payload) => {
+                    panic = Some(payload);
+                    Err(e!("transaction panicked"))
+                }
+            }
+        };
+        let result = match self.storage() {
+            DbStorage::Memory(storage) => storage.transaction(fut).await,
+            DbStorage::Persistent(storage) => storage.transaction(fut).await,
+        };
+        // changes are published only once they are committed
+        if scope != 0 {
+            match result {
+                Ok(_) => super::changes::publish_pending(scope),
+                Err(_) => super::changes::discard_pending(scope),
+            }
+        }
+        // rolled back already so the panic can continue
+        if let Some(payload) = panic {
+            std::panic::resume_unwind(payload);
+        }
+        result
+    }
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+    use gluesql::core::store::Store;
+    use std::time::Duration;
+
+    #[derive(Table, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[table(name = "test_tx_items")]
+    struct TxItem {
+        id: Uuid,
+        name: String,
+    }
+
+    fn item() -> TxItem {
+        TxItem {
+            id: Uuid::now_v7(),
+            name: "item".to_owned(),
+        }
+    }
+
+    #[test]
+    fn panics_roll_back_transactions() {
+        test_db(&[TxItem::schema()], async {
+            let mut reverted = item();
+            let panicked = AssertUnwindSafe(DB.in_transaction(async {
+                reverted.save().await?;
+                panic!("handler panicked");
+                #[allow(unreachable_code)]
+                OK
+            }))
+            .catch_unwind()
+            .await;
+            assert!(panicked.is_err());
+            assert_eq!(TxItem::select_by_pkey(reverted.id).await?, None);
+
+            let mut kept = item();
+            kept.save().await?;
+            assert_eq!(TxItem::select_by_pkey(kept.id).await?, Some(kept));
+            OK
+        });
+    }
+
+    #[test]
+    fn dropped_transactions_are_rolled_back() {
+        test_db(&[TxItem::schema()], async {
+            let mut reverted = item();
+            let dropped = DB.transaction(|_| async {
+                reverted.save().await?;
+                std::future::pending::<()>().await;
+                OK
+            });
+            assert!(tokio::time::timeout(Duration::from_millis(50), dropped)
+                .await
+                .is_err());
+
+            let mut kept = item();
+            tokio::time::timeout(Duration::from_secs(5), kept.save())
+                .await
+                .map_err(|_| e!("the lock of the dropped transaction is still held"))??;
+            assert_eq!(TxItem::select_by_pkey(reverted.id).await?, None);
+            assert_eq!(TxItem::select_by_pkey(kept.id).await?, Some(kept));
+            OK
+        });
+    }
+
+    #[test]
+    fn failed_transactions_undo_their_writes() {
+        test_db(&[TxItem::schema()], async {
+            let (mut updated, mut removed, mut inserted) = (item(), item(), item());
+            updated.save().await?;
+            removed.save().await?;
+            let failed = DB.in_transaction(async {
+                let mut changed = updated.clone();
+                changed.name = "changed".to_owned();
+                changed.save().await?;
+                removed.remove().await?;
+                inserted.save().await?;
+                DB.query("CREATE TABLE test_tx_created (id INTEGER)")
+                    .await?;
+                Err::<(), _>(e!("failed"))
+            });
+            assert!(failed.await.is_err());
+
+            assert_eq!(TxItem::select_by_pkey(updated.id).await?, Some(updated));
+            assert_eq!(TxItem::select_by_pkey(removed.id).await?, Some(removed));
+            assert_eq!(TxItem::select_by_pkey(inserted.id).await?, None);
+            assert!(DB
+                .storage()
+                .fetch_schema("test_tx_created")
+                .await?
+                .is_none());
+            OK
+        });
+    }
+}
@@ -0,0 +1,546 @@
+use crate::*;
+
+use futures::lock::Mutex as AsyncMutex;
+use gluesql::{
+    core::{
+        ast::{ColumnDef, IndexOperator, OrderByExpr},
+        data::{CustomFunction as StructCustomFunction, Key, Schema},
+        error::Result as GlueResult,
+        store::{
+            AlterTable, CustomFunction, CustomFunctionMut, DataRow, Index, IndexMut, MetaIter,
+            Metadata, RowIter, Store, StoreMut, Transaction,
+        },
+    },
+    gluesql_memory_storage::Item,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+};
+
+/// Prefix of the stored entries which aren't tables
+const META_PREFIX: &str = "$";
+/// Key of the counter which generates keys of the rows in tables without primary keys
+const ID_COUNTER_KEY: &str = "$id_counter";
+/// Prefix of the stored table schemas followed by the table name
+const SCHEMA_PREFIX: &str = "schema/";
+/// Prefix of the stored rows followed by the table name, `/` and the hex of the row key
+const ROW_PREFIX: &str = "row/";
+
+/// Durable key-value store for the serialized schemas and rows of the [`WriteThroughStorage`]
+#[doc(hidden)]
+#[async_trait(?Send)]
+pub trait TableStore {
+    async fn read_all(&self) -> Result<Vec<(String, Vec<u8>)>>;
+    /// Removes the entries starting with the cleared prefixes, then puts the entries with values
+    /// and removes the ones without them, all at once
+    async fn write(&self, cleared: Vec<String>, entries: Vec<(String, Option<Vec<u8>>)>) -> Result;
+}
+
+/// Memory storage which loads the tables from the [`TableStore`] and writes the modified rows back into it
+#[doc(hidden)]
+#[derive(Clone, Debug)]
+pub struct WriteThroughStorage<S> {
+    memory: MemoryStorage,
+    store: Arc<S>,
+    modified: Arc<std::sync::Mutex<Modified>>,
+    meta: Arc<std::sync::Mutex<HashMap<String, Vec<u8>>>>,
+    loaded: Arc<AsyncMutex<bool>>,
+}
+
+/// Entries modified since the last write into the store
+#[derive(Debug, Default)]
+struct Modified {
+    /// tables which are written as a whole, like the ones with altered columns
+    tables: HashSet<String>,
+    /// tables which schemas changed while the rows stayed the same
+    schemas: HashSet<String>,
+    rows: HashMap<String, HashSet<Key>>,
+    meta: HashSet<String>,
+}
+
+impl Modified {
+    fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+            && self.schemas.is_empty()
+            && self.rows.is_empty()
+            && self.meta.is_empty()
+    }
+
+    fn extend(&mut self, other: Modified) {
+        self.tables.extend(other.tables);
+        self.schemas.extend(other.schemas);
+        for (table, keys) in other.rows {
+            self.rows.entry(table).or_default().extend(keys);
+        }
+        self.meta.extend(other.meta);
+    }
+}
+
+impl<S: TableStore> WriteThroughStorage<S> {
+    pub(crate) fn new(store: S) -> Self {
+        Self {
+            memory: MemoryStorage::default(),
+            store: Arc::new(store),
+            modified: Default::default(),
+            meta: Default::default(),
+            loaded: Default::default(),
+        }
+    }
+
+    /// Reads the stored tables into memory, only the first call loads them
+    pub(crate) async fn load(&self) -> Result {
+        let mut loaded = self.loaded.lock().await;
+        if *loaded {
+            return OK;
+        }
+        let entries = self.store.read_all().await?;
+        let mut rows = vec![];
+        self.memory
+            .committed(|db| {
+                for (key, value) in entries {
+                    if key == ID_COUNTER_KEY {
+                        db.id_counter = bincode::deserialize(&value)?;
+                    } else if key.starts_with(META_PREFIX) {
+                        self.meta.lock().expect("meta").insert(key, value);
+                    } else if let Some(table) = key.strip_prefix(SCHEMA_PREFIX) {
+                        let item = Item {
+                            schema: bincode::deserialize(&value)?,
+                            rows: Default::default(),
+                        };
+                        db.items.insert(table.to_owned(), item);
+                    } else if let Some(row_key) = key.strip_prefix(ROW_PREFIX) {
+                        let (table, _) = row_key
+                            .rsplit_once('/')
+                            .ok_or_else(|| e!("invalid stored row key {key}"))?;
+                        rows.push((table.to_owned(), value));
+                    } else {
+                        return Err(e!("unknown stored key {key}"));
+                    }
+                }
+                for (table, value) in rows {
+                    let (key, row): (Key, DataRow) = bincode::deserialize(&value)?;
+                    if let Some(item) = db.items.get_mut(&table) {
+                        item.rows.insert(key, row);
+                    }
+                }
+                OK
+            })
+            .await?;
+        *loaded = true;
+        OK
+    }
+
+    /// Writes the committed state of the modified schemas and rows into the store
+    pub(crate) async fn persist(&self) -> Result {
+        let modified = std::mem::take(&mut *self.modified.lock().expect("modified"));
+        if modified.is_empty() {
+            return OK;
+        }
+        let result = async {
+            let (cleared, entries) = self
+                .memory
+                .committed(|db| {
+                    let id_counter = bincode::serialize(&db.id_counter)?;
+                    let mut entries = vec![(ID_COUNTER_KEY.to_owned(), Some(id_counter))];
+                    let mut cleared = vec![];
+                    for table in modified.tables.iter().chain(&modified.schemas) {
+                        let schema = db.items.get(table).map(|item| &item.schema);
+                        let schema = schema.map(bincode::serialize).transpose()?;
+                        entries.push((format!("{SCHEMA_PREFIX}{table}"), schema));
+                    }
+                    for table in &modified.tables {
+                        cleared.push(format!("{ROW_PREFIX}{table}/"));
+                        if let Some(item) = db.items.get(table) {
+                            for (key, row) in &item.rows {
+                                let row = bincode::serialize(&(key, row))?;
+                                entries.push((row_key(table, key)?, Some(row)));
+                            }
+                        }
+                    }
+                    for (table, keys) in &modified.rows {
+                        if modified.tables.contains(table) {
+                            continue;
+                        }
+                        let rows = db.items.get(table).map(|item| &item.rows);
+                        for key in keys {
+                            let row = rows.and_then(|rows| rows.get(key));
+                            let row = row.map(|row| bincode::serialize(&(key, row))).transpose()?;
+                            entries.push((row_key(table, key)?, row));
+                        }
+                    }
+                    let meta = self.meta.lock().expect("meta");
+                    for key in &modified.meta {
+                        entries.push((key.clone(), meta.get(key).cloned()));
+                    }
+                    Ok::<_, Error>((cleared, entries))
+                })
+                .await?;
+            self.store.write(cleared, entries).await
+        }
+        .await;
+        // retried with the next write
+        if result.is_err() {
+            self.modified.lock().expect("modified").extend(modified);
+        }
+        result
+    }
+
+    pub(crate) async fn transaction<T, F>(&self, fut: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        self.memory.transaction(fut).await
+    }
+
+    /// Stored value which isn't a part of the tables
+    pub(crate) fn meta(&self, key: &str) -> Option<Vec<u8>> {
+        let meta = self.meta.lock().expect("meta");
+        meta.get(&format!("{META_PREFIX}{key}")).cloned()
+    }
+
+    /// Sets the value which is written into the store along with the modified tables
+    pub(crate) fn set_meta(&self, key: &str, value: Vec<u8>) {
+        let key = format!("{META_PREFIX}{key}");
+        self.modified
+            .lock()
+            .expect("modified")
+            .meta
+            .insert(key.clone());
+        self.meta.lock().expect("meta").insert(key, value);
+    }
+
+    fn modify_table(&self, table_name: &str) {
+        let mut modified = self.modified.lock().expect("modified");
+        modified.tables.insert(table_name.to_owned());
+    }
+
+    fn modify_schema(&self, table_name: &str) {
+        let mut modified = self.modified.lock().expect("modified");
+        modified.schemas.insert(table_name.to_owned());
+    }
+
+    fn modify_rows(&self, table_name: &str, keys: impl IntoIterator<Item = Key>) {
+        let mut modified = self.modified.lock().expect("modified");
+        let rows = modified.rows.entry(table_name.to_owned()).or_default();
+        rows.extend(keys);
+    }
+}
+
+/// Key of the stored row which is unique across the tables
+fn row_key(table_name: &str, key: &Key) -> Result<String> {
+    let hex: String = bincode::serialize(key)?
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(format!("{ROW_PREFIX}{table_name}/{hex}"))
+}
+
+#[async_trait(?Send)]
+impl<S: TableStore> Store for WriteThroughStorage<S> {
+    async fn fetch_schema(&self, table_name: &str) -> GlueResult<Option<Schema>> {
+        self.memory.fetch_schema(table_name).await
+    }
+
+    async fn fetch_all_schemas(&self) -> GlueResult<Vec<Schema>> {
+        self.memory.fetch_all_schemas().await
+    }
+
+    async fn fetch_data(&self, table_name: &str, key: &Key) -> GlueResult<Option<DataRow>> {
+        self.memory.fetch_data(table_name, key).await
+    }
+
+    async fn scan_data(&self, table_name: &str) -> GlueResult<RowIter> {
+        self.memory.scan_data(table_name).await
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: TableStore> StoreMut for WriteThroughStorage<S> {
+    async fn insert_schema(&mut self, schema: &Schema) -> GlueResult<()> {
+        // rows of the replaced schema are removed
+        self.modify_table(&schema.table_name);
+        self.memory.insert_schema(schema).await
+    }
+
+    async fn delete_schema(&mut self, table_name: &str) -> GlueResult<()> {
+        self.modify_table(table_name);
+        self.memory.delete_schema(table_name).await
+    }
+
+    async fn append_data(&mut self, table_name: &str, rows: Vec<DataRow>) -> GlueResult<()> {
+        // appended rows get the following keys of the counter
+        let last = self.memory.id_counter().await;
+        let keys = (1..=rows.len() as i64).map(|offset| Key::I64(last + offset));
+        self.modify_rows(table_name, keys);
+        self.memory.append_data(table_name, rows).await
+    }
+
+    async fn insert_data(&mut self, table_name: &str, rows: Vec<(Key, DataRow)>) -> GlueResult<()> {
+        self.modify_rows(table_name, rows.iter().map(|(key, _)| key.clone()));
+        self.memory.insert_data(table_name, rows).await
+    }
+
+    async fn delete_data(&mut self, table_name: &str, keys: Vec<Key>) -> GlueResult<()> {
+        self.modify_rows(table_name, keys.iter().cloned());
+        self.memory.delete_data(table_name, keys).await
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: TableStore> AlterTable for WriteThroughStorage<S> {
+    async fn rename_schema(&mut self, table_name: &str, new_table_name: &str) -> GlueResult<()> {
+        self.modify_table(table_name);
+        self.modify_table(new_table_name);
+        self.memory.rename_schema(table_name, new_table_name).await
+    }
+
+    async fn rename_column(
+        &mut self,
+        table_name: &str,
+        old_column_name: &str,
+        new_column_name: &str,
+    ) -> GlueResult<()> {
+        self.modify_schema(table_name);
+        self.memory
+            .rename_column(table_name, old_column_name, new_column_name)
+            .await
+    }
+
+    async fn add_column(&mut self, table_name: &str, column_def: &ColumnDef) -> GlueResult<()> {
+        self.modify_table(table_name);
+        self.memory.add_column(table_name, column_def).await
+    }
+
+    async fn drop_column(
+        &mut self,
+        table_name: &str,
+        column_name: &str,
+        if_exists: bool,
+    ) -> GlueResult<()> {
+        self.modify_table(table_name);
+        self.memory
+            .drop_column(table_name, column_name, if_exists)
+            .await
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: TableStore> Transaction for WriteThroughStorage<S> {
+    async fn begin(&mut self, autocommit: bool) -> GlueResult<bool> {
+        self.memory.begin(autocommit).await
+    }
+
+    async fn rollback(&mut self) -> GlueResult<()> {
+        self.memory.rollback().await
+    }
+
+    async fn commit(&mut self) -> GlueResult<()> {
+        self.memory.commit().await
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: TableStore> CustomFunction for WriteThroughStorage<S> {
+    async fn fetch_function(&self, func_name: &str) -> GlueResult<Option<&StructCustomFunction>> {
+        self.memory.fetch_function(func_name).await
+    }
+
+    async fn fetch_all_functions(&self) -> GlueResult<Vec<&StructCustomFunction>> {
+        self.memory.fetch_all_functions().await
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: TableStore> CustomFunctionMut for WriteThroughStorage<S> {
+    async fn insert_function(&mut self, func: StructCustomFunction) -> GlueResult<()> {
+        self.memory.insert_function(func).await
+    }
+
+    async fn delete_function(&mut self, func_name: &str) -> GlueResult<()> {
+        self.memory.delete_function(func_name).await
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: TableStore> Index for WriteThroughStorage<S> {
+    async fn scan_indexed_data(
+        &self,
+        table_name: &str,
+        index_name: &str,
+        asc: Option<bool>,
+        cmp_value: Option<(&IndexOperator, sql::Value)>,
+    ) -> GlueResult<RowIter> {
+        self.memory
+            .scan_indexed_data(table_name, index_name, asc, cmp_value)
+            .await
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: TableStore> IndexMut for WriteThroughStorage<S> {
+    async fn create_index(
+        &mut self,
+        table_name: &str,
+        index_name: &str,
+        column: &OrderByExpr,
+    ) -> GlueResult<()> {
+        self.modify_schema(table_name);
+        self.memory
+            .create_index(table_name, index_name, column)
+            .await
+    }
+
+    async fn drop_index(&mut self, table_name: &str, index_name: &str) -> GlueResult<()> {
+        self.modify_schema(table_name);
+        self.memory.drop_index(table_name, index_name).await
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: TableStore> Metadata for WriteThroughStorage<S> {
+    async fn scan_table_meta(&self) -> GlueResult<MetaIter> {
+        self.memory.scan_table_meta().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use gluesql::prelude::Glue;
+    use std::collections::HashMap;
+
+    /// In-memory stand-in for the browser's IndexedDB which keeps the keys of the last write
+    #[derive(Debug, Clone, Default)]
+    struct MemoryTables {
+        entries: Arc<std::sync::Mutex<HashMap<String, Vec<u8>>>>,
+        last_write: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[async_trait(?Send)]
+    impl TableStore for MemoryTables {
+        async fn read_all(&self) -> Result<Vec<(String, Vec<u8>)>> {
+            Ok(self.entries.lock().unwrap().clone().into_iter().collect())
+        }
+
+        async fn write(
+            &self,
+            cleared: Vec<String>,
+            entries: Vec<(String, Option<Vec<u8>>)>,
+        ) -> Result {
+            let mut tables = self.entries.lock().unwrap();
+            for prefix in cleared {
+                tables.retain(|key, _| !key.starts_with(&prefix));
+            }
+            let mut last_write = self.last_write.lock().unwrap();
+            last_write.clear();
+            for (key, value) in entries {
+                last_write.push(key.clone());
+                match value {
+                    Some(value) => tables.insert(key, value),
+                    None => tables.remove(&key),
+                };
+            }
+            OK
+        }
+    }
+
+    async fn query(storage: &WriteThroughStorage<MemoryTables>, sql: &str) -> Vec<sql::Payload> {
+        storage.load().await.unwrap();
+        Glue::new(storage.clone()).execute(sql).await.unwrap()
+    }
+
+    #[test]
+    fn tables_persist_across_reloads() {
+        futures::executor::block_on(async {
+            let tables = MemoryTables::default();
+
+            let storage = WriteThroughStorage::new(tables.clone());
+            query(
+                &storage,
+                "CREATE TABLE todos (id INTEGER PRIMARY KEY, task TEXT)",
+            )
+            .await;
+            query(&storage, "CREATE TABLE logs (msg TEXT)").await;
+            query(&storage, "INSERT INTO todos VALUES (1, 'persist')").await;
+            query(&storage, "INSERT INTO logs VALUES ('first')").await;
+            storage.set_meta("cursor", vec![1]);
+            storage.persist().await.unwrap();
+
+            let reloaded = WriteThroughStorage::new(tables.clone());
+            query(&reloaded, "INSERT INTO logs VALUES ('second')").await;
+            query(&reloaded, "DROP TABLE todos").await;
+            let rolled_back = reloaded.transaction(async {
+                query(&reloaded, "INSERT INTO logs VALUES ('rolled back')").await;
+                Err::<(), _>(e!("rollback"))
+            });
+            assert!(rolled_back.await.is_err());
+            reloaded.persist().await.unwrap();
+
+            let reloaded = WriteThroughStorage::new(tables.clone());
+            let payload = query(&reloaded, "SELECT * FROM logs").await;
+            let sql::Payload::Select { rows, .. } = &payload[0] else {
+                panic!("unexpected payload: {payload:?}");
+            };
+            assert_eq!(rows.len(), 2);
+            assert!(reloaded.fetch_schema("todos").await.unwrap().is_none());
+            assert_eq!(reloaded.meta("cursor"), Some(vec![1]));
+        });
+    }
+
+    fn select_count(payload: &[sql::Payload]) -> usize {
+        let sql::Payload::Select { rows, .. } = &payload[0] else {
+            panic!("unexpected payload: {payload:?}");
+        };
+        rows.len()
+    }
+
+    #[test]
+    fn only_modified_rows_are_written() {
+        futures::executor::block_on(async {
+            let tables = MemoryTables::default();
+            let storage = WriteThroughStorage::new(tables.clone());
+            query(
+                &storage,
+                "CREATE TABLE todos (id INTEGER PRIMARY KEY, task TEXT)",
+            )
+            .await;
+            query(&storage, "CREATE TABLE logs (msg TEXT)").await;
+            for id in 0..20 {
+                query(
+                    &storage,
+                    &format!("INSERT INTO todos VALUES ({id}, 'task')"),
+                )
+                .await;
+            }
+            storage.persist().await.unwrap();
+
+            query(&storage, "UPDATE todos SET task = 'done' WHERE id = 3").await;
+            query(&storage, "DELETE FROM todos WHERE id = 4").await;
+            query(&storage, "INSERT INTO logs VALUES ('appended')").await;
+            storage.persist().await.unwrap();
+            let written = tables.last_write.lock().unwrap().clone();
+            assert_eq!(written.len(), 4, "{written:?}");
+            assert!(
+                written
+                    .iter()
+                    .filter(|key| key.starts_with("row/todos/"))
+                    .count()
+                    == 2
+            );
+            assert!(written.iter().any(|key| key.starts_with("row/logs/")));
+
+            let reloaded = WriteThroughStorage::new(tables.clone());
+            let done = query(&reloaded, "SELECT * FROM todos WHERE task = 'done'").await;
+            assert_eq!(select_count(&done), 1);
+            assert_eq!(
+                select_count(&query(&reloaded, "SELECT * FROM todos").await),
+                19
+            );
+            assert_eq!(
+                select_count(&query(&reloaded, "SELECT * FROM logs").await),
+                1
+            );
+        });
+    }
+}
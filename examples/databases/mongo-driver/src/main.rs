@@ -12,7 +12,7 @@ state!(TODOS: Collection<Todo> = async {
     db.collection::<Todo>("todos")
 });
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, Validate)]
 pub struct Todo {
     #[serde(default)]
     pub uuid: Uuid,
@@ -1,7 +1,7 @@
 use diesel::{pg::Pg, prelude::*};
-use prest::{Deserialize, Serialize, Uuid};
+use prest::{Deserialize, Serialize, Uuid, Validate};
 
-#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize, Validate)]
 #[diesel(table_name = crate::schema::todos)]
 #[diesel(check_for_backend(Pg))]
 pub struct Todo {
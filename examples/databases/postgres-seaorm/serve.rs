@@ -13,18 +13,18 @@ state!(DB: DatabaseConnection = async {
     db
 });
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 struct NewTodo {
     task: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 struct ToggleTodo {
     uuid: Uuid,
     done: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 struct DeleteTodo {
     uuid: Uuid,
 }
@@ -12,7 +12,7 @@ pub struct Todo {
     pub done: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct TodoForm {
     #[serde(default)]
     pub uuid: String,
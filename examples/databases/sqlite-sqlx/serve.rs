@@ -7,7 +7,7 @@ state!(DB: SqlitePool = async {
     conn
 });
 
-#[derive(Debug, FromRow, Serialize, Deserialize)]
+#[derive(Debug, FromRow, Serialize, Deserialize, Validate)]
 struct Todo {
     #[serde(default = "new_uuid")]
     pub uuid: String,
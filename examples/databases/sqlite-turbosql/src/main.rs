@@ -1,7 +1,7 @@
 use prest::*;
 use turbosql::{execute, select, Turbosql};
 
-#[derive(Default, Turbosql, Serialize, Deserialize)]
+#[derive(Default, Turbosql, Serialize, Deserialize, Validate)]
 struct Todo {
     pub rowid: Option<i64>,
     pub task: Option<String>,
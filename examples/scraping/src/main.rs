@@ -59,7 +59,7 @@ async fn scrape(
         .map(|text| get_content(text, &title_selector, &content_selector))
         .collect();
 
-    for story in stories {
+    for mut story in stories {
         story.save().await?;
     }
 
@@ -106,7 +106,7 @@ async fn into_page(content: Markup) -> Markup {
     }}
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Validate)]
 struct NewTodo {
     task: String,
 }
@@ -3,8 +3,6 @@ use todo_pwa_auth_sync::{into_page, shared_routes};
 
 embed_build_output_as!(BuiltAssets);
 
-state!(TODO_UPDATES: SseBroadcast<Option<Todo>> = { SseBroadcast::default() });
-
 #[derive(Table, Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 struct Todo {
@@ -61,7 +59,6 @@ async fn main() -> Result {
                 .put(|user: User, Vals(mut todo): Vals<Todo>| async move {
                     todo.owner = user.id;
                     todo.save().await?;
-                    TODO_UPDATES.send("add", Some(todo)).await?;
                     OK
                 })
                 .patch(|user: User, Vals(mut todo): Vals<Todo>| async move {
@@ -69,7 +66,6 @@ async fn main() -> Result {
                         return Err(Error::Unauthorized);
                     }
                     todo.update_done(!todo.done).await?;
-                    TODO_UPDATES.send(todo.id.to_string(), Some(todo)).await?;
                     OK
                 })
                 .delete(|user: User, Vals(todo): Vals<Todo>| async move {
@@ -77,7 +73,6 @@ async fn main() -> Result {
                         return Err(Error::Unauthorized);
                     }
                     todo.remove().await?;
-                    TODO_UPDATES.send(todo.id.to_string(), None).await?;
                     OK
                 }),
         )
@@ -85,8 +80,10 @@ async fn main() -> Result {
         .route(
             "/todos/subscribe",
             get(|auth: Auth| async {
-                TODO_UPDATES.stream_and_render(move |_event, todo| {
-                    todo.map(|t| t.render_for(&auth.user)).unwrap_or_default()
+                Todo::subscribe().stream_and_render(move |change| match change {
+                    Change::Inserted(todo) => ("add".to_owned(), todo.render_for(&auth.user)),
+                    Change::Updated(todo) => (todo.id.to_string(), todo.render_for(&auth.user)),
+                    Change::Deleted(todo) => (todo.id.to_string(), html!()),
                 })
             }),
         )
@@ -38,7 +38,7 @@ async fn main() -> Result {
                     div #list $"w-full" {(Todo::select_all().await?)}
                 ))
             })
-            .put(|todo: Vals<Todo>| async move { ok(todo.save().await?.render()) })
+            .put(|mut todo: Vals<Todo>| async move { ok(todo.save().await?.render()) })
             .delete(|todo: Vals<Todo>| async move { ok(todo.remove().await?) })
             .patch(|Vals(mut todo): Vals<Todo>| async move {
                 ok(todo.update_done(!todo.done).await?.render())
@@ -39,7 +39,7 @@ async fn main() -> Result {
     route(
         "/",
         get(|| async { ok(Todo::select_all().await?.render()) })
-            .put(|todo: Vals<Todo>| async move { ok(todo.save().await?.render()) })
+            .put(|mut todo: Vals<Todo>| async move { ok(todo.save().await?.render()) })
             .delete(|todo: Vals<Todo>| async move { ok(todo.remove().await?) })
             .patch(|Vals(mut todo): Vals<Todo>| async move {
                 ok(todo.update_done(!todo.done).await?.render())
@@ -0,0 +1,111 @@
+use crate::*;
+
+use std::{io::Write, path::Path as FilePath};
+
+const RESTORE_SCRIPT: &str = "const file = document.getElementById('backup-file').files[0];
+if (file && confirm('Replace all the data with the backup?')) {
+    fetch('/admin/db/backups/restore', { method: 'POST', body: file })
+        .then(res => alert(res.ok ? 'Restored' : 'Restore failed'))
+}";
+
+pub(crate) fn routes() -> Router {
+    route("/", get(full).post(backup_now))
+        .route("/latest", get(latest))
+        .route("/restore", post(restore))
+        .route("/:name", get(download))
+}
+
+pub(crate) async fn full() -> Result<Markup> {
+    let backups = DB.backups()?;
+    Ok(html! {
+        #"backups" $"w-full" {
+            $"font-bold text-lg" {"Backups"}
+            $"flex gap-4 items-center text-xs md:text-sm" {
+                button post="/admin/db/backups" target="#backups" swap-full {"Back up now"}
+                @if !backups.is_empty() {
+                    a href="/admin/db/backups/latest" boost="false" $"underline" download {"Download latest"}
+                }
+                input #"backup-file" type="file" {}
+                button onclick=(RESTORE_SCRIPT) {"Restore"}
+            }
+            $"w-full text-xs md:text-sm font-mono" {
+                @if backups.is_empty() {
+                    p {"none"}
+                }
+                @for backup in backups {
+                    @let name = file_name(&backup);
+                    @let size = backup.metadata().map(|m| m.len()).unwrap_or_default();
+                    $"w-full" {
+                        a href={"/admin/db/backups/"(name)} boost="false" $"underline" download {(name)}
+                        " - "(size / 1024)" KB"
+                    }
+                }
+            }
+        }
+    })
+}
+
+async fn backup_now() -> Result<Markup> {
+    DB.backup().await?;
+    full().await
+}
+
+async fn latest() -> Result<Response> {
+    match DB.backups()?.first() {
+        Some(path) => file_response(path),
+        None => Err(Error::NotFound),
+    }
+}
+
+async fn download(Path(name): Path<String>) -> Result<Response> {
+    // only the listed backups can be downloaded
+    match DB
+        .backups()?
+        .into_iter()
+        .find(|path| file_name(path) == name)
+    {
+        Some(path) => file_response(&path),
+        None => Err(Error::NotFound),
+    }
+}
+
+/// Streams the uploaded backup into a file which is restored once it's fully written,
+/// such uploads are excluded from the request body limit
+async fn restore(body: Body) -> Result {
+    let path = backups_dir()?.join("uploaded.partial");
+    let written = async {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let mut chunks = body.into_data_stream();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.map_err(|e| e!("backup upload failed: {e}"))?;
+            file.write_all(&chunk)?;
+        }
+        file.flush()?;
+        OK
+    };
+    let result = match written.await {
+        Ok(()) => DB.restore_from(&path).await,
+        Err(e) => Err(e),
+    };
+    std::fs::remove_file(path)?;
+    result
+}
+
+fn file_response(path: &FilePath) -> Result<Response> {
+    let data = std::fs::read(path)?;
+    let disposition = format!("attachment; filename=\"{}\"", file_name(path));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_owned()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+fn file_name(path: &FilePath) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
@@ -1,61 +1,99 @@
-use host::admin::{DELETE_SVG, DONE_SVG, EDIT_SVG};
+use host::admin::{DELETE_SVG, DONE_SVG, EDIT_SVG, HISTORY_SVG, LOADER_SVG};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
 use crate::*;
 
+const ROWS_PER_PAGE: usize = 50;
+
 pub(crate) async fn db_page() -> Markup {
     let tables = html! {
         @for table in DB.custom_tables() {
             $"font-bold text-lg" {(table.name())}
             a get=(table.full_path()) trigger="load" swap-this {}
         }
+        a get="/admin/migrations" trigger="load" swap-this {}
+        @if matches!(DB.storage(), DbStorage::Persistent(_)) {
+            a get="/admin/db/storage" trigger="load" swap-this {}
+            a get="/admin/db/backups" trigger="load" swap-this {}
+        }
     };
     html!((tables))
 }
 
 pub(crate) async fn db_routes() -> Router {
-    let mut router = route("/", get(db_page));
+    let mut router = route("/", get(db_page))
+        .nest("/storage", super::storage::routes())
+        .nest("/backups", super::backups::routes());
     for table in DB.custom_tables() {
         let get_by_id_path = format!("{}/:id", table.relative_path());
+        let view_by_id_path = format!("{}/row/:id", table.relative_path());
+        let page_path = format!("{}/page/:offset", table.relative_path());
+        let history_path = format!("{}/history/:id", table.relative_path());
+        let search_path = format!("{}/search", table.relative_path());
         router = router
             .route(
                 table.relative_path(),
                 get(|| async {
-                    let rows = table
-                        .get_all()
-                        .await?
-                        .into_iter()
-                        .map(|row| view_row(table, row));
+                    let count = table.count().await?;
+                    let searchable = table.columns().iter().any(|c| c.searchable);
+                    let results = format!("{}_results", table.name());
                     ok(html!(
-                        table #(table.name()) .table-editor $"w-full font-mono text-[0.5rem] lg:text-sm" {
-                            @let columns = table.columns().iter().map(|c| (c.name, c.rust_type));
-                            @for (name, rust_type) in columns {th {(name)" ("(rust_type)")"}}
-                            th #actions $"w-[70px]" {}
-                            (create_form(table))
-                            @for row in rows {(row)}
+                        $"text-xs italic" {(count)" rows"}
+                        @if searchable {
+                            input type="search" name="query" placeholder="search"
+                                get={(table.full_path())"/search"} target={"#"(results)}
+                                trigger="input changed delay:300ms, search" $"text-xs md:text-sm" {}
                         }
+                        div #(results) {(full_editor(table).await?)}
                     ))
                 }),
             )
             .route(
-                &get_by_id_path,
+                &search_path,
+                get(
+                    move |extract::Query(SearchParams { query }): extract::Query<SearchParams>| async move {
+                        if query.trim().is_empty() {
+                            return full_editor(table).await;
+                        }
+                        let rows = table.search(query, ROWS_PER_PAGE).await?;
+                        let rows = rows.into_iter().map(|row| view_row(table, row));
+                        ok(table_editor(table, None, rows))
+                    },
+                ),
+            )
+            .route(
+                &page_path,
+                get(move |Path(offset): Path<usize>| async move { rows_page(table, offset).await }),
+            )
+            .route(
+                &view_by_id_path,
                 get(|Path(id): Path<String>| async {
                     let row = table.get_row_by_id(id).await?;
-                    ok(edit_row(table, row))
+                    ok(table_editor(table, None, [view_row(table, row)]))
                 }),
             )
-            .route(table.relative_path(), put(|req: Request| async {
-                let id = table.save(req).await?;
-                let row = table.get_row_by_id(id).await?;
-                ok(view_row(table, row))
-            }))
             .route(
-                table.relative_path(),
-                patch(|req: Request| async {
-                    let id = table.save(req).await?;
+                &history_path,
+                get(|Path(id): Path<String>| async {
+                    let versions = table.get_row_history(id).await?;
+                    ok(history_table(table, versions))
+                }),
+            )
+            .route(
+                &get_by_id_path,
+                get(|Path(id): Path<String>| async {
                     let row = table.get_row_by_id(id).await?;
-                    ok(view_row(table, row))
+                    ok(edit_row(table, row, None, &Default::default()))
                 }),
             )
+            .route(
+                table.relative_path(),
+                put(|req: Request| async { save_row(table, req).await }),
+            )
+            .route(
+                table.relative_path(),
+                patch(|req: Request| async { save_row(table, req).await }),
+            )
             .route(
                 table.relative_path(),
                 delete(|req: Request| async { table.remove(req).await }),
@@ -64,45 +102,189 @@ pub(crate) async fn db_routes() -> Router {
     router
 }
 
-fn create_form(table: TableSchema) -> Markup {
+#[derive(Deserialize)]
+struct SearchParams {
+    query: String,
+}
+
+async fn full_editor(table: TableSchema) -> Result<Markup> {
+    let rows = rows_page(table, 0).await?;
+    let create_form = create_form(table, None, &Default::default());
+    ok(table_editor(table, Some(create_form), [rows]))
+}
+
+fn table_editor(
+    table: TableSchema,
+    create_form: Option<Markup>,
+    rows: impl IntoIterator<Item = Markup>,
+) -> Markup {
+    html!(
+        table #(table.name()) .table-editor $"w-full font-mono text-[0.5rem] lg:text-sm" {
+            @let columns = table.columns().iter().map(|c| (c.name, c.rust_type));
+            @for (name, rust_type) in columns {th {(name)" ("(rust_type)")"}}
+            th #actions $"w-[70px]" {}
+            @if let Some(form) = create_form {(form)}
+            @for row in rows {(row)}
+        }
+    )
+}
+
+async fn rows_page(table: TableSchema, offset: usize) -> Result<Markup> {
+    let rows = table.get_page(offset, ROWS_PER_PAGE).await?;
+    let maybe_more = rows.len() == ROWS_PER_PAGE;
+    let next_page = format!("{}/page/{}", table.full_path(), offset + ROWS_PER_PAGE);
+    let colspan = table.columns().len() + 1;
+    Ok(html!(
+        @for row in rows {(view_row(table, row))}
+        @if maybe_more {
+            tr get=(next_page) trigger="revealed" target="this" swap="outerHTML transition:false" {
+                td colspan=(colspan) { $"w-8 mx-auto" {(LOADER_SVG)} }
+            }
+        }
+    ))
+}
+
+/// Saves the row from the request, stale writes reopen the editor with the stored values
+/// and invalid ones with the submitted values and the messages of their fields
+async fn save_row(table: TableSchema, req: Request) -> Result<Response> {
+    let creating = req.method() == Method::PUT;
+    let (parts, body) = req.into_parts();
+    let body = axum::body::to_bytes(body, host::request_body_limit())
+        .await
+        .somehow()?;
+    let req = Request::from_parts(parts, Body::from(body.clone()));
+    let id = match table.save(req).await {
+        Ok(id) => id,
+        Err(Error::StaleWrite { id, .. }) => {
+            let row = table.get_row_by_id(id).await?;
+            let notice = "changed by another write, review and save again";
+            let form = edit_row(table, row, Some(notice), &Default::default());
+            return Ok(form.into_response());
+        }
+        Err(Error::Validation(errors)) if creating => {
+            let values = submitted_values(table, &body);
+            // the create form is replaced instead of inserting a row after it
+            let target = format!("#{}", key_selector(table, None));
+            let headers = [
+                ("HX-Retarget", target),
+                ("HX-Reswap", "outerHTML".to_owned()),
+            ];
+            return Ok((headers, create_form(table, Some(values), &errors)).into_response());
+        }
+        Err(Error::Validation(errors)) => {
+            let values = submitted_values(table, &body);
+            return Ok(edit_row(table, values, None, &errors).into_response());
+        }
+        Err(e) => return Err(e),
+    };
+    let row = table.get_row_by_id(id).await?;
+    Ok(view_row(table, row).into_response())
+}
+
+/// Values of the columns as they were sent by the editor
+fn submitted_values(table: TableSchema, body: &[u8]) -> Vec<String> {
+    let submitted: serde_json::Map<String, JsonValue> = from_json_slice(body).unwrap_or_default();
+    let values = table
+        .columns()
+        .iter()
+        .map(|column| match submitted.get(column.field_name) {
+            Some(JsonValue::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        });
+    values.collect()
+}
+
+fn create_form(
+    table: TableSchema,
+    values: Option<Vec<String>>,
+    errors: &ValidationErrors,
+) -> Markup {
     let columns = table.columns();
     let key_selector = key_selector(table, None);
 
-    let cells = columns.iter().map(|schema| {
-        html!(
-            td .create { (column_input(schema, None, &key_selector)) }
-        )
-    });
+    // columns with defaults are filled by the generated save
+    let cells = columns
+        .iter()
+        .enumerate()
+        .map(|(i, schema)| match schema.default {
+            Some(_) => html!(td .create $"italic" {"default"}),
+            None => {
+                let value = values.as_ref().map(|values| values[i].as_str());
+                html!(td .create {
+                    (column_input(schema, value, &key_selector))
+                    (field_errors(errors, schema))
+                })
+            }
+        });
 
+    // invalid values are sent back with a retarget to be fixed
+    let reset =
+        format!("if (!event.detail.xhr.getResponseHeader('HX-Retarget')) reset('.{key_selector}')");
     html!(tr #(key_selector) {
         @for cell in cells {(cell)}
         td .actions put-after={"#"(key_selector)} include={"."(key_selector)} { div {
-            button put=(table.full_path()) after-request={"reset('."(key_selector)"')"} {(DONE_SVG)}
+            button put=(table.full_path()) after-request=(reset) {(DONE_SVG)}
         }}
     })
 }
 
+fn field_errors(errors: &ValidationErrors, schema: &ColumnSchema) -> Markup {
+    html!(@for message in errors.field(schema.field_name) { $"text-xs italic" {(message)} })
+}
+
 fn view_row(table: TableSchema, values: Vec<String>) -> Markup {
-    let columns = table.columns();
     let key_selector = key_selector(table, Some(&values));
 
-    let cells = values.iter().map(|value| html! {td ."view" {(value)}});
+    let cells =
+        std::iter::zip(table.columns(), &values).map(|(column, value)| {
+            match reference_url(column, value) {
+                Some(url) => html! {td ."view" {a href=(url) boost="false" $"underline" {(value)}}},
+                None => html! {td ."view" {(value)}},
+            }
+        });
 
-    let id = std::iter::zip(columns, &values)
-        .find(|(col, _)| col.pkey)
-        .map(|(_, v)| v)
-        .expect("Some column must be primary key");
+    // composite ids are json arrays with quotes and slashes
+    let id = _pkey_id(pkey_values(table, &values));
+    let id = utf8_percent_encode(&id, NON_ALPHANUMERIC);
     let edit_url = format!("{}/{id}", table.full_path());
+    let history_url = format!("{}/history/{id}", table.full_path());
 
     html!(tr #(key_selector) {
         @for cell in cells {(cell)}
         td .actions { div {
             button get=(edit_url) target={"#"(key_selector)} {(EDIT_SVG)}
+            button get=(history_url) replace-url into="main" {(HISTORY_SVG)}
         }}
     })
 }
 
-fn edit_row(table: TableSchema, values: Vec<String>) -> Markup {
+/// Versions of the row from the newest to the oldest
+fn history_table(table: TableSchema, versions: Vec<RowVersion<Vec<String>>>) -> Markup {
+    html!(
+        table $"w-full font-mono text-[0.5rem] lg:text-sm" {
+            th {"txid"}
+            th {"written at"}
+            th {"replaced by"}
+            @for column in table.columns() {th {(column.name)}}
+            @for version in versions.iter().rev() {
+                tr {
+                    td {(version.txid)}
+                    td {@if let Some(time) = version.written_at {(time.format("%Y-%m-%d %H:%M:%S"))}}
+                    td {@if let Some(txid) = version.replaced_by {(txid)}}
+                    @for value in &version.item {td {(value)}}
+                }
+            }
+        }
+    )
+}
+
+fn edit_row(
+    table: TableSchema,
+    values: Vec<String>,
+    notice: Option<&str>,
+    errors: &ValidationErrors,
+) -> Markup {
     let columns = table.columns();
     let key_selector = key_selector(table, Some(&values));
 
@@ -110,24 +292,54 @@ fn edit_row(table: TableSchema, values: Vec<String>) -> Markup {
         html!(
             td .edit {
                 (column_input(schema, Some(value), &key_selector))
-                @if schema.pkey {
+                @if schema.pkey || schema.version || schema.timestamp {
                     (value)
                 }
+                (field_errors(errors, schema))
             }
         )
     });
 
     html!(tr #(key_selector) {
         @for cell in cells {(cell)}
-        td .actions target={"#"(key_selector)} include={"."(key_selector)} { div {
-            button patch=(table.full_path()) {(DONE_SVG)}
-            button hx-confirm="are you sure you want to delete?" delete=(table.full_path()) {(DELETE_SVG)}
-        }}
+        td .actions target={"#"(key_selector)} include={"."(key_selector)} {
+            div {
+                button patch=(table.full_path()) {(DONE_SVG)}
+                button hx-confirm="are you sure you want to delete?" delete=(table.full_path()) {(DELETE_SVG)}
+            }
+            @if let Some(notice) = notice { $"text-xs italic" {(notice)} }
+        }
     })
 }
 
+/// Link to the row of the referenced table
+fn reference_url(column: &ColumnSchema, value: &str) -> Option<String> {
+    // only custom tables are editable
+    let target = DB
+        .custom_tables()
+        .into_iter()
+        .find(|t| Some(t.name()) == column.references)?;
+    // optional values are rendered as json
+    let id = match column.optional {
+        true => match from_json_str(value).ok()? {
+            serde_json::Value::Null => return None,
+            serde_json::Value::String(id) => id,
+            id => id.to_string(),
+        },
+        false => value.to_owned(),
+    };
+    let id = utf8_percent_encode(&id, NON_ALPHANUMERIC);
+    Some(format!("{}/row/{id}", target.full_path()))
+}
+
 fn column_input(schema: &ColumnSchema, value: Option<&str>, key_selector: &String) -> Markup {
-    let input_type = if value.is_some() && schema.pkey {
+    // keys, versions and timestamps of the stored rows are sent back as they are
+    let existing_key = value.is_some() && (schema.pkey || schema.version || schema.timestamp);
+    if !schema.variants.is_empty() && !existing_key {
+        return variant_select(schema, value, key_selector);
+    }
+
+    let input_type = if existing_key {
         "hidden"
     } else {
         column_input_type(schema)
@@ -139,25 +351,50 @@ fn column_input(schema: &ColumnSchema, value: Option<&str>, key_selector: &Strin
         input
             .(key_selector)
             type=(input_type)
-            name=(schema.name)
+            name=(schema.field_name)
             value=[value]
             checked[checked] {}
     }
 }
 
+/// Dropdown of the unit variants with the json values the editor sends, keeps the current value if it holds data
+fn variant_select(schema: &ColumnSchema, value: Option<&str>, key_selector: &String) -> Markup {
+    let mut options = vec![];
+    if schema.optional {
+        options.push(("null".to_owned(), String::new()));
+    }
+    for variant in schema.variants.iter().filter(|v| !v.ends_with("..)")) {
+        let json = to_json_string(variant).expect("strings should serialize");
+        options.push((json, variant.to_string()));
+    }
+    if let Some(value) = value.filter(|v| !options.iter().any(|(json, _)| json == v)) {
+        options.push((value.to_owned(), value.to_owned()));
+    }
+
+    html! {
+        select .(key_selector) name=(schema.field_name) {
+            @for (json, label) in options {
+                option value=(json) selected[Some(json.as_str()) == value] {(label)}
+            }
+        }
+    }
+}
+
 fn key_selector(table: TableSchema, values: Option<&Vec<String>>) -> String {
     if let Some(values) = values {
-        let pkey_index = table
-            .columns()
-            .iter()
-            .position(|c| c.pkey)
-            .expect("Some column must be the primary key");
-        format!("key{}", values[pkey_index].clone())
+        format!("key{}", pkey_values(table, values).join("_"))
     } else {
         format!("new_{}", table.name())
     }
 }
 
+fn pkey_values(table: TableSchema, values: &Vec<String>) -> Vec<String> {
+    std::iter::zip(table.columns(), values)
+        .filter(|(col, _)| col.pkey)
+        .map(|(_, v)| v.clone())
+        .collect()
+}
+
 fn column_input_type(column: &ColumnSchema) -> &str {
     let singular = !column.list && !column.optional;
     match column.sql_type {
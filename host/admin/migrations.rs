@@ -0,0 +1,20 @@
+use crate::*;
+
+pub(crate) async fn full() -> Result<Markup> {
+    let mut records = MigrationRecord::select_all().await?;
+    records.sort_by_key(|r| r.version);
+
+    Ok(html! {
+        $"w-full" {
+            $"font-bold text-lg" {"Applied migrations"}
+            $"w-full text-xs md:text-sm font-mono" {
+                @if records.is_empty() {
+                    p {"none"}
+                }
+                @for record in records {
+                    $"w-full" {b{(record.version)" - "(record.name)}", applied at "(record.applied_at)}
+                }
+            }
+        }
+    })
+}
@@ -1,10 +1,13 @@
 use crate::*;
 
+mod backups;
 mod db_editor;
 mod logs;
+mod migrations;
 mod remote;
 mod routes_stats;
 mod schedule_stats;
+mod storage;
 mod system_stats;
 
 const ADMIN_SVG: PreEscaped<&str> = PreEscaped(include_str!("assets/admin.svg"));
@@ -15,6 +18,7 @@ const LOADER_SVG: PreEscaped<&str> = PreEscaped(include_str!("assets/loader.svg"
 const EDIT_SVG: PreEscaped<&str> = PreEscaped(include_str!("assets/edit.svg"));
 const DONE_SVG: PreEscaped<&str> = PreEscaped(include_str!("assets/done.svg"));
 const DELETE_SVG: PreEscaped<&str> = PreEscaped(include_str!("assets/delete.svg"));
+const HISTORY_SVG: PreEscaped<&str> = PreEscaped(include_str!("assets/history.svg"));
 
 pub(crate) async fn routes() -> Router {
     route(
@@ -32,6 +36,7 @@ pub(crate) async fn routes() -> Router {
     .route("/latest_info/:offset", get(logs::info))
     .route("/traces", get(logs::traces_explorer))
     .route("/schedule_stats", get(schedule_stats::full))
+    .route("/migrations", get(migrations::full))
     .route("/analytics", get(routes_stats::full))
     .nest("/remote", remote::routes())
     .nest("/db", db_editor::db_routes().await)
@@ -1,48 +1,16 @@
 use crate::*;
 use std::collections::HashMap;
 
-pub(crate) async fn full() -> Result<Markup> {
-    let jobs_records = ScheduledJobRecord::select_all().await?;
-
-    #[derive(Default)]
-    struct ScheduledJobStat {
-        finished_successfully: u32,
-        in_progress: u32,
-        avg_duration: f64,
-        errors: Vec<(NaiveDateTime, String)>,
-    }
-
-    let jobs_stats: HashMap<String, ScheduledJobStat> = jobs_records.into_iter().fold(
-        HashMap::new(),
-        |mut map,
-         ScheduledJobRecord {
-             name,
-             start,
-             end,
-             error,
-             ..
-         }| {
-            let entry = map.entry(name).or_default();
-
-            if end.is_none() {
-                entry.in_progress += 1;
-            } else if end.is_some() && error.is_none() {
-                let updated_successes = entry.finished_successfully + 1;
-                let duration = (end.unwrap() - start).num_milliseconds().abs() as f64;
-
-                let updated_avg_duration =
-                    (entry.finished_successfully as f64 * entry.avg_duration + duration)
-                        / (updated_successes as f64);
-
-                entry.finished_successfully = updated_successes;
-                entry.avg_duration = updated_avg_duration;
-            } else if end.is_some() && error.is_some() {
-                entry.errors.push((end.unwrap(), error.unwrap()));
-            }
+#[derive(Debug, Default, PartialEq)]
+struct ScheduledJobStat {
+    finished_successfully: usize,
+    in_progress: usize,
+    avg_duration: f64,
+    errors: Vec<(NaiveDateTime, String)>,
+}
 
-            map
-        },
-    );
+pub(crate) async fn full() -> Result<Markup> {
+    let jobs_stats = jobs_stats().await?;
 
     Ok(html! {
         $"w-full" get="/admin/schedule_stats" trigger="load delay:10s" swap-this-no-transition {
@@ -59,3 +27,94 @@ pub(crate) async fn full() -> Result<Markup> {
         }
     })
 }
+
+/// Stats of the jobs by their names, counted and averaged by a single grouped query
+async fn jobs_stats() -> Result<Vec<(String, ScheduledJobStat)>> {
+    let projection = r#"
+        SUM(CASE WHEN "end" IS NULL THEN 1 ELSE 0 END),
+        SUM(CASE WHEN "end" IS NOT NULL AND error IS NULL THEN 1 ELSE 0 END),
+        SUM(CASE WHEN "end" IS NOT NULL AND error IS NULL THEN duration_ms ELSE 0 END)
+    "#;
+    let rows = _aggregate::<ScheduledJobRecord>(projection, Some("name"), None).await?;
+
+    let cols = ScheduledJobRecord::cols;
+    let mut errors: HashMap<String, Vec<(NaiveDateTime, String)>> = HashMap::new();
+    for record in ScheduledJobRecord::select_where(cols.error.is_not_null()).await? {
+        if let (Some(end), Some(error)) = (record.end, record.error) {
+            errors.entry(record.name).or_default().push((end, error));
+        }
+    }
+
+    let mut jobs_stats = vec![];
+    for row in rows {
+        let [sql::Value::Str(name), in_progress, finished, total_duration] = row.as_slice() else {
+            return Err(e!("unexpected scheduled jobs stats row {row:?}"));
+        };
+        let name = name.clone();
+        let finished = i64::try_from(finished).somehow()? as usize;
+        // AVG of the database is NULL if any of the values is NULL
+        let total_duration = f64::try_from(total_duration).somehow()?;
+        let stats = ScheduledJobStat {
+            finished_successfully: finished,
+            in_progress: i64::try_from(in_progress).somehow()? as usize,
+            avg_duration: match finished {
+                0 => 0.0,
+                finished => total_duration / finished as f64,
+            },
+            errors: errors.remove(&name).unwrap_or_default(),
+        };
+        jobs_stats.push((name, stats));
+    }
+    Ok(jobs_stats)
+}
+
+#[cfg(all(test, host))]
+mod test {
+    use super::*;
+
+    fn record(name: &str, duration_ms: Option<u64>, error: Option<&str>) -> ScheduledJobRecord {
+        let start = Utc::now().naive_utc();
+        ScheduledJobRecord {
+            id: Uuid::now_v7(),
+            name: name.to_owned(),
+            start,
+            end: duration_ms.map(|ms| start + chrono::Duration::milliseconds(ms as i64)),
+            error: error.map(ToOwned::to_owned),
+            duration_ms,
+        }
+    }
+
+    #[test]
+    fn stats_are_grouped_by_job() {
+        test_db(&[ScheduledJobRecord::schema()], async {
+            let mut records = vec![
+                record("stats-a", Some(10), None),
+                record("stats-a", Some(30), None),
+                record("stats-a", Some(500), Some("failed")),
+                record("stats-a", None, None),
+                record("stats-b", None, None),
+            ];
+            // finished before the durations were recorded
+            let mut historical = record("stats-b", Some(20), None);
+            historical.duration_ms = None;
+            records.push(historical);
+            ScheduledJobRecord::insert_many(&records).await?;
+            ScheduledJobRecord::fill_durations().await?;
+
+            let stats: HashMap<String, ScheduledJobStat> =
+                jobs_stats().await?.into_iter().collect();
+            let a = &stats["stats-a"];
+            assert_eq!((a.in_progress, a.finished_successfully), (1, 2));
+            assert_eq!(a.avg_duration, 20.0);
+            assert_eq!(
+                a.errors,
+                vec![(records[2].end.unwrap(), "failed".to_owned())]
+            );
+            let b = &stats["stats-b"];
+            assert_eq!((b.in_progress, b.finished_successfully), (1, 1));
+            assert_eq!(b.avg_duration, 20.0);
+            assert!(b.errors.is_empty());
+            OK
+        })
+    }
+}
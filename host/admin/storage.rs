@@ -0,0 +1,64 @@
+use crate::*;
+
+pub(crate) fn routes() -> Router {
+    route("/", get(full).post(gc_now))
+}
+
+pub(crate) async fn full() -> Result<Markup> {
+    let tables = DB.tables_storage().await?;
+    let size = DB.size_on_disk().await?;
+    let last_gc = DB.last_gc().await?;
+    Ok(html! {
+        #"storage" $"w-full" {
+            $"font-bold text-lg" {"Storage"}
+            $"flex gap-4 items-center text-xs md:text-sm" {
+                @match size {
+                    Some(size) => {
+                        span {"On disk: "(megabytes(size))}
+                        button post="/admin/db/storage" target="#storage" swap-full {"Collect garbage now"}
+                    }
+                    None => span {"On disk: n/a (in-memory storage)"},
+                }
+            }
+            $"text-xs md:text-sm" {
+                "Last gc: "
+                @match last_gc {
+                    Some(run) => {
+                        (run.timestamp.format("%Y-%m-%d %H:%M:%S"))" - "(run.snapshots)" snapshots, "
+                        (kilobytes(run.bytes))" reclaimed"
+                    }
+                    None => "never",
+                }
+            }
+            table $"w-full text-xs md:text-sm font-mono" {
+                th {"table"}
+                th {"rows"}
+                th {"size"}
+                th {"versions"}
+                th {"max depth"}
+                @for table in tables {
+                    tr {
+                        td {(table.name)}
+                        td {(table.rows)}
+                        td {(kilobytes(table.bytes))}
+                        td {(table.versions)}
+                        td {(table.max_depth)}
+                    }
+                }
+            }
+        }
+    })
+}
+
+async fn gc_now() -> Result<Markup> {
+    DB.gc().await?;
+    full().await
+}
+
+fn kilobytes(bytes: u64) -> String {
+    format!("{:.1} KB", bytes as f64 / 1000.0)
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
@@ -35,7 +35,7 @@ impl RouteStat {
             let mut mhal = HashMap::new();
             mhal.insert(req_method, (1, latency));
 
-            let stats = RouteStat {
+            let mut stats = RouteStat {
                 path,
                 method_hits_and_latency: mhal,
                 is_asset,
@@ -26,6 +26,7 @@ pub type OAuthCode = String;
 pub struct User {
     pub id: Uuid,
     pub permissions: Vec<String>,
+    #[enum_column]
     pub group: UserGroup,
     #[unique_column]
     pub username: Option<String>,
@@ -34,7 +35,7 @@ pub struct User {
     pub password_hash: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(TableEnum, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum UserGroup {
     Admin,
     Visitor,
@@ -124,7 +125,7 @@ impl User {
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Validate)]
 struct AuthForm {
     username: Option<String>,
     email: Option<String>,
@@ -144,21 +145,14 @@ async fn login(mut auth: Auth, Vals(form): Vals<AuthForm>) -> Result<Response> {
 
     let user = if signup {
         let new = if let Some(username) = username {
-            if User::select_by_username(&username).await?.is_some() {
-                return Ok(StatusCode::CONFLICT.into_response());
-            }
             User::from_username_password(username, password)
         } else if let Some(email) = email {
-            if User::select_by_email(&email).await?.is_some() {
-                return Ok(StatusCode::CONFLICT.into_response());
-            }
             User::from_email_password(email, password)
         } else {
             return Ok(StatusCode::BAD_REQUEST.into_response());
         };
-        let Ok(_) = new.save().await else {
-            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
-        };
+        // taken usernames and emails are rejected by their unique columns with 409
+        new.insert_self().await?;
         new
     } else {
         if let Some(username) = username {
@@ -347,7 +341,7 @@ impl AuthnBackend for DbStorage {
                 match maybe_user {
                     Some(user) => Ok(Some(user)),
                     None => {
-                        let user = User::from_email(email);
+                        let mut user = User::from_email(email);
                         user.save()
                             .await
                             .map_err(|e| AuthError::UserNotFound(e.to_string()))?;
@@ -78,6 +78,9 @@ pub trait HostUtils: Sized {
     async fn add_default_assets(self) -> Self;
     fn add_analytics(self) -> Self;
     fn add_auth(self) -> Result<Self>;
+    /// Serves `/sync/{table}` routes for the service workers which pull and push rows of the `#[table(sync(...))]` tables,
+    /// should be added before `run` so that the auth layer covers them
+    fn add_sync(self) -> Self;
 }
 
 #[async_trait]
@@ -120,6 +123,10 @@ impl HostUtils for Router {
         server::start(self).await
     }
 
+    fn add_sync(self) -> Self {
+        self.nest("/sync", sync_routes())
+    }
+
     fn add_auth(self) -> Result<Self> {
         #[cfg(feature = "auth")]
         {
@@ -167,9 +174,7 @@ impl HostUtils for Router {
         #[cfg(not(debug_assertions))]
         let host_services = host_services
             .layer(tower_http::compression::CompressionLayer::new())
-            .layer(tower_http::limit::RequestBodyLimitLayer::new(
-                request_body_limit(),
-            ));
+            .layer(axum::middleware::from_fn(limit_request_body));
 
         let host_services = host_services
             .layer(tower_http::normalize_path::NormalizePathLayer::trim_trailing_slash());
@@ -224,6 +229,21 @@ fn request_body_limit() -> usize {
     }
 }
 
+/// Limits the request bodies except for the uploaded DB backups which are streamed into files
+#[allow(dead_code)]
+async fn limit_request_body(request: Request, next: Next) -> Response {
+    if request.uri().path() == "/admin/db/backups/restore" {
+        return next.run(request).await;
+    }
+    use tower::ServiceExt;
+    let next = next.map_request(|request: Request<_>| request.map(Body::new));
+    let limited = tower_http::limit::RequestBodyLimit::new(next, request_body_limit());
+    match limited.oneshot(request).await {
+        Ok(response) => response.map(Body::new),
+        Err(infallible) => match infallible {},
+    }
+}
+
 #[allow(dead_code)]
 fn not_htmx_predicate<Body>(req: &Request<Body>) -> bool {
     !req.headers().contains_key("hx-request")
@@ -138,7 +138,7 @@ impl RemoteHost {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub(crate) struct DeploymentInfo {
     pub pid: Option<u32>,
     pub pkg_name: String,
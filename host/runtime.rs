@@ -153,6 +153,7 @@ pub struct ScheduledJobRecord {
     pub start: NaiveDateTime,
     pub end: Option<NaiveDateTime>,
     pub error: Option<String>,
+    pub duration_ms: Option<u64>,
 }
 
 impl ScheduledJobRecord {
@@ -163,12 +164,17 @@ impl ScheduledJobRecord {
             start: Utc::now().naive_utc(),
             end: None,
             error: None,
+            duration_ms: None,
         };
         trace!(target:"runtime", job = %name, start = %stat.start);
         let stat_clone = stat.clone();
         RT.spawn(async move {
-            if let Err(e) = stat_clone.save().await {
-                error!(target:"runtime", "Failed to record start of the scheduled job stat {stat_clone:?} : {e}");
+            match stat_clone.insert_self().await {
+                // already saved by the end of a quicker job
+                Ok(()) | Err(Error::Conflict { .. }) => (),
+                Err(e) => {
+                    error!(target:"runtime", "Failed to record start of the scheduled job stat {stat_clone:?} : {e}")
+                }
             }
         });
         stat
@@ -179,17 +185,34 @@ impl ScheduledJobRecord {
 
         trace!(target:"runtime", job = %self.name, end = %end);
 
-        if let Err(e) = self.update_end(Some(end)).await {
+        self.end = Some(end);
+        self.duration_ms = Some((end - self.start).num_milliseconds().unsigned_abs());
+        self.error = error.clone();
+        let mut saved = self.save().await;
+        // the start could've been inserted in the meantime
+        if let Err(Error::Conflict { .. }) = saved {
+            saved = self.save().await;
+        }
+        if let Err(e) = saved {
             error!(target:"runtime", "Failed to record end of the scheduled job stat {self:?} : {e}");
         }
 
         if let Some(e) = error {
             error!(target:"runtime", "Scheduled job {} error: {e}", self.name);
-            if let Err(upd_e) = self.update_error(Some(e.clone())).await {
-                error!(target:"runtime", "Failed to record error {e} of the scheduled job stat {self:?} : {upd_e}");
-            }
         }
     }
+
+    /// Fills `duration_ms` of the jobs which finished before it was recorded
+    pub(crate) async fn fill_durations() -> Result {
+        let cols = Self::cols;
+        let filter = cols.end.is_not_null().and(cols.duration_ms.is_null());
+        let mut records = Self::select_where(filter).await?;
+        for record in records.iter_mut() {
+            let end = record.end.expect("filtered finished jobs");
+            record.duration_ms = Some((end - record.start).num_milliseconds().unsigned_abs());
+        }
+        Self::save_many(&mut records).await
+    }
 }
 
 /// Simplified interface to run [`RepeatableJob`]s in prest's [`RT`]
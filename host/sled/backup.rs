@@ -0,0 +1,173 @@
+use {
+    super::{get_id_offset, sled_config, SharedSledStorage, SledStorage},
+    crate::{e, prest, tx_scope, Result, OK},
+    serde::{Deserialize, Serialize},
+    std::{
+        fs::File,
+        io::{BufReader, Read, Write},
+        path::Path,
+    },
+};
+
+const BACKUP_MAGIC: &[u8; 8] = b"PRESTDB\0";
+const BACKUP_VERSION: u32 = 1;
+const DEFAULT_TREE: &[u8] = b"__sled__default";
+
+#[derive(Serialize, Deserialize)]
+struct BackupHeader {
+    version: u32,
+    /// ids generated after the restore should be greater than the ones in the backup
+    id_offset: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupEntry {
+    tree: Vec<u8>,
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl SharedSledStorage {
+    /// Writes all the trees into the backup while the transactions wait for it
+    pub async fn backup(&self, writer: &mut impl Write) -> Result {
+        self.exclusive(|storage| {
+            let id_offset = storage.id_offset + storage.tree.generate_id()?;
+            writer.write_all(BACKUP_MAGIC)?;
+            let header = BackupHeader {
+                version: BACKUP_VERSION,
+                id_offset,
+            };
+            bincode::serialize_into(&mut *writer, &header)?;
+            for (_, tree, entries) in storage.tree.export() {
+                for mut entry in entries {
+                    let value = entry.pop().expect("exported entry should have a value");
+                    let key = entry.pop().expect("exported entry should have a key");
+                    let entry = BackupEntry {
+                        tree: tree.clone(),
+                        key,
+                        value,
+                    };
+                    bincode::serialize_into(&mut *writer, &Some(entry))?;
+                }
+            }
+            bincode::serialize_into(&mut *writer, &None::<BackupEntry>)?;
+            writer.flush()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Reads the whole backup without restoring anything, fails if it's malformed or truncated
+    pub fn check_backup(&self, backup: &Path) -> Result {
+        read_backup(backup, |_| OK)?;
+        OK
+    }
+
+    /// Replaces all the trees with the backup contents. They are written into a fresh sled directory first
+    /// which takes the place of the current one only once the whole backup is restored
+    pub async fn restore(&self, backup: &Path) -> Result {
+        self.check_backup(backup)?;
+        self.exclusive(|storage| {
+            let live = storage.path.clone();
+            let staging = live.with_extension("restoring");
+            let replaced = live.with_extension("replaced");
+            for leftover in [&staging, &replaced] {
+                if leftover.exists() {
+                    std::fs::remove_dir_all(leftover)?;
+                }
+            }
+
+            let fresh = sled_config(&staging).open()?;
+            let restored = (|| {
+                let header = read_backup(backup, |BackupEntry { tree, key, value }| {
+                    match tree.as_slice() {
+                        DEFAULT_TREE => fresh.insert(key, value)?,
+                        _ => fresh.open_tree(tree)?.insert(key, value)?,
+                    };
+                    OK
+                })?;
+                // ids generated after the restore should be greater than the ones in both DBs
+                let current = storage.id_offset + storage.tree.generate_id()?;
+                let id_offset = header.id_offset.max(current).max(get_id_offset(&fresh)?);
+                fresh.insert("id_offset", &id_offset.to_be_bytes())?;
+                fresh.flush()?;
+                Ok(id_offset)
+            })();
+            drop(fresh);
+            let id_offset = match restored {
+                Ok(id_offset) => id_offset,
+                Err(e) => {
+                    std::fs::remove_dir_all(&staging)?;
+                    return Err(e);
+                }
+            };
+
+            // the current trees are closed and kept aside until the restored ones are opened in their place
+            storage.tree.flush()?;
+            let placeholder = sled::Config::new().temporary(true).open()?;
+            drop(std::mem::replace(&mut storage.tree, placeholder));
+            let swapped = (|| {
+                std::fs::rename(&live, &replaced)?;
+                std::fs::rename(&staging, &live)?;
+                sled_config(&live).open()
+            })();
+            match swapped {
+                Ok(tree) => {
+                    storage.tree = tree;
+                    std::fs::remove_dir_all(&replaced)?;
+                }
+                Err(e) => {
+                    if replaced.exists() {
+                        if live.exists() {
+                            std::fs::remove_dir_all(&live)?;
+                        }
+                        std::fs::rename(&replaced, &live)?;
+                    }
+                    if staging.exists() {
+                        std::fs::remove_dir_all(&staging)?;
+                    }
+                    storage.tree = sled_config(&live).open()?;
+                    return Err(e.into());
+                }
+            }
+            storage.id_offset = id_offset;
+            storage.build_search_indexes()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Waits for the running transaction and blocks the new ones until the closure is done
+    async fn exclusive<T>(&self, f: impl FnOnce(&mut SledStorage) -> Result<T>) -> Result<T> {
+        if tx_scope() != 0 {
+            return Err(e!(
+                "exclusive storage access can't be used inside of a transaction"
+            ));
+        }
+        self.open_transaction().await?;
+        let mut storage = self.state.db.write().await;
+        let result = tokio::task::block_in_place(|| f(&mut storage));
+        drop(storage);
+        self.close_transaction().await;
+        result
+    }
+}
+
+/// Passes the entries of the backup to the closure once its header is checked
+fn read_backup(backup: &Path, mut f: impl FnMut(BackupEntry) -> Result) -> Result<BackupHeader> {
+    let mut reader = BufReader::new(File::open(backup)?);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != BACKUP_MAGIC {
+        return Err(e!("not a prest DB backup"));
+    }
+    let header: BackupHeader = bincode::deserialize_from(&mut reader)?;
+    let version = header.version;
+    if version != BACKUP_VERSION {
+        return Err(e!("unsupported DB backup version {version}"));
+    }
+    while let Some(entry) = bincode::deserialize_from::<_, Option<BackupEntry>>(&mut reader)? {
+        f(entry)?;
+    }
+    Ok(header)
+}
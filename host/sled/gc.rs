@@ -2,14 +2,46 @@ use {
     super::{
         err_into, key,
         lock::{get_txdata_key, Lock, TxData},
-        SledStorage, Snapshot,
+        SharedSledStorage, SledStorage, Snapshot,
+    },
+    gluesql::core::{
+        data::Schema,
+        error::{Error, Result},
+        store::DataRow,
     },
-    gluesql::core::{data::Schema, error::Result, store::DataRow},
     std::time::{SystemTime, UNIX_EPOCH},
 };
 
+/// Versions of the rows, schemas and index entries removed by the gc and the bytes they took
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcStats {
+    pub snapshots: u64,
+    pub bytes: u64,
+}
+
+impl SharedSledStorage {
+    /// Runs the gc between the transactions, commits run it as well but only while there are writes
+    pub async fn collect_garbage(&self) -> Result<GcStats> {
+        if self.owns_lock() {
+            return Err(Error::StorageMsg(
+                "gc can't run inside of a transaction".to_owned(),
+            ));
+        }
+        self.open_transaction().await?;
+        let db = self.state.db.write().await;
+        // left by the commits interrupted during their gc which would skip it otherwise
+        let result = match db.tree.remove("gc_lock") {
+            Ok(_) => db.gc(),
+            Err(e) => Err(err_into(e)),
+        };
+        drop(db);
+        self.close_transaction().await;
+        result
+    }
+}
+
 impl SledStorage {
-    pub fn gc(&self) -> Result<()> {
+    pub fn gc(&self) -> Result<GcStats> {
         let mut lock: Lock = self
             .tree
             .get("lock/")
@@ -33,6 +65,12 @@ impl SledStorage {
                     .map_err(err_into)
             })
             .take_while(|tx_data| match (tx_data, self.tx_timeout) {
+                // previous versions written by the recent transactions are kept for the time-travel reads
+                (Ok(TxData { created_at, .. }), _)
+                    if now.saturating_sub(*created_at) < self.history_retention =>
+                {
+                    false
+                }
                 (Ok(TxData { alive, .. }), None) => !alive,
                 (Ok(tx_data), Some(tx_timeout)) => {
                     let TxData {
@@ -52,7 +90,7 @@ impl SledStorage {
         let max_txid = match txids.iter().last() {
             Some(txid) => txid,
             None => {
-                return Ok(());
+                return Ok(GcStats::default());
             }
         };
 
@@ -70,81 +108,89 @@ impl SledStorage {
                 .collect::<Result<Vec<_>>>()
         };
 
+        let mut stats = GcStats::default();
+        // temporary entries keep the data keys as values
+        let remove_temp = |temp_key: sled::IVec, data_key: &sled::IVec| -> Result<u64> {
+            self.tree.remove(&temp_key).map_err(err_into)?;
+            Ok((temp_key.len() + data_key.len()) as u64)
+        };
+
         macro_rules! gc_txid {
             ($txid: expr, $prefix: expr, $T: ty) => {
                 for (temp_key, data_key) in fetch_keys($prefix)? {
-                    let snapshot: Option<Snapshot<$T>> = self
-                        .tree
-                        .get(&data_key)
-                        .map_err(err_into)?
-                        .map(|v| bincode::deserialize(&v))
-                        .transpose()
-                        .map_err(err_into)?;
-
-                    let snapshot = match snapshot {
-                        None => {
-                            continue;
-                        }
-                        Some(snapshot) => snapshot.gc($txid),
+                    let Some(value) = self.tree.get(&data_key).map_err(err_into)? else {
+                        continue;
                     };
+                    let snapshot: Snapshot<$T> = bincode::deserialize(&value).map_err(err_into)?;
+                    let versions = snapshot.versions().count();
+                    let snapshot = snapshot.gc($txid);
 
-                    match snapshot {
+                    let kept = match snapshot {
                         Some(snapshot) => {
-                            bincode::serialize(&snapshot)
-                                .map_err(err_into)
-                                .map(|v| self.tree.insert(data_key, v))?
-                                .map_err(err_into)?;
+                            let kept = snapshot.versions().count();
+                            let updated = bincode::serialize(&snapshot).map_err(err_into)?;
+                            stats.bytes += value.len().saturating_sub(updated.len()) as u64;
+                            self.tree.insert(&data_key, updated).map_err(err_into)?;
+                            kept
                         }
                         None => {
-                            self.tree.remove(data_key).map_err(err_into)?;
+                            self.tree.remove(&data_key).map_err(err_into)?;
+                            stats.bytes += (data_key.len() + value.len()) as u64;
+                            0
                         }
-                    }
+                    };
+                    stats.snapshots += (versions - kept) as u64;
 
-                    self.tree.remove(temp_key).map_err(err_into)?;
+                    stats.bytes += remove_temp(temp_key, &data_key)?;
                 }
             };
         }
 
+        let mut collected_rows = vec![];
         for txid in txids {
+            let data_keys = fetch_keys(key::temp_data_prefix(txid))?;
+            collected_rows.extend(data_keys.into_iter().map(|(_, data_key)| data_key));
             gc_txid!(txid, key::temp_data_prefix(txid), DataRow);
             gc_txid!(txid, key::temp_schema_prefix(txid), Schema);
 
             for (temp_key, data_key) in fetch_keys(key::temp_index_prefix(txid))? {
-                let snapshots: Option<Vec<Snapshot<Vec<u8>>>> = self
-                    .tree
-                    .get(&data_key)
-                    .map_err(err_into)?
-                    .map(|v| bincode::deserialize(&v))
-                    .transpose()
-                    .map_err(err_into)?;
-
-                let snapshots = match snapshots {
-                    Some(snapshots) => snapshots,
-                    None => {
-                        continue;
-                    }
+                let Some(value) = self.tree.get(&data_key).map_err(err_into)? else {
+                    continue;
+                };
+                let snapshots: Vec<Snapshot<Vec<u8>>> =
+                    bincode::deserialize(&value).map_err(err_into)?;
+                let versions = |snapshots: &[Snapshot<Vec<u8>>]| -> usize {
+                    snapshots.iter().map(|s| s.versions().count()).sum()
                 };
+                let before = versions(&snapshots);
 
                 let snapshots = snapshots
                     .into_iter()
                     .filter_map(|snapshot| snapshot.gc(txid))
                     .collect::<Vec<_>>();
+                stats.snapshots += (before - versions(&snapshots)) as u64;
 
                 if snapshots.is_empty() {
-                    self.tree.remove(data_key).map_err(err_into)?;
+                    self.tree.remove(&data_key).map_err(err_into)?;
+                    stats.bytes += (data_key.len() + value.len()) as u64;
                 } else {
-                    bincode::serialize(&snapshots)
-                        .map_err(err_into)
-                        .map(|v| self.tree.insert(data_key, v))?
-                        .map_err(err_into)?;
+                    let updated = bincode::serialize(&snapshots).map_err(err_into)?;
+                    stats.bytes += value.len().saturating_sub(updated.len()) as u64;
+                    self.tree.insert(&data_key, updated).map_err(err_into)?;
                 }
 
-                self.tree.remove(temp_key).map_err(err_into)?;
+                stats.bytes += remove_temp(temp_key, &data_key)?;
             }
 
-            self.tree.remove(get_txdata_key(txid)).map_err(err_into)?;
+            let tx_data_key = get_txdata_key(txid);
+            if let Some(tx_data) = self.tree.remove(&tx_data_key).map_err(err_into)? {
+                stats.bytes += (tx_data_key.len() + tx_data.len()) as u64;
+            }
         }
 
-        Ok(())
+        // words of the collected versions are removed from the search index
+        self.reindex_search(&collected_rows)?;
+
+        Ok(stats)
     }
 }
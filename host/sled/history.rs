@@ -0,0 +1,240 @@
+use {
+    super::{
+        err_into, key,
+        lock::{get_txdata_key, Lock, TxData},
+        SharedSledStorage, Snapshot,
+    },
+    crate::tx_scope,
+    gluesql::core::{
+        error::{Error, Result},
+        store::DataRow,
+    },
+    sled::Db,
+    std::{
+        collections::BTreeSet,
+        sync::atomic::Ordering,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Version of a row as `(created_by, deleted_by, data)`
+pub type RowVersion = (u64, Option<u64>, DataRow);
+
+/// Stored rows of a table with their versions
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TableStats {
+    pub rows: u64,
+    /// keys and values of the row versions and the index entries
+    pub bytes: u64,
+    pub versions: u64,
+    /// most versions kept for a single row
+    pub max_depth: u64,
+}
+
+impl SharedSledStorage {
+    /// Rows of the table visible right after the transaction with the `txid`
+    pub async fn scan_as_of(&self, table_name: &str, txid: u64) -> Result<Vec<DataRow>> {
+        let db = self.state.db.read().await;
+        let Lock {
+            lock_txid, gc_txid, ..
+        } = fetch_lock(&db.tree)?;
+        if gc_txid.is_some_and(|gc_txid| txid < gc_txid) {
+            return Err(Error::StorageMsg(format!(
+                "history before the transaction {txid} isn't retained"
+            )));
+        }
+        let lock_txid = lock_txid.filter(|_| !self.owns_lock());
+
+        db.tree
+            .scan_prefix(key::data_prefix(table_name).as_bytes())
+            .map(|item| {
+                let (_, value) = item.map_err(err_into)?;
+                let snapshot: Snapshot<DataRow> = bincode::deserialize(&value).map_err(err_into)?;
+                Ok(snapshot.get(txid, lock_txid))
+            })
+            .filter_map(|result| result.transpose())
+            .collect()
+    }
+
+    /// Versions of every row of the table from the newest to the oldest, grouped by rows
+    pub async fn scan_history(&self, table_name: &str) -> Result<Vec<Vec<RowVersion>>> {
+        let db = self.state.db.read().await;
+        let Lock { lock_txid, .. } = fetch_lock(&db.tree)?;
+        // changes of the running transaction aren't visible to others
+        let lock_txid = lock_txid.filter(|_| !self.owns_lock());
+
+        db.tree
+            .scan_prefix(key::data_prefix(table_name).as_bytes())
+            .map(|item| {
+                let (_, value) = item.map_err(err_into)?;
+                row_versions(&value, lock_txid)
+            })
+            .filter(|versions| !matches!(versions, Ok(versions) if versions.is_empty()))
+            .collect()
+    }
+
+    /// Versions of the rows of the table written by the transactions after the `since` one, grouped by rows.
+    /// Relies on the entries of the transactions kept until the gc, so `since` shouldn't be collected by it yet
+    pub async fn scan_changes(&self, table_name: &str, since: u64) -> Result<Vec<Vec<RowVersion>>> {
+        let db = self.state.db.read().await;
+        let Lock { lock_txid, .. } = fetch_lock(&db.tree)?;
+        let lock_txid = lock_txid.filter(|_| !self.owns_lock());
+
+        let data_prefix = key::data_prefix(table_name);
+        let mut data_keys = BTreeSet::new();
+        for item in db
+            .tree
+            .range(key::temp_data_prefix(since.saturating_add(1))..)
+        {
+            let (temp_key, data_key) = item.map_err(err_into)?;
+            if !temp_key.starts_with(key::TEMP_DATA.as_bytes()) {
+                break;
+            }
+            if data_key.starts_with(data_prefix.as_bytes()) {
+                data_keys.insert(data_key);
+            }
+        }
+
+        let mut changes = vec![];
+        for data_key in data_keys {
+            let Some(value) = db.tree.get(&data_key).map_err(err_into)? else {
+                continue;
+            };
+            let versions = row_versions(&value, lock_txid)?;
+            if !versions.is_empty() {
+                changes.push(versions);
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Latest transaction started at or before the unix timestamp in milliseconds
+    pub async fn txid_at(&self, timestamp: u128) -> Result<u64> {
+        let db = self.state.db.read().await;
+        let mut latest = None;
+        for item in db.tree.scan_prefix("tx_data/") {
+            let (_, value) = item.map_err(err_into)?;
+            let TxData {
+                txid, created_at, ..
+            } = bincode::deserialize(&value).map_err(err_into)?;
+            if created_at > timestamp {
+                break;
+            }
+            latest = Some(txid);
+        }
+        match (latest, fetch_lock(&db.tree)?.gc_txid) {
+            (Some(txid), _) => Ok(txid),
+            (None, None) => Ok(0),
+            (None, Some(_)) => Err(Error::StorageMsg(format!(
+                "history at {timestamp} isn't retained"
+            ))),
+        }
+    }
+
+    /// Start of the transaction as unix timestamp in milliseconds, `None` if it's already collected by gc
+    pub async fn tx_timestamp(&self, txid: u64) -> Result<Option<u128>> {
+        let db = self.state.db.read().await;
+        let tx_data: Option<TxData> = db
+            .tree
+            .get(get_txdata_key(txid))
+            .map_err(err_into)?
+            .map(|value| bincode::deserialize(&value))
+            .transpose()
+            .map_err(err_into)?;
+        Ok(tx_data.map(|tx_data| tx_data.created_at))
+    }
+
+    /// Transaction up to which the versions replaced by others are collected by gc
+    pub async fn gc_txid(&self) -> Result<Option<u64>> {
+        let db = self.state.db.read().await;
+        Ok(fetch_lock(&db.tree)?.gc_txid)
+    }
+
+    /// Oldest transaction which can still write new versions, `None` if all of them are finished
+    pub async fn oldest_running_txid(&self) -> Result<Option<u64>> {
+        let db = self.state.db.read().await;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(err_into)?
+            .as_millis();
+        for item in db.tree.scan_prefix("tx_data/") {
+            let (_, value) = item.map_err(err_into)?;
+            let TxData {
+                txid,
+                alive,
+                created_at,
+            } = bincode::deserialize(&value).map_err(err_into)?;
+            let expired = db
+                .tx_timeout
+                .is_some_and(|tx_timeout| now.saturating_sub(created_at) >= tx_timeout);
+            if alive && !expired {
+                return Ok(Some(txid));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Rows of the table visible to new reads, their versions and the stored bytes
+    pub async fn table_stats(&self, table_name: &str) -> Result<TableStats> {
+        let db = self.state.db.read().await;
+        let Lock { lock_txid, .. } = fetch_lock(&db.tree)?;
+        let lock_txid = lock_txid.filter(|_| !self.owns_lock());
+
+        let mut stats = TableStats::default();
+        for item in db.tree.scan_prefix(key::data_prefix(table_name).as_bytes()) {
+            let (key, value) = item.map_err(err_into)?;
+            let snapshot: Snapshot<DataRow> = bincode::deserialize(&value).map_err(err_into)?;
+            let depth = snapshot.versions().count() as u64;
+            stats.bytes += (key.len() + value.len()) as u64;
+            stats.versions += depth;
+            stats.max_depth = stats.max_depth.max(depth);
+            if snapshot.get(u64::MAX, lock_txid).is_some() {
+                stats.rows += 1;
+            }
+        }
+        for item in db
+            .tree
+            .scan_prefix(format!("index/{table_name}/").as_bytes())
+        {
+            let (key, value) = item.map_err(err_into)?;
+            stats.bytes += (key.len() + value.len()) as u64;
+        }
+        Ok(stats)
+    }
+
+    /// Size of the storage files in bytes
+    pub async fn size_on_disk(&self) -> Result<u64> {
+        let db = self.state.db.read().await;
+        db.tree.size_on_disk().map_err(err_into)
+    }
+
+    pub(super) fn owns_lock(&self) -> bool {
+        let scope = tx_scope();
+        scope != 0 && self.state.owner.load(Ordering::SeqCst) == scope
+    }
+}
+
+/// Versions of the stored snapshot without the ones of the running transaction
+fn row_versions(snapshot: &[u8], lock_txid: Option<u64>) -> Result<Vec<RowVersion>> {
+    let snapshot: Snapshot<DataRow> = bincode::deserialize(snapshot).map_err(err_into)?;
+    let versions = snapshot
+        .versions()
+        .filter(|(created_by, ..)| Some(*created_by) != lock_txid)
+        .map(|(created_by, deleted_by, row)| {
+            let deleted_by = deleted_by.filter(|txid| Some(*txid) != lock_txid);
+            (created_by, deleted_by, row.clone())
+        })
+        .collect();
+    Ok(versions)
+}
+
+pub(super) fn fetch_lock(tree: &Db) -> Result<Lock> {
+    let lock = tree
+        .get("lock/")
+        .map_err(err_into)?
+        .map(|lock| bincode::deserialize(&lock))
+        .transpose()
+        .map_err(err_into)?
+        .unwrap_or_default();
+    Ok(lock)
+}
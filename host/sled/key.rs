@@ -1,6 +1,6 @@
 use sled::IVec;
 
-const TEMP_DATA: &str = "temp_data/";
+pub const TEMP_DATA: &str = "temp_data/";
 const TEMP_SCHEMA: &str = "temp_schema/";
 const TEMP_INDEX: &str = "temp_index/";
 
@@ -1,12 +1,15 @@
 // forked from gluesql sled storage with additions from https://github.com/kanekoshoyu/gluesql_shared_sled_storage
 mod alter_table;
+mod backup;
 mod error;
 mod gc;
+mod history;
 mod index;
 mod index_mut;
 mod index_sync;
 mod key;
 mod lock;
+mod search;
 mod snapshot;
 mod store;
 mod store_mut;
@@ -15,7 +18,7 @@ mod transaction;
 use {
     self::snapshot::Snapshot,
     super::SYSTEM_INFO,
-    crate::{warn, Arc, Result, RwLock},
+    crate::{tx_scope, warn, Arc, Result, RwLock, TxScoped},
     error::{err_into, tx_err_into},
     gluesql::core::{
         data::Schema,
@@ -30,8 +33,10 @@ use {
         Db,
     },
     std::{
+        collections::HashMap,
+        future::Future,
         mem::replace,
-        sync::atomic::{AtomicBool, Ordering},
+        sync::atomic::{AtomicBool, AtomicU64, Ordering},
     },
     tokio::sync::Notify,
 };
@@ -52,16 +57,30 @@ pub struct SharedSledStorage {
 pub(crate) struct RWSledStorage {
     pub db: RwLock<SledStorage>,
     pub in_progress: AtomicBool,
+    /// scope which holds the transaction lock, 0 if none
+    owner: AtomicU64,
     notify: Notify,
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct SledStorage {
     pub tree: Db,
+    /// directory of the tree which is reopened after restoring a backup
+    pub path: std::path::PathBuf,
     pub id_offset: u64,
     pub state: State,
     /// transaction timeout in milliseconds
     pub tx_timeout: Option<u128>,
+    /// how long `gc` keeps the previous versions of the rows in milliseconds
+    pub history_retention: u128,
+    /// positions of the `#[searchable]` columns by tables
+    pub searchable: Arc<std::sync::RwLock<HashMap<String, Vec<usize>>>>,
+}
+
+impl SledStorage {
+    fn in_transaction(&self) -> bool {
+        matches!(self.state, State::Transaction { .. })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -74,26 +93,24 @@ pub(crate) enum State {
     },
 }
 
-type ExportData<T> = (u64, Vec<(Vec<u8>, Vec<u8>, T)>);
-
 impl SharedSledStorage {
-    pub fn new(db_path: std::path::PathBuf) -> crate::Result<Self> {
-        let total_ram_mbs = SYSTEM_INFO.ram;
-        let sled_config = sled::Config::default()
-            .path(db_path)
-            // use up to 20% ram for cache
-            .cache_capacity(total_ram_mbs.div_ceil(5) * 1_000_000);
-
-        let tree = sled_config.open().map_err(err_into)?;
+    pub fn new(
+        db_path: std::path::PathBuf,
+        history_retention: std::time::Duration,
+    ) -> crate::Result<Self> {
+        let tree = sled_config(&db_path).open().map_err(err_into)?;
         let id_offset = get_id_offset(&tree)?;
         let state = State::Idle;
         let tx_timeout = Some(DEFAULT_TX_TIMEOUT);
 
         let mut database = SledStorage {
             tree,
+            path: db_path,
             id_offset,
             state,
             tx_timeout,
+            history_retention: history_retention.as_millis(),
+            searchable: Default::default(),
         };
 
         match replace(&mut database.state, State::Idle) {
@@ -122,6 +139,7 @@ impl SharedSledStorage {
             state: Arc::new(RWSledStorage {
                 db: RwLock::new(database),
                 in_progress: AtomicBool::new(false),
+                owner: AtomicU64::new(0),
                 notify: Notify::new(),
             }),
         };
@@ -129,6 +147,12 @@ impl SharedSledStorage {
     }
     async fn open_transaction(&self) -> GlueResult<()> {
         let state = &self.state;
+        let scope = tx_scope();
+
+        // statements inside of the transaction scope reuse its lock
+        if scope != 0 && state.owner.load(Ordering::SeqCst) == scope {
+            return Ok(());
+        }
 
         while state
             .in_progress
@@ -138,47 +162,91 @@ impl SharedSledStorage {
             // Await notification that the transaction has completed.
             state.notify.notified().await;
         }
+        state.owner.store(scope, Ordering::SeqCst);
 
         Ok(())
     }
     async fn close_transaction(&self) {
         // Set the transaction as not in progress and notify all waiting.
         let state = &self.state;
+        state.owner.store(0, Ordering::SeqCst);
         state.in_progress.store(false, Ordering::Release);
         state.notify.notify_one();
     }
 
+    /// Runs the future inside of a single transaction which is committed if it succeeds and rolled back otherwise.
+    /// Statements and transactions started by the future (in the same task) join the transaction instead of waiting for it.
+    pub async fn transaction<T, F>(&self, fut: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        use {crate::host::await_blocking, gluesql::core::store::Transaction};
+        // nested transactions are merged into the outer one
+        let current = tx_scope();
+        if current != 0 && self.state.owner.load(Ordering::SeqCst) == current {
+            return fut.await;
+        }
+        let mut storage = self.clone();
+        TxScoped::new(async move {
+            // glue futures aren't Send so they are awaited in place
+            await_blocking(storage.begin(false))?;
+            // dropped futures shouldn't keep the lock
+            let mut rollback = RollbackOnDrop(Some(storage.clone()));
+            let result = fut.await;
+            rollback.0 = None;
+            let result = match result {
+                Ok(value) => match await_blocking(storage.commit()) {
+                    Ok(()) => Ok(value),
+                    Err(e) => {
+                        // commit fails before releasing the lock if the transaction timed out
+                        if await_blocking(storage.state.db.read()).in_transaction() {
+                            await_blocking(storage.rollback())?;
+                        }
+                        Err(e.into())
+                    }
+                },
+                Err(e) => {
+                    await_blocking(storage.rollback())?;
+                    Err(e)
+                }
+            };
+            // make sure that the outcome is persisted even if the app exits right away
+            let tree = storage.state.db.read().await.tree.clone();
+            tree.flush_async().await.map_err(err_into)?;
+            result
+        })
+        .await
+    }
+
     pub fn flush(&self) -> sled::Result<usize> {
         self.state.db.blocking_write().tree.flush()
     }
+}
 
-    #[allow(dead_code)]
-    pub fn export(&self) -> Result<ExportData<impl Iterator<Item = Vec<Vec<u8>>>>> {
-        let storage = self.state.db.blocking_write();
-        let id_offset = storage.id_offset + storage.tree.generate_id()?;
-        let data = storage.tree.export();
+/// Rolls back the transaction of the future which was dropped before it finished
+struct RollbackOnDrop(Option<SharedSledStorage>);
 
-        Ok((id_offset, data))
+impl Drop for RollbackOnDrop {
+    fn drop(&mut self) {
+        use gluesql::core::store::Transaction;
+        let Some(mut storage) = self.0.take() else {
+            return;
+        };
+        // glue futures aren't Send and the future might be dropped outside of the runtime
+        std::thread::spawn(move || {
+            if let Err(e) = futures::executor::block_on(storage.rollback()) {
+                warn!(target: "storage", "error rolling back dropped transaction: {e}");
+            }
+        });
     }
+}
 
-    #[allow(dead_code)]
-    pub fn import(&mut self, export: ExportData<impl Iterator<Item = Vec<Vec<u8>>>>) -> Result<()> {
-        let mut storage = self.state.db.blocking_write();
-        let (new_id_offset, data) = export;
-        let old_id_offset = get_id_offset(&storage.tree)?;
-
-        storage.tree.import(data);
-
-        if new_id_offset > old_id_offset {
-            storage
-                .tree
-                .insert("id_offset", &new_id_offset.to_be_bytes())?;
-
-            storage.id_offset = new_id_offset;
-        }
-
-        Ok(())
-    }
+pub(crate) fn sled_config(path: &std::path::Path) -> sled::Config {
+    let total_ram_mbs = SYSTEM_INFO.ram;
+    sled::Config::default()
+        .path(path)
+        // use up to 20% ram for cache
+        .cache_capacity(total_ram_mbs.div_ceil(5) * 1_000_000)
 }
 
 fn get_id_offset(tree: &Db) -> GlueResult<u64> {
@@ -211,3 +279,222 @@ pub(crate) fn fetch_schema(
 impl Metadata for SharedSledStorage {}
 impl CustomFunction for SharedSledStorage {}
 impl CustomFunctionMut for SharedSledStorage {}
+
+#[cfg(test)]
+mod test {
+    use super::SharedSledStorage;
+    use crate::*;
+    use gluesql::{
+        core::store::DataRow,
+        prelude::{Glue, Payload, Value},
+    };
+    use std::time::Duration;
+
+    fn storage() -> SharedSledStorage {
+        let path = std::env::temp_dir().join(format!("prest-sled-test-{}", Uuid::now_v7()));
+        SharedSledStorage::new(path, Duration::ZERO).expect("sled storage should open")
+    }
+
+    async fn execute(storage: &SharedSledStorage, sql: &str) -> Result<Vec<Payload>> {
+        Glue::new(storage.clone())
+            .execute(sql)
+            .await
+            .map_err(|e| e!("{e}"))
+    }
+
+    async fn count(storage: &SharedSledStorage) -> Result<i64> {
+        let payload = execute(storage, "SELECT COUNT(*) FROM items").await?;
+        match payload.first() {
+            Some(Payload::Select { rows, .. }) => match rows[0][0] {
+                Value::I64(count) => Ok(count),
+                ref other => Err(e!("unexpected count: {other:?}")),
+            },
+            other => Err(e!("unexpected payload: {other:?}")),
+        }
+    }
+
+    fn test_storage(test: impl std::future::Future<Output = Result>) {
+        let runtime = tokio::runtime::Runtime::new().expect("test runtime should start");
+        runtime.block_on(test).expect("test should succeed");
+    }
+
+    #[test]
+    fn dropped_transactions_are_rolled_back() {
+        test_storage(async {
+            let storage = storage();
+            execute(&storage, "CREATE TABLE items (id INTEGER)").await?;
+
+            let dropped = storage.transaction(async {
+                execute(&storage, "INSERT INTO items VALUES (1)").await?;
+                std::future::pending::<()>().await;
+                OK
+            });
+            assert!(tokio::time::timeout(Duration::from_millis(50), dropped)
+                .await
+                .is_err());
+
+            let next = storage.transaction(async {
+                execute(&storage, "INSERT INTO items VALUES (2)").await?;
+                OK
+            });
+            tokio::time::timeout(Duration::from_secs(5), next)
+                .await
+                .map_err(|_| e!("the lock of the dropped transaction is still held"))??;
+            assert_eq!(count(&storage).await?, 1);
+            OK
+        });
+    }
+
+    #[test]
+    fn failed_transactions_are_rolled_back() {
+        test_storage(async {
+            let storage = storage();
+            execute(&storage, "CREATE TABLE items (id INTEGER)").await?;
+
+            let failed = storage.transaction(async {
+                execute(&storage, "INSERT INTO items VALUES (1)").await?;
+                Err::<(), _>(e!("failed"))
+            });
+            assert!(failed.await.is_err());
+
+            storage
+                .transaction(async {
+                    execute(&storage, "INSERT INTO items VALUES (2)").await?;
+                    OK
+                })
+                .await?;
+            assert_eq!(count(&storage).await?, 1);
+            OK
+        });
+    }
+
+    #[test]
+    fn replaced_versions_are_read_as_of_their_transactions() {
+        test_storage(async {
+            let path = std::env::temp_dir().join(format!("prest-sled-test-{}", Uuid::now_v7()));
+            let storage = SharedSledStorage::new(path, Duration::from_secs(3600))?;
+            execute(&storage, "CREATE TABLE items (id INTEGER, name TEXT)").await?;
+            execute(&storage, "INSERT INTO items VALUES (1, 'first')").await?;
+            execute(&storage, "UPDATE items SET name = 'second' WHERE id = 1").await?;
+            execute(&storage, "DELETE FROM items WHERE id = 1").await?;
+
+            let history = storage.scan_history("items").await?;
+            let [versions] = history.as_slice() else {
+                return Err(e!("expected the versions of a single row: {history:?}"));
+            };
+            let [(second_txid, deleted_by, second), (first_txid, replaced_by, first)] =
+                versions.as_slice()
+            else {
+                return Err(e!("expected two versions: {versions:?}"));
+            };
+            assert_eq!(
+                (name(first), name(second)),
+                ("first".into(), "second".into())
+            );
+            // each version is replaced by the transaction which wrote the next one
+            assert_eq!(*replaced_by, Some(*second_txid));
+            let deleted_by = deleted_by.expect("deleted version");
+
+            let names = |rows: Vec<DataRow>| rows.iter().map(name).collect::<Vec<_>>();
+            let as_of = |txid| storage.scan_as_of("items", txid);
+            assert_eq!(names(as_of(*first_txid).await?), ["first"]);
+            assert_eq!(names(as_of(*second_txid).await?), ["second"]);
+            assert!(as_of(deleted_by).await?.is_empty());
+            assert!(as_of(first_txid - 1).await?.is_empty());
+            OK
+        });
+    }
+
+    #[test]
+    fn changes_are_scanned_after_the_transaction() {
+        test_storage(async {
+            let path = std::env::temp_dir().join(format!("prest-sled-test-{}", Uuid::now_v7()));
+            let storage = SharedSledStorage::new(path, Duration::from_secs(3600))?;
+            execute(&storage, "CREATE TABLE items (id INTEGER, name TEXT)").await?;
+            execute(&storage, "INSERT INTO items VALUES (1, 'kept')").await?;
+            execute(&storage, "INSERT INTO items VALUES (2, 'first')").await?;
+            let inserted = storage.scan_history("items").await?;
+            let txids = inserted.iter().map(|versions| versions[0].0);
+            let (first, since) = (txids.clone().min(), txids.max());
+            let (first, since) = (first.expect("inserted rows"), since.expect("inserted rows"));
+            execute(&storage, "UPDATE items SET name = 'second' WHERE id = 2").await?;
+
+            let changes = storage.scan_changes("items", since).await?;
+            let [versions] = changes.as_slice() else {
+                return Err(e!("expected the versions of the updated row: {changes:?}"));
+            };
+            assert_eq!(name(&versions[0].2), "second");
+            assert!(versions[0].0 > since);
+            assert_eq!(storage.scan_changes("items", first - 1).await?.len(), 2);
+            OK
+        });
+    }
+
+    fn name(row: &DataRow) -> String {
+        match row {
+            DataRow::Vec(values) => String::from(&values[1]),
+            DataRow::Map(_) => panic!("expected a row of a table with columns"),
+        }
+    }
+
+    async fn postings(storage: &SharedSledStorage, word: &str) -> Result<usize> {
+        let db = storage.state.db.read().await;
+        let prefix = format!("notes\0{word}\0");
+        Ok(db.search_tree()?.scan_prefix(prefix).count())
+    }
+
+    #[test]
+    fn stale_postings_are_removed() {
+        test_storage(async {
+            let storage = storage();
+            execute(&storage, "CREATE TABLE notes (id INTEGER, text TEXT)").await?;
+            storage.register_search("notes", vec![1]).await?;
+            execute(&storage, "INSERT INTO notes VALUES (1, 'old words')").await?;
+            assert_eq!(postings(&storage, "old").await?, 1);
+
+            execute(&storage, "UPDATE notes SET text = 'new words' WHERE id = 1").await?;
+            assert_eq!(postings(&storage, "new").await?, 1);
+            storage.collect_garbage().await?;
+            assert_eq!(postings(&storage, "old").await?, 0);
+            assert_eq!(postings(&storage, "words").await?, 1);
+
+            execute(&storage, "DELETE FROM notes WHERE id = 1").await?;
+            storage.collect_garbage().await?;
+            assert_eq!(postings(&storage, "new").await?, 0);
+            assert_eq!(postings(&storage, "words").await?, 0);
+
+            let failed = storage.transaction(async {
+                execute(&storage, "INSERT INTO notes VALUES (2, 'rolled back')").await?;
+                Err::<(), _>(e!("failed"))
+            });
+            assert!(failed.await.is_err());
+            assert_eq!(postings(&storage, "rolled").await?, 0);
+            OK
+        });
+    }
+
+    #[test]
+    fn backups_are_restored_only_when_readable() {
+        test_storage(async {
+            let storage = storage();
+            execute(&storage, "CREATE TABLE items (id INTEGER)").await?;
+            execute(&storage, "INSERT INTO items VALUES (1)").await?;
+
+            let backup = std::env::temp_dir().join(format!("prest-backup-{}", Uuid::now_v7()));
+            storage.backup(&mut std::fs::File::create(&backup)?).await?;
+            let data = std::fs::read(&backup)?;
+            let truncated = backup.with_extension("truncated");
+            std::fs::write(&truncated, &data[..data.len() - 1])?;
+
+            execute(&storage, "INSERT INTO items VALUES (2)").await?;
+            assert!(storage.restore(&truncated).await.is_err());
+            assert_eq!(count(&storage).await?, 2);
+
+            storage.restore(&backup).await?;
+            assert_eq!(count(&storage).await?, 1);
+            execute(&storage, "INSERT INTO items VALUES (3)").await?;
+            assert_eq!(count(&storage).await?, 2);
+            OK
+        });
+    }
+}
@@ -0,0 +1,197 @@
+use {
+    super::{err_into, history::fetch_lock, key, lock::Lock, SharedSledStorage, SledStorage, Snapshot},
+    crate::db::tokenize,
+    gluesql::core::{data::Value, error::Result, store::DataRow},
+    sled::{IVec, Tree},
+    std::collections::HashSet,
+};
+
+/// Tree of the inverted index with `{table}\0{word}\0{data key}` postings, `\x01{table}\0{data key}` entries
+/// of the words indexed for the rows and `\0{table}` entries of the indexed columns
+const SEARCH_TREE: &str = "search";
+/// Indexes built in other formats are rebuilt
+const INDEX_FORMAT: u8 = 1;
+
+impl SharedSledStorage {
+    /// Indexes the words of the columns in the new rows of the table, rebuilds the index if the columns changed
+    pub async fn register_search(&self, table_name: &str, columns: Vec<usize>) -> Result<()> {
+        let db = self.state.db.read().await;
+        db.searchable
+            .write()
+            .expect("searchable columns")
+            .insert(table_name.to_owned(), columns);
+        db.build_search_index(table_name)
+    }
+
+    /// Latest versions of the rows which contain words starting with every term
+    pub async fn search_rows(&self, table_name: &str, terms: &[String]) -> Result<Vec<DataRow>> {
+        let db = self.state.db.read().await;
+        let search = db.search_tree()?;
+        let Lock { lock_txid, .. } = fetch_lock(&db.tree)?;
+        // changes of the running transaction aren't visible to others
+        let lock_txid = lock_txid.filter(|_| !self.owns_lock());
+
+        let mut candidates: Option<HashSet<IVec>> = None;
+        for term in terms {
+            let prefix = [table_name.as_bytes(), b"\0", term.as_bytes()].concat();
+            let mut matches = HashSet::new();
+            for item in search.scan_prefix(&prefix) {
+                let (posting, _) = item.map_err(err_into)?;
+                matches.extend(posting_data_key(table_name, &posting));
+            }
+            candidates = Some(match candidates {
+                None => matches,
+                Some(candidates) => candidates.intersection(&matches).cloned().collect(),
+            });
+        }
+
+        let mut rows = vec![];
+        let mut stale = vec![];
+        for data_key in candidates.unwrap_or_default() {
+            let Some(snapshot) = db.tree.get(&data_key).map_err(err_into)? else {
+                stale.push(data_key);
+                continue;
+            };
+            let snapshot: Snapshot<DataRow> = bincode::deserialize(&snapshot).map_err(err_into)?;
+            rows.extend(snapshot.get(u64::MAX, lock_txid));
+        }
+        // rows removed without reindexing don't need postings
+        db.reindex_search(&stale)?;
+        Ok(rows)
+    }
+}
+
+impl SledStorage {
+    pub(super) fn search_tree(&self) -> Result<Tree> {
+        self.tree.open_tree(SEARCH_TREE).map_err(err_into)
+    }
+
+    /// Brings the postings of the rows in line with the words of their stored versions: adds the new words
+    /// and removes the stale ones left by updates, deletions collected by gc and rollbacks
+    pub(super) fn reindex_search<'a>(
+        &self,
+        data_keys: impl IntoIterator<Item = &'a IVec>,
+    ) -> Result<()> {
+        let searchable = self.searchable.read().expect("searchable columns");
+        if searchable.is_empty() {
+            return Ok(());
+        }
+        let search = self.search_tree()?;
+        let mut batch = sled::Batch::default();
+        for data_key in data_keys {
+            let Some((table_name, columns)) = searchable
+                .iter()
+                .find(|(table_name, _)| data_key.starts_with(key::data_prefix(table_name).as_bytes()))
+            else {
+                continue;
+            };
+            let words = match self.tree.get(data_key).map_err(err_into)? {
+                Some(snapshot) => {
+                    let snapshot: Snapshot<DataRow> = bincode::deserialize(&snapshot).map_err(err_into)?;
+                    snapshot
+                        .versions()
+                        .flat_map(|(.., row)| row_words(row, columns))
+                        .collect()
+                }
+                None => HashSet::new(),
+            };
+            let words_key = words_key(table_name, data_key);
+            let indexed: HashSet<String> = match search.get(&words_key).map_err(err_into)? {
+                Some(indexed) => bincode::deserialize(&indexed).map_err(err_into)?,
+                None => HashSet::new(),
+            };
+            for word in indexed.difference(&words) {
+                batch.remove(posting(table_name, word, data_key));
+            }
+            for word in words.difference(&indexed) {
+                batch.insert(posting(table_name, word, data_key), &[]);
+            }
+            match words.is_empty() {
+                true => batch.remove(words_key),
+                false => batch.insert(words_key, bincode::serialize(&words).map_err(err_into)?),
+            }
+        }
+        search.apply_batch(batch).map_err(err_into)
+    }
+
+    /// Reindexes every version of the table's rows unless they are indexed for the same columns already
+    pub(super) fn build_search_index(&self, table_name: &str) -> Result<()> {
+        let columns = match self
+            .searchable
+            .read()
+            .expect("searchable columns")
+            .get(table_name)
+        {
+            Some(columns) => bincode::serialize(&(INDEX_FORMAT, columns)).map_err(err_into)?,
+            None => return Ok(()),
+        };
+        let search = self.search_tree()?;
+        let columns_key = [b"\0", table_name.as_bytes()].concat();
+        if search.get(&columns_key).map_err(err_into)?.as_deref() == Some(columns.as_slice()) {
+            return Ok(());
+        }
+
+        for prefix in [
+            [table_name.as_bytes(), b"\0"].concat(),
+            words_key(table_name, &IVec::default()),
+        ] {
+            for item in search.scan_prefix(&prefix) {
+                let (entry, _) = item.map_err(err_into)?;
+                search.remove(entry).map_err(err_into)?;
+            }
+        }
+        let data_keys = self
+            .tree
+            .scan_prefix(key::data_prefix(table_name))
+            .keys()
+            .collect::<sled::Result<Vec<_>>>()
+            .map_err(err_into)?;
+        self.reindex_search(&data_keys)?;
+        search.insert(columns_key, columns).map_err(err_into)?;
+        Ok(())
+    }
+
+    /// Rebuilds the indexes of the registered tables if they are missing
+    pub(super) fn build_search_indexes(&self) -> Result<()> {
+        let tables = self
+            .searchable
+            .read()
+            .expect("searchable columns")
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        for table_name in tables {
+            self.build_search_index(&table_name)?;
+        }
+        Ok(())
+    }
+}
+
+fn posting(table_name: &str, word: &str, data_key: &IVec) -> Vec<u8> {
+    [table_name.as_bytes(), b"\0", word.as_bytes(), b"\0", data_key].concat()
+}
+
+fn words_key(table_name: &str, data_key: &IVec) -> Vec<u8> {
+    [b"\x01", table_name.as_bytes(), b"\0", data_key].concat()
+}
+
+/// Words of the searchable columns in the row
+fn row_words(row: &DataRow, columns: &[usize]) -> HashSet<String> {
+    let DataRow::Vec(values) = row else {
+        return HashSet::new();
+    };
+    columns
+        .iter()
+        .filter_map(|i| match values.get(*i) {
+            Some(Value::Str(text)) => Some(tokenize(text)),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+fn posting_data_key(table_name: &str, posting: &[u8]) -> Option<IVec> {
+    let rest = posting.get(table_name.len() + 1..)?;
+    let end = rest.iter().position(|byte| *byte == 0)?;
+    Some(IVec::from(&rest[end + 1..]))
+}
@@ -122,13 +122,21 @@ impl<T: Clone> Snapshot<T> {
         None
     }
 
+    /// Versions from the newest to the oldest as `(created_by, deleted_by, data)`
+    pub fn versions(&self) -> impl Iterator<Item = (u64, Option<u64>, &T)> {
+        self.0
+            .iter()
+            .map(|item| (item.created_by, item.deleted_by, &item.data))
+    }
+
     pub fn gc(self, txid: u64) -> Option<Self> {
+        // replaced versions are behind the current one so all of them are checked
         let items = self
             .0
             .into_iter()
-            .skip_while(|SnapshotItem { deleted_by, .. }| match deleted_by {
-                Some(d_txid) => d_txid <= &txid,
-                None => false,
+            .filter(|SnapshotItem { deleted_by, .. }| match deleted_by {
+                Some(d_txid) => d_txid > &txid,
+                None => true,
             })
             .collect::<Vec<_>>();
 
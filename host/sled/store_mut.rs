@@ -167,22 +167,26 @@ impl StoreMut for SharedSledStorage {
         let state = &db.state;
         let tx_timeout = db.tx_timeout;
         let tx_rows = &rows;
+        // keys of the appended rows for the search index
+        let keys = std::cell::RefCell::new(vec![]);
 
-        let tx_result = db.tree.transaction(move |tree| {
-            let (txid, autocommit) = match lock::acquire(tree, state, tx_timeout)? {
-                LockAcquired::Success { txid, autocommit } => (txid, autocommit),
+        let tx_result = db.tree.transaction(|tree| {
+            let txid = match lock::acquire(tree, state, tx_timeout)? {
+                LockAcquired::Success { txid, .. } => txid,
                 LockAcquired::RollbackAndRetry { lock_txid } => {
                     return Ok(TxPayload::RollbackAndRetry(lock_txid));
                 }
             };
 
             let index_sync = IndexSync::new(tree, txid, table_name)?;
+            keys.borrow_mut().clear();
 
             await_blocking(async {
                 for row in tx_rows.iter() {
                     let id = id_offset + tree.generate_id()?;
                     let id = id.to_be_bytes();
                     let key = key::data(table_name, id.to_vec());
+                    keys.borrow_mut().push(key.clone());
 
                     index_sync.insert(&key, row).await?;
 
@@ -193,11 +197,9 @@ impl StoreMut for SharedSledStorage {
 
                     tree.insert(&key, snapshot)?;
 
-                    if !autocommit {
-                        let temp_key = key::temp_data(txid, &key);
-
-                        tree.insert(temp_key, key)?;
-                    }
+                    // registered for the autocommit statements too so that they can be rolled back and collected by gc
+                    let temp_key = key::temp_data(txid, &key);
+                    tree.insert(temp_key, key)?;
                 }
 
                 Ok(()) as ConflictableTransactionResult<(), Error>
@@ -214,6 +216,8 @@ impl StoreMut for SharedSledStorage {
 
             drop(db);
             self.append_data(table_name, rows).await?;
+        } else {
+            db.reindex_search(keys.borrow().iter())?;
         }
 
         Ok(())
@@ -226,8 +230,8 @@ impl StoreMut for SharedSledStorage {
         let tx_rows = &rows;
 
         let tx_result = db.tree.transaction(move |tree| {
-            let (txid, autocommit) = match lock::acquire(tree, state, tx_timeout)? {
-                LockAcquired::Success { txid, autocommit } => (txid, autocommit),
+            let txid = match lock::acquire(tree, state, tx_timeout)? {
+                LockAcquired::Success { txid, .. } => txid,
                 LockAcquired::RollbackAndRetry { lock_txid } => {
                     return Ok(TxPayload::RollbackAndRetry(lock_txid));
                 }
@@ -273,11 +277,8 @@ impl StoreMut for SharedSledStorage {
 
                     tree.insert(&key, snapshot)?;
 
-                    if !autocommit {
-                        let temp_key = key::temp_data(txid, &key);
-
-                        tree.insert(temp_key, key)?;
-                    }
+                    let temp_key = key::temp_data(txid, &key);
+                    tree.insert(temp_key, key)?;
                 }
                 Ok(()) as ConflictableTransactionResult<(), Error>
             })?;
@@ -293,6 +294,12 @@ impl StoreMut for SharedSledStorage {
 
             drop(db);
             self.insert_data(table_name, rows).await?;
+        } else {
+            let keys = rows
+                .iter()
+                .map(|(key, _)| Ok(key::data(table_name, key.to_cmp_be_bytes()?)))
+                .collect::<Result<Vec<_>>>()?;
+            db.reindex_search(&keys)?;
         }
 
         Ok(())
@@ -305,8 +312,8 @@ impl StoreMut for SharedSledStorage {
         let tx_keys = &keys;
 
         let tx_result = db.tree.transaction(move |tree| {
-            let (txid, autocommit) = match lock::acquire(tree, state, tx_timeout)? {
-                LockAcquired::Success { txid, autocommit } => (txid, autocommit),
+            let txid = match lock::acquire(tree, state, tx_timeout)? {
+                LockAcquired::Success { txid, .. } => txid,
                 LockAcquired::RollbackAndRetry { lock_txid } => {
                     return Ok(TxPayload::RollbackAndRetry(lock_txid));
                 }
@@ -344,11 +351,8 @@ impl StoreMut for SharedSledStorage {
 
                     index_sync.delete(&key, &row).await?;
 
-                    if !autocommit {
-                        let temp_key = key::temp_data(txid, &key);
-
-                        tree.insert(temp_key, key)?;
-                    }
+                    let temp_key = key::temp_data(txid, &key);
+                    tree.insert(temp_key, key)?;
                 }
 
                 Ok(()) as ConflictableTransactionResult<(), Error>
@@ -114,22 +114,23 @@ impl Transaction for SharedSledStorage {
         db.state = State::Idle;
 
         if db.tree.get("gc_lock").map_err(err_into)?.is_some() {
+            drop(db);
+            self.close_transaction().await;
             return Ok(());
         }
 
         db.tree.insert("gc_lock", &[1]).map_err(err_into)?;
 
-        let gc_result = db.gc();
+        let gc_result = db.gc().map(|_| ());
 
-        db.tree.remove("gc_lock").map_err(err_into)?;
+        let unlocked = db.tree.remove("gc_lock").map_err(err_into);
 
         drop(db);
 
-        if gc_result.is_ok() {
-            self.close_transaction().await;
-        }
+        // the transaction is already committed so others shouldn't wait for it even if gc fails
+        self.close_transaction().await;
 
-        gc_result
+        gc_result.and(unlocked.map(|_| ()))
     }
 }
 
@@ -186,7 +187,7 @@ impl SledStorage {
         let index_items = fetch_items(key::temp_index_prefix(txid))?;
 
         self.tree
-            .transaction(move |tree| {
+            .transaction(|tree| {
                 rollback_items::<DataRow>(tree, txid, &data_items)?;
                 rollback_items::<Schema>(tree, txid, &schema_items)?;
 
@@ -225,7 +226,9 @@ impl SledStorage {
 
                 Ok(())
             })
-            .map_err(tx_err_into)
+            .map_err(tx_err_into)?;
+
+        self.reindex_search(data_items.iter().map(|(_, data_key)| data_key))
     }
 
     pub fn check_retry(
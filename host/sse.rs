@@ -22,7 +22,9 @@ pub struct SseBroadcast<T: Clone + Send> {
 
 impl<T: Clone + Send> Default for SseBroadcast<T> {
     fn default() -> Self {
-        let (sender, receiver) = broadcast(1000);
+        let (mut sender, receiver) = broadcast(1000);
+        // the inner receiver is never read so the oldest events are dropped instead of blocking
+        sender.set_overflow(true);
         Self { sender, receiver }
     }
 }
@@ -78,3 +80,48 @@ impl<T: Clone + Send + 'static + std::marker::Sync> SseBroadcastExt<T> for SseBr
     //     get(|| async {})
     // }
 }
+
+/// Utility to stream [`Table::subscribe`]d [`Change`]s as server sent events or into [`SseBroadcast`]s
+pub trait ChangesSseExt<T> {
+    /// Renders every change into the event name and its data
+    fn stream_and_render<F>(self, f: F) -> Response
+    where
+        F: FnMut(Change<T>) -> (String, Markup) + std::marker::Send + 'static;
+
+    /// Spawns a task which sends every change into the broadcast with the event name returned by `f`
+    fn forward_to<B, F>(self, broadcast: &'static SseBroadcast<B>, f: F)
+    where
+        B: Clone + Send + Sync + 'static,
+        F: FnMut(Change<T>) -> (String, B) + std::marker::Send + 'static;
+}
+
+impl<T: Clone + Send + Sync + 'static> ChangesSseExt<T> for Changes<T> {
+    fn stream_and_render<F>(self, mut f: F) -> Response
+    where
+        F: FnMut(Change<T>) -> (String, Markup) + std::marker::Send + 'static,
+    {
+        let stream = self.map(move |change| {
+            let (event_name, rendered) = f(change);
+            SseEvent::default().event(event_name).data(rendered.0)
+        });
+
+        Sse::new(stream.map(Ok::<axum::response::sse::Event, std::convert::Infallible>))
+            .keep_alive(SseKeepAlive::default())
+            .into_response()
+    }
+
+    fn forward_to<B, F>(mut self, broadcast: &'static SseBroadcast<B>, mut f: F)
+    where
+        B: Clone + Send + Sync + 'static,
+        F: FnMut(Change<T>) -> (String, B) + std::marker::Send + 'static,
+    {
+        RT.spawn(async move {
+            while let Some(change) = self.next().await {
+                let (event_name, data) = f(change);
+                if let Err(e) = broadcast.send(event_name, data).await {
+                    warn!(target: "sse", "failed to forward table change: {e}");
+                }
+            }
+        });
+    }
+}
@@ -6,6 +6,7 @@ use sysinfo::{
 /// Describes collected stats for system resources
 #[derive(Debug, Table, Serialize, Deserialize)]
 pub(crate) struct SystemStat {
+    #[index_column]
     pub timestamp: NaiveDateTime,
     pub app_cpu: f32,
     pub other_cpu: f32,
@@ -77,7 +78,7 @@ impl SystemInfo {
         let app_cpu = current.cpu_usage() / SYSTEM_INFO.cores as f32;
         let other_cpu = sys.global_cpu_usage() - app_cpu;
 
-        let stats = SystemStat {
+        let mut stats = SystemStat {
             timestamp: Utc::now().naive_utc(),
             app_cpu,
             other_cpu,
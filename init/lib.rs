@@ -24,6 +24,56 @@ pub fn init(
     init_pc2(args.into(), item.into()).into()
 }
 
+/// Turns `async fn name() -> Result` into a versioned `prest::Migration` and registers it to run during the `init`
+#[proc_macro_attribute]
+pub fn migration(
+    args: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    migration_pc2(args.into(), item.into()).into()
+}
+
+pub(crate) fn migration_pc2(args: TokenStream, item: TokenStream) -> TokenStream {
+    let version: syn::LitInt = match syn::parse2(args) {
+        Ok(version) => version,
+        Err(e) => return token_stream_with_error(item, e),
+    };
+    let input: syn::ItemFn = match syn::parse2(item.clone()) {
+        Ok(it) => it,
+        Err(e) => return token_stream_with_error(item, e),
+    };
+    if input.sig.asyncness.is_none() || !input.sig.inputs.is_empty() {
+        let msg = "migration macro should be only used on async functions without arguments";
+        let e = syn::Error::new_spanned(&input.sig.ident, msg);
+        return token_stream_with_error(item, e);
+    }
+
+    let syn::ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = input;
+    let name = &sig.ident;
+    // registration should be disabled along with the migration itself
+    let cfgs = attrs.iter().filter(|attr| attr.path().is_ident("cfg"));
+
+    quote! {
+        #(#attrs)*
+        #[allow(non_upper_case_globals)]
+        #vis const #name: prest::Migration = {
+            #sig #block
+            prest::Migration {
+                version: #version,
+                name: stringify!(#name),
+                run: || Box::pin(#name()),
+            }
+        };
+        #(#cfgs)*
+        prest::_inventory::submit! { #name }
+    }
+}
+
 pub(crate) fn init_pc2(args: TokenStream, item: TokenStream) -> TokenStream {
     // If any of the steps for this macro fail, we still want to expand to an item that is as close
     // to the expected output as possible. This helps out IDEs such that completions and other
@@ -221,6 +271,7 @@ fn expand(mut input: ItemFn, config: Config) -> TokenStream {
         manifest_dir,
         persistent,
         domain,
+        history_retention_secs,
     } = config.manifest;
 
     let domain = match domain {
@@ -228,7 +279,7 @@ fn expand(mut input: ItemFn, config: Config) -> TokenStream {
         None => quote!(None),
     };
     let init_config = quote!(
-        prest::APP_CONFIG._init(#manifest_dir, #name, #version, #persistent, #domain)
+        prest::APP_CONFIG._init(#manifest_dir, #name, #version, #persistent, #domain, #history_retention_secs)
     );
 
     let filters = config.log_filters.into_iter().map(|(filter, level)| {
@@ -335,6 +386,7 @@ struct Manifest {
     manifest_dir: String,
     persistent: bool,
     domain: Option<String>,
+    history_retention_secs: u64,
 }
 
 fn get_manifest() -> Manifest {
@@ -360,12 +412,18 @@ fn get_manifest() -> Manifest {
         .flatten()
         .flatten();
 
+    let history_retention_secs = metadata
+        .and_then(|cfgs| cfgs.get("history_retention_secs"))
+        .and_then(|v| v.as_integer())
+        .unwrap_or(0) as u64;
+
     Manifest {
         name,
         version,
         manifest_dir,
         persistent,
         domain,
+        history_retention_secs,
     }
 }
 
@@ -4,8 +4,10 @@
 // for macro-generated code inside prest itself
 pub(crate) use crate as prest;
 
-pub use prest_init_macro::init;
+pub use prest_init_macro::{init, migration};
 
+#[doc(hidden)]
+pub use inventory as _inventory;
 #[doc(hidden)]
 pub use serde;
 pub use serde_derive::{Deserialize, Serialize};
@@ -33,13 +35,14 @@ pub use axum::{
 //     HxRetarget, HxTarget, HxTrigger, HxTriggerName, SwapOption,
 // };
 
-pub use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+pub use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 pub use futures::{
     future::{join_all, FutureExt},
     stream::{self, Stream, StreamExt, TryStreamExt},
 };
 pub use serde_json::{
-    from_slice as from_json_slice, from_str as from_json_str, json, to_string as to_json_string,
+    from_slice as from_json_slice, from_str as from_json_str, from_value as from_json_value, json,
+    to_string as to_json_string, Value as JsonValue,
 };
 pub use std::sync::LazyLock as Lazy;
 pub use std::{env::var as env_var, sync::Arc};
@@ -59,6 +62,15 @@ pub use result::{ok, AnyError, Error, Result, Somehow, OK};
 mod vals;
 pub use vals::Vals;
 
+mod validate;
+#[doc(hidden)]
+pub use validate::{
+    _ValidateLength, _validate_email, _validate_length, _validate_range, _validate_regex,
+};
+#[doc(hidden)]
+pub use regex::Regex as _Regex;
+pub use validate::{Validate, ValidationErrors};
+
 #[cfg(feature = "db")]
 mod db;
 #[cfg(feature = "db")]
@@ -21,6 +21,21 @@ pub enum Error {
     Unauthorized,
     #[error("Not found")]
     NotFound,
+    /// Written value of a unique or primary key column is already stored in another row
+    #[error("{table}.{column} already has this value")]
+    Conflict { table: String, column: String },
+    /// Updated row was changed by another write since its `#[version_column]` value was read
+    #[error("{table} row {id} was changed by another write, reload it and try again")]
+    StaleWrite { table: String, id: String },
+    /// Values failed the `#[validate(...)]` checks of their fields
+    #[error("invalid values: {0}")]
+    Validation(#[from] ValidationErrors),
+    /// Written row has no value for a non-optional column
+    #[error("missing value of a required column in {table}")]
+    NotNullViolation { table: String },
+    /// Written row references a missing row or the removed row is still referenced
+    #[error("reference of {table}.{column} is violated")]
+    ForeignKeyViolation { table: String, column: String },
     #[error(transparent)]
     Env(#[from] std::env::VarError),
     #[error(transparent)]
@@ -60,7 +75,7 @@ pub enum Error {
     #[cfg(all(host, feature = "db"))]
     #[error(transparent)]
     SledConflictableTransactionError(#[from] ::sled::transaction::ConflictableTransactionError),
-    #[cfg(all(host, feature = "db"))]
+    #[cfg(feature = "db")]
     #[error(transparent)]
     Bincode(#[from] bincode::Error),
     #[error(transparent)]
@@ -93,6 +108,14 @@ impl IntoResponse for Error {
                 StatusCode::UNAUTHORIZED.into_response()
             }
             Error::NotFound => StatusCode::NOT_FOUND.into_response(),
+            Error::Conflict { .. } => StatusCode::CONFLICT.into_response(),
+            Error::StaleWrite { .. } => (StatusCode::CONFLICT, self.to_string()).into_response(),
+            Error::Validation(errors) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, Json(errors)).into_response()
+            }
+            Error::NotNullViolation { .. } | Error::ForeignKeyViolation { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY.into_response()
+            }
             _ => {
                 error!("{self}");
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -0,0 +1,129 @@
+use crate::*;
+
+use js_sys::{Array, Promise, Uint8Array};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    IdbDatabase, IdbKeyRange, IdbRequest, IdbTransaction, IdbTransactionMode, WorkerGlobalScope,
+};
+
+const DATABASE_NAME: &str = "prest";
+const TABLES_STORE: &str = "tables";
+
+/// IndexedDB object store which keeps the serialized schemas and rows of the service worker's [`DB`]
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct IdbTables;
+
+#[async_trait(?Send)]
+impl TableStore for IdbTables {
+    async fn read_all(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let db = open().await?;
+        let tx = db.transaction_with_str(TABLES_STORE).map_err(js_error)?;
+        let store = tx.object_store(TABLES_STORE).map_err(js_error)?;
+        let keys = store.get_all_keys().map_err(js_error)?;
+        let values = store.get_all().map_err(js_error)?;
+        let (keys, values) = (request(&keys).await, request(&values).await);
+        db.close();
+
+        let keys = keys?.unchecked_into::<Array>();
+        let values = values?.unchecked_into::<Array>();
+        let entries = keys
+            .iter()
+            .zip(values.iter())
+            .filter_map(|(key, value)| Some((key.as_string()?, Uint8Array::new(&value).to_vec())))
+            .collect();
+        Ok(entries)
+    }
+
+    async fn write(&self, cleared: Vec<String>, entries: Vec<(String, Option<Vec<u8>>)>) -> Result {
+        let db = open().await?;
+        let tx = db
+            .transaction_with_str_and_mode(TABLES_STORE, IdbTransactionMode::Readwrite)
+            .map_err(js_error)?;
+        let store = tx.object_store(TABLES_STORE).map_err(js_error)?;
+        // requests of the transaction are applied in order
+        for prefix in cleared {
+            let upper = format!("{prefix}\u{ffff}");
+            let range = IdbKeyRange::bound(&JsValue::from_str(&prefix), &JsValue::from_str(&upper))
+                .map_err(js_error)?;
+            store.delete(&range).map_err(js_error)?;
+        }
+        for (key, value) in entries {
+            let key = JsValue::from_str(&key);
+            match value {
+                Some(value) => store.put_with_key(&Uint8Array::from(value.as_slice()), &key),
+                None => store.delete(&key),
+            }
+            .map_err(js_error)?;
+        }
+        let result = committed(&tx).await;
+        db.close();
+        result
+    }
+}
+
+/// Loads the stored tables and creates the registered ones before the first request is handled
+pub(crate) async fn load_db() -> Result {
+    static MIGRATED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+    let DbStorage::Persistent(storage) = DB.storage() else {
+        return OK;
+    };
+    storage.load().await?;
+    if !MIGRATED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+        DB.migrate().await?;
+    }
+    OK
+}
+
+/// Writes the rows modified while handling the request into the IndexedDB
+pub(crate) async fn persist_db() -> Result {
+    match DB.storage() {
+        DbStorage::Persistent(storage) => storage.persist().await,
+        DbStorage::Memory(_) => OK,
+    }
+}
+
+async fn open() -> Result<IdbDatabase> {
+    let factory = js_sys::global()
+        .unchecked_into::<WorkerGlobalScope>()
+        .indexed_db()
+        .map_err(js_error)?
+        .ok_or_else(|| e!("IndexedDB is not available"))?;
+    let open = factory.open_with_u32(DATABASE_NAME, 1).map_err(js_error)?;
+    let opening = open.clone();
+    let on_upgrade = Closure::once_into_js(move || {
+        if let Ok(db) = opening.result() {
+            let _ = db
+                .unchecked_into::<IdbDatabase>()
+                .create_object_store(TABLES_STORE);
+        }
+    });
+    open.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+    Ok(request(&open).await?.unchecked_into())
+}
+
+/// Resolves with the result of the request once it succeeds
+async fn request(request: &IdbRequest) -> Result<JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    JsFuture::from(promise).await.map_err(js_error)?;
+    request.result().map_err(js_error)
+}
+
+/// Resolves once the transaction is committed
+async fn committed(tx: &IdbTransaction) -> Result {
+    let promise = Promise::new(&mut |resolve, reject| {
+        tx.set_oncomplete(Some(&resolve));
+        tx.set_onerror(Some(&reject));
+        tx.set_onabort(Some(&reject));
+    });
+    JsFuture::from(promise).await.map_err(js_error)?;
+    OK
+}
+
+fn js_error(err: JsValue) -> Error {
+    e!("IndexedDB error: {err:?}")
+}
@@ -1,16 +1,23 @@
 use crate::*;
 
+#[cfg(feature = "db")]
+mod idb;
 mod state;
+#[cfg(feature = "db")]
+mod sync;
 
 pub use console_error_panic_hook::set_once as set_panic_hook;
 use js_sys::{Array, Reflect, Set, Uint8Array};
 pub use wasm_bindgen::prelude::wasm_bindgen;
 use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
 pub use web_sys::{console, FetchEvent, ServiceWorkerGlobalScope};
 
-// TODO: figure out how to use gluesql::idb_storage::IdbStorage or another browser-based as PersistentStorage for SW
 #[cfg(feature = "db")]
-pub(crate) type PersistentStorage = gluesql::gluesql_shared_memory_storage::SharedMemoryStorage;
+pub(crate) use idb::IdbTables;
+/// Memory storage which is loaded from and written into the IndexedDB around the requests
+#[cfg(feature = "db")]
+pub(crate) type PersistentStorage = WriteThroughStorage<IdbTables>;
 
 pub use futures::executor::block_on as await_blocking;
 
@@ -18,13 +25,20 @@ pub use futures::executor::block_on as await_blocking;
 pub trait ServiceWorkerUtils {
     fn handle_fetch_events(self);
     fn init_tracing(self) -> Self;
+    /// Creates the table in the service worker's DB which is kept in the IndexedDB and synced with the host if it's `#[table(sync)]`
+    #[cfg(feature = "db")]
+    fn register_table<T: Table + 'static>(self) -> Self;
 }
 
 static mut ROUTER: Option<Router> = None;
 
 impl ServiceWorkerUtils for Router {
     fn handle_fetch_events(mut self) {
-        self = self.init_tracing().route("/sw/health", get(StatusCode::OK));
+        self = self
+            .init_tracing()
+            .route("/sw/health", get(StatusCode::OK))
+            // matched routes answer the probes without running their handlers
+            .route_layer(axum::middleware::from_fn(answer_probe));
         unsafe { ROUTER = Some(self) }
     }
     fn init_tracing(self) -> Self {
@@ -57,9 +71,17 @@ impl ServiceWorkerUtils for Router {
         }
         self
     }
+    #[cfg(feature = "db")]
+    fn register_table<T: Table + 'static>(self) -> Self {
+        DB._register_table(T::schema());
+        if T::SYNC {
+            sync::register::<T>();
+        }
+        self
+    }
 }
 
-/// Process request to the same host using the router and respond if status < 400
+/// Process requests to the routes of the router and respond if status < 400, otherwise fetch them from the network
 #[wasm_bindgen]
 pub async unsafe fn handle_fetch(sw: ServiceWorkerGlobalScope, event: FetchEvent) {
     let Some(ref mut router) = ROUTER else {
@@ -70,13 +92,70 @@ pub async unsafe fn handle_fetch(sw: ServiceWorkerGlobalScope, event: FetchEvent
 
     let host = &sw.location().host();
 
-    let request = fetch_into_axum_request(&event).await;
     // process only requests to our host
-    if request.uri().host() != Some(host) {
+    let Ok(uri) = event.request().url().parse::<Uri>() else {
+        return;
+    };
+    if uri.host() != Some(host) {
         return;
     }
 
-    // pass the request to the router
+    // others like assets or host-only routes go to the network without the worker
+    if !serves(router, &event.request().method(), uri) {
+        return;
+    }
+
+    // responding is decided after the DB is loaded from the IndexedDB so the network request is kept as a fallback
+    let Ok(fallback) = event.request().clone() else {
+        return;
+    };
+    let fetch_event = event.clone();
+    let promise = wasm_bindgen_futures::future_to_promise(async move {
+        match process(router, &fetch_event).await {
+            Some(response) => axum_response_to_websys(response).await,
+            None => JsFuture::from(sw.fetch_with_request(&fallback)).await,
+        }
+    });
+    event.respond_with(&promise).unwrap();
+}
+
+/// Header of the requests which only check whether the router has a route for them
+const PROBE_HEADER: &str = "x-prest-sw-probe";
+
+async fn answer_probe(request: Request, next: Next) -> Response {
+    match request.headers().contains_key(PROBE_HEADER) {
+        true => StatusCode::NO_CONTENT.into_response(),
+        false => next.run(request).await,
+    }
+}
+
+/// Whether the router has a route for the method and the path, the probe is answered right away by [`answer_probe`]
+fn serves(router: &mut Router, method: &str, uri: Uri) -> bool {
+    use tower::Service;
+    let probe = http::Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(PROBE_HEADER, "1")
+        .body(Body::empty());
+    let Ok(probe) = probe else {
+        return false;
+    };
+    match router.call(probe).now_or_never() {
+        Some(Ok(response)) => response.status() == StatusCode::NO_CONTENT,
+        _ => false,
+    }
+}
+
+/// Passes the request to the router, `None` if it should go to the network
+async fn process(router: &mut Router, event: &FetchEvent) -> Option<Response> {
+    #[cfg(feature = "db")]
+    if let Err(e) = idb::load_db().await {
+        error!(target: "db", "loading DB from the IndexedDB failed: {e}");
+        return None;
+    }
+
+    let request = fetch_into_axum_request(event).await;
+
     use tower::Service;
     let response = match router.call(request).await {
         Ok(res) => res,
@@ -87,17 +166,22 @@ pub async unsafe fn handle_fetch(sw: ServiceWorkerGlobalScope, event: FetchEvent
                 &event.request(),
                 &format!(" : {:?}", e).into(),
             );
-            return;
+            return None;
         }
     };
 
-    if response.status().as_u16() >= 400 {
-        return;
+    #[cfg(feature = "db")]
+    if let Err(e) = idb::persist_db().await {
+        error!(target: "db", "writing DB into the IndexedDB failed: {e}");
     }
 
-    let response = axum_response_to_websys(response);
-    let promise = wasm_bindgen_futures::future_to_promise(response);
-    event.respond_with(&promise).unwrap();
+    // keeps the worker alive until the sync is done
+    #[cfg(feature = "db")]
+    if let Some(syncing) = sync::schedule(event.request().method() != "GET") {
+        let _ = event.wait_until(&syncing);
+    }
+
+    (response.status().as_u16() < 400).then_some(response)
 }
 
 pub async fn fetch_into_axum_request(fetch_event: &web_sys::FetchEvent) -> http::Request<Body> {
@@ -0,0 +1,257 @@
+use crate::*;
+
+use futures::future::LocalBoxFuture;
+use js_sys::{Promise, Uint8Array};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{RequestInit, Response as WebResponse, WorkerGlobalScope};
+
+/// Host changes are pulled at most once per this interval unless there are local writes to push
+const PULL_INTERVAL_MS: u64 = 30_000;
+
+type SyncFn = fn() -> LocalBoxFuture<'static, Result>;
+
+static SYNCED_TABLES: std::sync::Mutex<Vec<SyncFn>> = std::sync::Mutex::new(vec![]);
+static SYNCING: AtomicBool = AtomicBool::new(false);
+static PENDING: AtomicBool = AtomicBool::new(false);
+static LAST_SYNC: AtomicU64 = AtomicU64::new(0);
+
+/// State of the table as it was last synced with the host
+#[derive(Default, Serialize, Deserialize)]
+struct SyncState {
+    /// transaction of the host to pull the changes after
+    txid: Option<u64>,
+    /// rows which local changes are detected against
+    shadow: Vec<Vec<sql::Value>>,
+}
+
+pub(crate) fn register<T: Table + 'static>() {
+    SYNCED_TABLES
+        .lock()
+        .expect("synced tables")
+        .push(|| sync_table::<T>().boxed_local());
+}
+
+/// Starts syncing the tables unless it's running already, resolves once it's done
+pub(crate) fn schedule(local_writes: bool) -> Option<Promise> {
+    if SYNCED_TABLES.lock().expect("synced tables").is_empty() {
+        return None;
+    }
+    let since_last = (js_sys::Date::now() as u64).saturating_sub(LAST_SYNC.load(Ordering::SeqCst));
+    if !local_writes && since_last < PULL_INTERVAL_MS {
+        return None;
+    }
+    PENDING.store(true, Ordering::SeqCst);
+    // running sync repeats once it's done
+    if SYNCING.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    Some(wasm_bindgen_futures::future_to_promise(async {
+        while PENDING.swap(false, Ordering::SeqCst) {
+            LAST_SYNC.store(js_sys::Date::now() as u64, Ordering::SeqCst);
+            let tables = SYNCED_TABLES.lock().expect("synced tables").clone();
+            for sync in tables {
+                // most likely offline, local writes are pushed with the next sync
+                if let Err(e) = sync().await {
+                    debug!(target: "sync", "sync stopped: {e}");
+                    break;
+                }
+            }
+            if let Err(e) = super::idb::persist_db().await {
+                error!(target: "db", "writing synced DB into the IndexedDB failed: {e}");
+            }
+        }
+        SYNCING.store(false, Ordering::SeqCst);
+        Ok(JsValue::UNDEFINED)
+    }))
+}
+
+/// Pushes local changes since the last sync and applies the host's ones except for the rows changed meanwhile
+async fn sync_table<T: Table>() -> Result {
+    let DbStorage::Persistent(storage) = DB.storage() else {
+        return OK;
+    };
+    let state_key = format!("sync/{}", T::TABLE_NAME);
+    let mut state: SyncState = match storage.meta(&state_key) {
+        Some(state) => bincode::deserialize(&state)?,
+        None => SyncState::default(),
+    };
+
+    let mut local = keyed::<T>(T::select().rows().await?)?;
+    let mut shadow = keyed::<T>(std::mem::take(&mut state.shadow))?;
+    let mut writes = vec![];
+    let mut written = vec![];
+    for (key, row) in &local {
+        match shadow.get(key) {
+            Some(base) if base == row => {}
+            base => {
+                writes.push(SyncWrite {
+                    base: base.cloned(),
+                    row: Some(row.clone()),
+                });
+                written.push(key.clone());
+            }
+        }
+    }
+    for (key, base) in &shadow {
+        if !local.contains_key(key) {
+            writes.push(SyncWrite {
+                base: Some(base.clone()),
+                row: None,
+            });
+            written.push(key.clone());
+        }
+    }
+    if !writes.is_empty() {
+        let path = format!("/sync/{}", T::TABLE_NAME);
+        let rejected: Vec<SyncRejected> =
+            bincode::deserialize(&request(&path, Some(bincode::serialize(&writes)?)).await?)?;
+        shadow = local.clone();
+        // rejected writes are reverted to the stored rows instead of being pushed again
+        let mut reverted = HashMap::new();
+        for SyncRejected { write, stored } in rejected {
+            let Some(key) = written.get(write) else {
+                continue;
+            };
+            match &stored {
+                Some(row) => shadow.insert(key.clone(), row.clone()),
+                None => shadow.remove(key),
+            };
+            reverted.insert(key.clone(), stored);
+        }
+        if !reverted.is_empty() {
+            revert::<T>(&mut local, reverted).await?;
+        }
+        state.shadow = shadow.values().cloned().collect();
+        storage.set_meta(&state_key, bincode::serialize(&state)?);
+    }
+
+    let path = match state.txid {
+        Some(txid) => format!("/sync/{}?since={txid}", T::TABLE_NAME),
+        None => format!("/sync/{}", T::TABLE_NAME),
+    };
+    let pull: SyncPull = bincode::deserialize(&request(&path, None).await?)?;
+
+    DB.in_transaction(async {
+        let current = keyed::<T>(T::select().rows().await?)?;
+        // rows changed during the requests are kept and pushed with the next sync
+        let untouched = |key: &String| current.get(key) == local.get(key);
+        let mut shadow = match pull.full {
+            true => HashMap::new(),
+            false => shadow,
+        };
+        let mut removed = vec![];
+        for row in pull.removed {
+            let key = key_of::<T>(&row)?;
+            shadow.remove(&key);
+            if untouched(&key) && current.contains_key(&key) {
+                removed.push(key);
+            }
+        }
+        let mut upserts = vec![];
+        for row in pull.rows {
+            let key = key_of::<T>(&row)?;
+            if untouched(&key) {
+                upserts.push(row.clone());
+            }
+            shadow.insert(key, row);
+        }
+        if pull.full {
+            let missing = current
+                .keys()
+                .filter(|key| !shadow.contains_key(*key) && untouched(key));
+            removed.extend(missing.cloned());
+        }
+
+        for key in &removed {
+            replace::<T>(&current[key], None).await?;
+        }
+        for row in upserts {
+            replace::<T>(&row, Some(row.clone())).await?;
+        }
+
+        state.txid = Some(pull.txid);
+        state.shadow = shadow.into_values().collect();
+        storage.set_meta(&state_key, bincode::serialize(&state)?);
+        OK
+    })
+    .await
+}
+
+/// Replaces the local rows of the rejected writes with the stored ones unless they were changed during the push
+async fn revert<T: Table>(
+    local: &mut HashMap<String, Vec<sql::Value>>,
+    reverted: HashMap<String, Option<Vec<sql::Value>>>,
+) -> Result {
+    DB.in_transaction(async {
+        let current = keyed::<T>(T::select().rows().await?)?;
+        for (key, stored) in reverted {
+            if current.get(&key) != local.get(&key) {
+                continue;
+            }
+            if let Some(row) = current.get(&key).or(stored.as_ref()) {
+                replace::<T>(row, stored.clone()).await?;
+            }
+            match stored {
+                Some(row) => local.insert(key, row),
+                None => local.remove(&key),
+            };
+        }
+        OK
+    })
+    .await
+}
+
+/// Removes the local row with the key of the `keyed` one and inserts the `row` if there is one
+async fn replace<T: Table>(keyed: &[sql::Value], row: Option<Vec<sql::Value>>) -> Result {
+    let pkey = T::from_row(keyed.to_vec())?.get_pkey();
+    T::delete().filter(T::pkey_filter(&pkey)).exec().await?;
+    if let Some(row) = row {
+        T::insert()
+            .values(vec![T::from_row(row)?.into_row()?])
+            .exec()
+            .await?;
+    }
+    OK
+}
+
+/// Rows by the debug representations of their primary keys
+fn keyed<T: Table>(rows: Vec<Vec<sql::Value>>) -> Result<HashMap<String, Vec<sql::Value>>> {
+    rows.into_iter()
+        .map(|row| Ok((key_of::<T>(&row)?, row)))
+        .collect()
+}
+
+fn key_of<T: Table>(row: &[sql::Value]) -> Result<String> {
+    Ok(format!("{:?}", T::from_row(row.to_vec())?.get_pkey()))
+}
+
+/// Sends the request to the host bypassing the service worker
+async fn request(path: &str, body: Option<Vec<u8>>) -> Result<Vec<u8>> {
+    let init = RequestInit::new();
+    if let Some(body) = body {
+        init.set_method("POST");
+        init.set_body(&Uint8Array::from(body.as_slice()));
+    }
+    let scope = js_sys::global().unchecked_into::<WorkerGlobalScope>();
+    let response = JsFuture::from(scope.fetch_with_str_and_init(path, &init))
+        .await
+        .map_err(fetch_error)?
+        .unchecked_into::<WebResponse>();
+    if !response.ok() {
+        let status = response.status();
+        return Err(e!("sync request to {path} failed with status {status}"));
+    }
+    let body = JsFuture::from(response.array_buffer().map_err(fetch_error)?)
+        .await
+        .map_err(fetch_error)?;
+    Ok(Uint8Array::new(&body).to_vec())
+}
+
+fn fetch_error(err: JsValue) -> Error {
+    e!("sync request failed: {err:?}")
+}
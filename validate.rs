@@ -0,0 +1,214 @@
+use crate::*;
+
+use std::collections::BTreeMap;
+
+/// Checks of the values, implemented by `#[derive(Validate)]` and `Table` derives from the `#[validate(...)]`
+/// field attributes. Run by the [`Vals`] extractor and `Table` saves
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// Messages of the failed checks by the names of the fields, responds with 422 and a json object of them
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationErrors(BTreeMap<String, Vec<String>>);
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, message: String) {
+        self.0.entry(field.to_owned()).or_default().push(message);
+    }
+
+    /// Messages of the field's failed checks
+    pub fn field(&self, name: &str) -> &[String] {
+        self.0.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[doc(hidden)]
+    pub fn _into_result(self) -> Result<(), Self> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields = self
+            .0
+            .iter()
+            .map(|(field, messages)| format!("{field} {}", messages.join(", ")));
+        write!(f, "{}", fields.collect::<Vec<_>>().join("; "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Number of characters or items checked by `length(...)`
+#[doc(hidden)]
+pub trait _ValidateLength {
+    fn _length(&self) -> usize;
+}
+
+impl _ValidateLength for str {
+    fn _length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl _ValidateLength for String {
+    fn _length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl<T> _ValidateLength for Vec<T> {
+    fn _length(&self) -> usize {
+        self.len()
+    }
+}
+
+#[doc(hidden)]
+pub fn _validate_length(
+    length: usize,
+    min: Option<usize>,
+    max: Option<usize>,
+) -> Result<(), String> {
+    _validate_range(&length, min, max).map_err(|message| format!("length {message}"))
+}
+
+#[doc(hidden)]
+pub fn _validate_range<T: PartialOrd + std::fmt::Display>(
+    value: &T,
+    min: Option<T>,
+    max: Option<T>,
+) -> Result<(), String> {
+    let below = min.as_ref().is_some_and(|min| value < min);
+    let above = max.as_ref().is_some_and(|max| value > max);
+    if !below && !above {
+        return Ok(());
+    }
+    Err(match (min, max) {
+        (Some(min), Some(max)) => format!("should be between {min} and {max}"),
+        (Some(min), None) => format!("should be at least {min}"),
+        (None, Some(max)) => format!("should be at most {max}"),
+        (None, None) => unreachable!("checked bounds"),
+    })
+}
+
+#[doc(hidden)]
+pub fn _validate_regex<S: AsRef<str> + ?Sized>(
+    value: &S,
+    regex: &regex::Regex,
+) -> Result<(), String> {
+    match regex.is_match(value.as_ref()) {
+        true => Ok(()),
+        false => Err(format!("should match {}", regex.as_str())),
+    }
+}
+
+#[doc(hidden)]
+pub fn _validate_email<S: AsRef<str> + ?Sized>(value: &S) -> Result<(), String> {
+    let value = value.as_ref();
+    let valid = match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !value.contains(char::is_whitespace)
+        }
+        None => false,
+    };
+    match valid {
+        true => Ok(()),
+        false => Err("should be a valid email".to_owned()),
+    }
+}
+
+#[cfg(all(test, host, feature = "db"))]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Validate, Deserialize)]
+    struct Signup {
+        #[validate(length(min = 2, max = 8))]
+        name: String,
+        #[validate(email)]
+        email: String,
+        #[validate(range(min = 18, max = 130))]
+        age: Option<u8>,
+        #[validate(regex = "^[a-z]+$", custom = not_admin)]
+        handle: String,
+    }
+
+    fn not_admin(handle: &String) -> Result<(), String> {
+        match handle == "admin" {
+            true => Err("is reserved".to_owned()),
+            false => Ok(()),
+        }
+    }
+
+    fn signup(name: &str, email: &str, age: Option<u8>, handle: &str) -> Signup {
+        Signup {
+            name: name.to_owned(),
+            email: email.to_owned(),
+            age,
+            handle: handle.to_owned(),
+        }
+    }
+
+    fn put(body: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::PUT)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_owned()))
+            .expect("request")
+    }
+
+    #[test]
+    fn derived_checks_collect_messages_by_field() {
+        assert!(signup("ann", "ann@example.com", None, "ann")
+            .validate()
+            .is_ok());
+        assert!(signup("ann", "ann@example.com", Some(30), "ann")
+            .validate()
+            .is_ok());
+
+        let errors = signup("a", "ann@", Some(3), "Admin")
+            .validate()
+            .expect_err("invalid signup");
+        assert_eq!(errors.field("name"), ["length should be between 2 and 8"]);
+        assert_eq!(errors.field("email"), ["should be a valid email"]);
+        assert_eq!(errors.field("age"), ["should be between 18 and 130"]);
+        assert_eq!(errors.field("handle"), ["should match ^[a-z]+$"]);
+
+        let errors = signup("ann", "ann@example.com", None, "admin").validate();
+        assert_eq!(
+            errors.expect_err("reserved").field("handle"),
+            ["is reserved"]
+        );
+    }
+
+    #[test]
+    fn vals_reject_invalid_values() {
+        test_db(&[], async {
+            let body = r#"{"name":"a","email":"ann@example.com","handle":"ann"}"#;
+            let Err(Error::Validation(errors)) = Vals::<Signup>::from_request(put(body), &()).await
+            else {
+                panic!("expected invalid name")
+            };
+            assert_eq!(errors.field("name").len(), 1);
+            assert!(errors.field("email").is_empty());
+
+            let body = r#"{"name":"ann","email":"ann@example.com","handle":"ann"}"#;
+            let Vals(value) = Vals::<Signup>::from_request(put(body), &()).await?;
+            assert_eq!(value.handle, "ann");
+            OK
+        })
+    }
+}
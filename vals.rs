@@ -1,28 +1,31 @@
 use crate::*;
 
-/// Utility that deserializes from either [`Query`] (GET) or [`Json`] (others) based on request method
+/// Utility that deserializes from either [`Query`] (GET) or [`Json`] (others) based on request method and rejects
+/// values which fail their [`Validate`] checks with 422 and a json object of messages by field
 pub struct Vals<T>(pub T);
 #[async_trait]
 impl<T, S> FromRequest<S> for Vals<T>
 where
-    T: serde::de::DeserializeOwned + Send,
+    T: serde::de::DeserializeOwned + Validate + Send,
     S: Send + Sync,
 {
     type Rejection = Error;
 
     async fn from_request(req: Request<Body>, state: &S) -> Result<Self> {
-        if req.method() == Method::GET || req.method() == Method::HEAD {
+        let params = if req.method() == Method::GET || req.method() == Method::HEAD {
             let (mut parts, _) = req.into_parts();
             match axum::extract::Query::<T>::from_request_parts(&mut parts, state).await {
-                Ok(axum::extract::Query(params)) => Ok(Vals(params)),
-                Err(e) => Err(e.into()),
+                Ok(axum::extract::Query(params)) => params,
+                Err(e) => return Err(e.into()),
             }
         } else {
             match Json::<T>::from_request(req, state).await {
-                Ok(Json(params)) => Ok(Vals(params)),
-                Err(e) => Err(e.into()),
+                Ok(Json(params)) => params,
+                Err(e) => return Err(e.into()),
             }
-        }
+        };
+        params.validate()?;
+        Ok(Vals(params))
     }
 }
 